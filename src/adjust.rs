@@ -0,0 +1,293 @@
+// Color adjustments: grayscale conversion and per-channel tone changes.
+
+use alloc::vec::Vec;
+
+use crate::{AlphaMode, Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+// Relative contribution of each channel to luma; should sum to one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LumaWeights {
+  pub r: f32,
+  pub g: f32,
+  pub b: f32
+}
+
+impl LumaWeights {
+  pub const REC709: LumaWeights = LumaWeights { r: 0.2126, g: 0.7152, b: 0.0722 };
+  pub const REC601: LumaWeights = LumaWeights { r: 0.299, g: 0.587, b: 0.114 };
+
+  pub fn luma(&self, color: Rgba) -> u8 {
+    let value = color.r as f32 * self.r + color.g as f32 * self.g + color.b as f32 * self.b;
+    (value + 0.5).clamp(0.0, 255.0) as u8
+  }
+}
+
+impl Default for LumaWeights {
+  fn default() -> LumaWeights {
+    LumaWeights::REC709
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrayscaleOutput {
+  // 8-bpp with a 256-entry gray ramp, where the index is the luma. Alpha is dropped.
+  Indexed,
+  // 24-bpp, or 32-bpp keeping alpha when the source has it.
+  Truecolor
+}
+
+impl Bitmap {
+  // Premultiplied images are divided out first, so the copy always has
+  // straight alpha.
+  pub fn to_grayscale(&self, weights: LumaWeights, output: GrayscaleOutput) -> Bitmap {
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    let lumas = self.pixels.iter().map(|pixel| {
+      let color = self.straight_color(*pixel);
+      (weights.luma(color), color.a)
+    });
+
+    match output {
+      GrayscaleOutput::Indexed => gray_ramp(self.width(), pix_height, lumas.map(|(luma, _)| luma)),
+
+      GrayscaleOutput::Truecolor if self.header.bpp == 32 => {
+        let pixels = lumas.map(|(luma, alpha)| Pixel::ABGR(luma, luma, luma, alpha)).collect();
+        Bitmap::assemble(self.width(), pix_height, 32, Vec::new(), pixels)
+      }
+
+      GrayscaleOutput::Truecolor => {
+        let pixels = lumas.map(|(luma, _)| Pixel::BGR(luma, luma, luma)).collect();
+        Bitmap::assemble(self.width(), pix_height, 24, Vec::new(), pixels)
+      }
+    }
+  }
+
+  // Whether every color has equal red, green and blue: for indexed images,
+  // every palette entry, so the index picks a gray level. Alpha is ignored.
+  pub fn is_grayscale(&self) -> bool {
+    let gray = |color: Rgba| color.r == color.g && color.g == color.b;
+    let palette_gray = self.header.bpp > 8 || self.palette.iter().all(|color| gray(*color));
+    palette_gray && self.pixels.iter().all(|pixel| matches!(pixel, Pixel::PaletteColor(_)) || gray(self.color(*pixel)))
+  }
+
+  // One byte per pixel, top-down: the level itself for grayscale images,
+  // looked up per palette index rather than per pixel, and Rec. 709 luma
+  // for anything else. Alpha is dropped.
+  pub fn to_gray8(&self) -> Vec<u8> {
+    if !self.is_grayscale() {
+      return self.pixels.iter().map(|pixel| LumaWeights::REC709.luma(self.straight_color(*pixel))).collect()
+    }
+
+    let mut levels = [0; 256];
+    for (level, color) in levels.iter_mut().zip(&self.palette) {
+      *level = color.r;
+    }
+    self.pixels.iter()
+      .map(|pixel| match pixel {
+        Pixel::PaletteColor(index) => levels[*index as usize],
+        other => self.straight_color(*other).r
+      })
+      .collect()
+  }
+
+  // One 8-bpp gray ramp image per channel: red, green, blue, then alpha for
+  // 32-bpp images.
+  pub fn split_channels(&self) -> Vec<Bitmap> {
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    let colors = self.to_rgba();
+    let channels = if self.header.bpp == 32 { 4 } else { 3 };
+
+    (0 .. channels)
+      .map(|channel| {
+        let levels = colors.iter().map(|color| [color.r, color.g, color.b, color.a][channel]);
+        gray_ramp(self.width(), pix_height, levels)
+      })
+      .collect()
+  }
+
+  // Inverse of `split_channels`: three same-sized images give a 24-bpp
+  // result, four a 32-bpp one with the last as alpha. Each channel is read
+  // from the red component, which for grayscale images is the level.
+  pub fn merge_channels(channels: &[Bitmap]) -> BitmapResult<Bitmap> {
+    let first = channels.first().ok_or(BitmapError::InvalidBitmapData)?;
+    let same_size = channels.iter().all(|channel| channel.width() == first.width() && channel.height() == first.height());
+    if !(3 ..= 4).contains(&channels.len()) || !same_size {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let level = |channel: usize, index: usize| {
+      let bitmap = &channels[channel];
+      bitmap.color(bitmap.pixels[index]).r
+    };
+    let pixels = (0 .. first.pixels.len())
+      .map(|index| match channels.len() {
+        4 => Pixel::ABGR(level(2, index), level(1, index), level(0, index), level(3, index)),
+        _ => Pixel::BGR(level(2, index), level(1, index), level(0, index))
+      })
+      .collect();
+
+    let pix_height = first.header.pix_height.signum() * first.height() as i32;
+    let bpp = if channels.len() == 4 { 32 } else { 24 };
+    Ok(Bitmap::assemble(first.width(), pix_height, bpp, Vec::new(), pixels))
+  }
+
+  // Inverts the color channels, leaving alpha alone. Indexed images have
+  // their palette inverted so the indices keep meaning the same entries.
+  pub fn invert(&mut self) {
+    self.map_colors(|color| Rgba::new(255 - color.r, 255 - color.g, 255 - color.b, color.a));
+  }
+
+  // Scales each channel's distance from mid-gray by `contrast` (1.0 leaves
+  // it unchanged), then adds `brightness`, clamping to 0 ..= 255. Indexed
+  // images are adjusted through their palette.
+  pub fn adjust(&mut self, brightness: i16, contrast: f32) {
+    let channel = |value: u8| {
+      let value = (value as f32 - 128.0) * contrast + 128.0 + brightness as f32;
+      (value + 0.5).clamp(0.0, 255.0) as u8
+    };
+    self.map_colors(|color| Rgba::new(channel(color.r), channel(color.g), channel(color.b), color.a));
+  }
+
+  // Multiplies every channel by the matching one of `color`, alpha
+  // included where the image has it. Indexed images are tinted through
+  // their palette.
+  pub fn tint(&mut self, color: Rgba) {
+    let multiply = |value: u8, by: u8| ((value as u16 * by as u16 + 127) / 255) as u8;
+    self.map_colors(|pixel| {
+      Rgba::new(multiply(pixel.r, color.r), multiply(pixel.g, color.g), multiply(pixel.b, color.b), multiply(pixel.a, color.a))
+    });
+  }
+
+  // Raises each normalized channel to `1 / gamma`, so values above 1.0
+  // brighten the midtones. Alpha is left alone.
+  #[cfg(feature = "std")]
+  pub fn apply_gamma(&mut self, gamma: f32) {
+    let table = gamma_table(|value| value.powf(1.0 / gamma));
+    self.map_colors(|color| Rgba::new(table[color.r as usize], table[color.g as usize], table[color.b as usize], color.a));
+  }
+
+  // Re-encodes calibrated-RGB pixels to sRGB through the header's
+  // per-channel gammas, then declares sRGB in the header so nothing applies
+  // the curve again. Returns false, changing nothing, for images without
+  // them. Primaries aren't mapped, and the header no longer records them.
+  #[cfg(feature = "std")]
+  pub fn apply_header_gamma(&mut self) -> bool {
+    let Some([red, green, blue]) = self.header_gamma() else {
+      return false
+    };
+
+    let curve = |gamma: f32| gamma_table(move |value| crate::color_space::linear_to_srgb(value.powf(gamma)));
+    let (red, green, blue) = (curve(red), curve(green), curve(blue));
+    self.map_colors(|color| Rgba::new(red[color.r as usize], green[color.g as usize], blue[color.b as usize], color.a));
+    self.declare_srgb();
+    true
+  }
+
+  // Applies `f` to every truecolor pixel and to every palette entry. `f`
+  // sees straight alpha; premultiplied pixels are divided out first and
+  // scaled back afterwards.
+  pub(crate) fn map_colors<F: Fn(Rgba) -> Rgba>(&mut self, f: F) {
+    let premultiplied = self.alpha_mode == AlphaMode::Premultiplied;
+    self.unpremultiply_alpha();
+
+    for entry in &mut self.palette {
+      *entry = f(*entry);
+    }
+
+    for pixel in &mut self.pixels {
+      *pixel = match *pixel {
+        Pixel::ABGR(b, g, r, a) => {
+          let color = f(Rgba { r, g, b, a });
+          Pixel::ABGR(color.b, color.g, color.r, color.a)
+        }
+        Pixel::BGR(b, g, r) => {
+          let color = f(Rgba { r, g, b, a: 255 });
+          Pixel::BGR(color.b, color.g, color.r)
+        }
+        index => index
+      };
+    }
+
+    if premultiplied {
+      self.premultiply_alpha();
+    }
+  }
+}
+
+// Lookup table for a curve over normalized channel values.
+#[cfg(feature = "std")]
+fn gamma_table<F: Fn(f32) -> f32>(curve: F) -> [u8; 256] {
+  let mut table = [0; 256];
+  for (value, out) in table.iter_mut().enumerate() {
+    *out = (curve(value as f32 / 255.0) * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+  }
+  table
+}
+
+// 8-bpp image whose index is the gray level, from row-major `levels`.
+pub(crate) fn gray_ramp<I: Iterator<Item = u8>>(width: u32, pix_height: i32, levels: I) -> Bitmap {
+  let palette = (0 ..= 255).map(|level| Rgba::new(level, level, level, 255)).collect();
+  let pixels = levels.map(Pixel::PaletteColor).collect();
+  Bitmap::assemble(width, pix_height, 8, palette, pixels)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+  use alloc::vec;
+  use alloc::vec::Vec;
+
+  use crate::{AlphaMode, Bitmap, GrayscaleOutput, LumaWeights, Pixel, Rect};
+
+  // A 1x1 24-bpp file with a V4 header declaring calibrated RGB at gamma 2.
+  fn calibrated() -> Vec<u8> {
+    let mut file = Vec::new();
+    file.extend_from_slice(b"BM");
+    file.extend_from_slice(&126u32.to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&122u32.to_le_bytes());
+    file.extend_from_slice(&108u32.to_le_bytes());
+    file.extend_from_slice(&1i32.to_le_bytes());
+    file.extend_from_slice(&1i32.to_le_bytes());
+    file.extend_from_slice(&[1, 0, 24, 0]);
+    // Compression through masks, then a zero bV4CSType and endpoints.
+    file.extend_from_slice(&[0; 44]);
+    file.extend_from_slice(&[0; 36]);
+    file.extend_from_slice(&[0, 0, 2, 0].repeat(3));
+    file.extend_from_slice(&[128, 128, 128, 0]);
+    file
+  }
+
+  #[test]
+  fn header_gamma_is_applied_once() {
+    let mut bitmap = Bitmap::from_bytes(&calibrated()).unwrap();
+    assert_eq!(bitmap.header_gamma(), Some([2.0; 3]));
+    let expected = bitmap.to_srgb8();
+
+    assert!(bitmap.apply_header_gamma());
+    assert!(bitmap.is_srgb());
+    assert_eq!(bitmap.header_gamma(), None);
+    assert_eq!(bitmap.to_srgb8(), expected);
+    assert!(!bitmap.apply_header_gamma());
+  }
+
+  #[test]
+  fn premultiplied_images_adjust_straight_colors() {
+    let straight = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(200, 100, 50, 128), Pixel::ABGR(10, 20, 30, 255)]).unwrap();
+    let mut premultiplied = straight.clone();
+    premultiplied.premultiply_alpha();
+
+    let gray = premultiplied.to_grayscale(LumaWeights::REC709, GrayscaleOutput::Truecolor);
+    assert_eq!(gray.alpha_mode, AlphaMode::Straight);
+    assert!(gray.compare(&straight.to_grayscale(LumaWeights::REC709, GrayscaleOutput::Truecolor), 1).matches);
+    assert!(premultiplied.to_gray8().iter().zip(straight.to_gray8()).all(|(a, b)| a.abs_diff(b) <= 1));
+
+    let (mut inverted, mut expected) = (premultiplied.clone(), straight.clone());
+    inverted.invert();
+    expected.invert();
+    assert_eq!(inverted.alpha_mode, AlphaMode::Premultiplied);
+    assert!(inverted.compare(&expected, 2).matches);
+
+    let mut canvas = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(0, 0, 0, 0); 2]).unwrap();
+    canvas.blit_from(&premultiplied, Rect::new(0, 0, 2, 1), (0, 0));
+    assert!(canvas.compare(&straight, 1).matches);
+  }
+}
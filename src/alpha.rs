@@ -0,0 +1,310 @@
+// Alpha channel utilities.
+
+use alloc::vec::Vec;
+
+use crate::adjust::gray_ramp;
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+// Whether the color channels of a bitmap's pixels are scaled by alpha.
+// Files always store straight alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlphaMode {
+  #[default]
+  Straight,
+  Premultiplied
+}
+
+// How `composite` combines source colors with the destination, before the
+// result is laid over it with source-over alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+  #[default]
+  SourceOver,
+  Multiply,
+  Screen,
+  Darken,
+  Lighten
+}
+
+// Where `watermark` places the mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatermarkPosition {
+  TopLeft,
+  TopRight,
+  BottomLeft,
+  #[default]
+  BottomRight,
+  Center,
+  // Repeated edge to edge from the top-left corner.
+  Tiled
+}
+
+impl BlendMode {
+  fn blend(self, source: f32, destination: f32) -> f32 {
+    match self {
+      BlendMode::SourceOver => source,
+      BlendMode::Multiply => source * destination,
+      BlendMode::Screen => source + destination - source * destination,
+      BlendMode::Darken => source.min(destination),
+      BlendMode::Lighten => source.max(destination)
+    }
+  }
+}
+
+impl Bitmap {
+  // Scales the color channels of 32-bpp pixels by their alpha. Does nothing
+  // if the image is already premultiplied.
+  pub fn premultiply_alpha(&mut self) {
+    if self.alpha_mode == AlphaMode::Premultiplied {
+      return
+    }
+
+    let scale = |value: u8, alpha: u8| ((value as u16 * alpha as u16 + 127) / 255) as u8;
+    for pixel in &mut self.pixels {
+      if let Pixel::ABGR(b, g, r, a) = *pixel {
+        *pixel = Pixel::ABGR(scale(b, a), scale(g, a), scale(r, a), a);
+      }
+    }
+    self.alpha_mode = AlphaMode::Premultiplied;
+  }
+
+  // Inverse of `premultiply_alpha`; fully transparent pixels become black.
+  // Does nothing unless the image is premultiplied.
+  pub fn unpremultiply_alpha(&mut self) {
+    if self.alpha_mode == AlphaMode::Straight {
+      return
+    }
+
+    let scale = |value: u8, alpha: u8| match alpha {
+      0 => 0,
+      _ => ((value as u16 * 255 + alpha as u16 / 2) / alpha as u16).min(255) as u8
+    };
+    for pixel in &mut self.pixels {
+      if let Pixel::ABGR(b, g, r, a) = *pixel {
+        *pixel = Pixel::ABGR(scale(b, a), scale(g, a), scale(r, a), a);
+      }
+    }
+    self.alpha_mode = AlphaMode::Straight;
+  }
+
+  // 32-bpp copy where pixels whose color matches `key` (alpha aside) become
+  // transparent black and the rest keep their color, opaque unless they
+  // already had alpha. `key` is resolved through this bitmap's palette.
+  pub fn color_key_to_alpha(&self, key: Pixel) -> Bitmap {
+    let key = self.color(key);
+    let pixels = self.pixels.iter()
+      .map(|pixel| {
+        let color = self.color(*pixel);
+        if (color.r, color.g, color.b) == (key.r, key.g, key.b) {
+          Pixel::ABGR(0, 0, 0, 0)
+        } else {
+          Pixel::ABGR(color.b, color.g, color.r, color.a)
+        }
+      })
+      .collect();
+
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    let mut bitmap = Bitmap::assemble(self.width(), pix_height, 32, Vec::new(), pixels);
+    bitmap.alpha_mode = self.alpha_mode;
+    bitmap
+  }
+
+  // Straight-alpha color of `pixel` as normalized floats.
+  fn straight(&self, pixel: Pixel) -> [f32; 4] {
+    let color = self.color(pixel);
+    let alpha = color.a as f32 / 255.0;
+    let channel = |value: u8| {
+      if self.alpha_mode == AlphaMode::Straight || !matches!(pixel, Pixel::ABGR(..)) {
+        value as f32 / 255.0
+      } else if alpha > 0.0 {
+        (value as f32 / 255.0 / alpha).min(1.0)
+      } else {
+        0.0
+      }
+    };
+    [channel(color.r), channel(color.g), channel(color.b), alpha]
+  }
+
+  // `color`, divided back out of premultiplied alpha if `alpha_mode` says
+  // it's in there: what encoders and exports write, since every format
+  // they target stores straight alpha.
+  pub fn straight_color(&self, pixel: Pixel) -> Rgba {
+    // Only truecolor pixels are scaled; palettes always stay straight.
+    let color = self.color(pixel);
+    if self.alpha_mode == AlphaMode::Straight || !matches!(pixel, Pixel::ABGR(..)) || color.a == 255 {
+      return color
+    }
+
+    let scale = |value: u8| match color.a {
+      0 => 0,
+      alpha => ((value as u16 * 255 + alpha as u16 / 2) / alpha as u16).min(255) as u8
+    };
+    Rgba::new(scale(color.r), scale(color.g), scale(color.b), color.a)
+  }
+
+  // 8-bpp gray ramp image of the alpha plane, where each index is the
+  // pixel's alpha. `None` for images without alpha: depths other than 32
+  // bpp, except indexed images whose palette carries some.
+  pub fn alpha_channel(&self) -> Option<Bitmap> {
+    let palette_alpha = self.header.bpp <= 8 && self.palette.iter().any(|color| color.a != 255);
+    if self.header.bpp != 32 && !palette_alpha {
+      return None
+    }
+
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    Some(gray_ramp(self.width(), pix_height, self.pixels.iter().map(|pixel| self.color(*pixel).a)))
+  }
+
+  // Replaces alpha with the levels of `mask`, a same-sized image read
+  // through its red channel as `merge_channels` does, so the output of
+  // `alpha_channel` round-trips. The image becomes 32-bpp and keeps its
+  // `alpha_mode`.
+  pub fn set_alpha_channel(&mut self, mask: &Bitmap) -> BitmapResult<()> {
+    if (mask.width(), mask.height()) != (self.width(), self.height()) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let mode = self.alpha_mode;
+    self.unpremultiply_alpha();
+    self.pixels = self.pixels.iter()
+      .zip(&mask.pixels)
+      .map(|(pixel, level)| {
+        let color = self.color(*pixel);
+        Pixel::ABGR(color.b, color.g, color.r, mask.color(*level).r)
+      })
+      .collect();
+
+    self.palette.clear();
+    self.header.bpp = 32;
+    self.header.colors = 0;
+    self.header.important_colors = 0;
+    if mode == AlphaMode::Premultiplied {
+      self.premultiply_alpha();
+    }
+    Ok(())
+  }
+
+  // Converts indexed pixels to 24-bpp so arbitrary colors can be stored.
+  pub(crate) fn promote_truecolor(&mut self) {
+    if self.header.bpp > 8 {
+      return
+    }
+
+    for index in 0 .. self.pixels.len() {
+      let color = self.color(self.pixels[index]);
+      self.pixels[index] = Pixel::BGR(color.b, color.g, color.r);
+    }
+    self.palette.clear();
+    self.header.bpp = 24;
+    self.header.colors = 0;
+    self.header.important_colors = 0;
+  }
+
+  // Draws `other` with its top-left corner at (x, y), clipped to this image.
+  // Alpha is composited source-over; 24-bpp destinations stay opaque, and
+  // indexed ones are promoted to 24-bpp first.
+  pub fn composite(&mut self, other: &Bitmap, x: i32, y: i32, mode: BlendMode) {
+    self.promote_truecolor();
+    let has_alpha = self.header.bpp == 32;
+
+    for sy in 0 .. other.height() {
+      for sx in 0 .. other.width() {
+        let (dx, dy) = (x as i64 + sx as i64, y as i64 + sy as i64);
+        if dx < 0 || dy < 0 || dx >= self.width() as i64 || dy >= self.height() as i64 {
+          continue
+        }
+
+        let index = dy as usize * self.width() as usize + dx as usize;
+        let [sr, sg, sb, sa] = other.straight(other.pixels[sy as usize * other.width() as usize + sx as usize]);
+        let [dr, dg, db, da] = self.straight(self.pixels[index]);
+
+        let out_alpha = sa + da * (1.0 - sa);
+        let channel = |source: f32, destination: f32| {
+          // The blended color only applies where the destination is covered.
+          let source = (1.0 - da) * source + da * mode.blend(source, destination);
+          if out_alpha > 0.0 { (source * sa + destination * da * (1.0 - sa)) / out_alpha } else { 0.0 }
+        };
+        let color = [channel(sr, dr), channel(sg, dg), channel(sb, db)];
+
+        let scale = if self.alpha_mode == AlphaMode::Premultiplied { out_alpha } else { 1.0 };
+        let byte = |value: f32| (value * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+        let out = Rgba::new(byte(color[0] * scale), byte(color[1] * scale), byte(color[2] * scale), byte(out_alpha));
+
+        self.pixels[index] = if has_alpha {
+          Pixel::ABGR(out.b, out.g, out.r, out.a)
+        } else {
+          Pixel::BGR(out.b, out.g, out.r)
+        };
+      }
+    }
+  }
+
+  // Stamps `mark` at `position`, flush with the chosen edges, blended
+  // source-over with its alpha scaled by `opacity` (0 to 1).
+  pub fn watermark(&mut self, mark: &Bitmap, position: WatermarkPosition, opacity: f32) {
+    if mark.pixels.is_empty() {
+      return
+    }
+
+    let opacity = opacity.clamp(0.0, 1.0);
+    let byte = |value: f32| (value * 255.0 + 0.5) as u8;
+    let pixels = mark.pixels.iter()
+      .map(|pixel| {
+        let [r, g, b, a] = mark.straight(*pixel);
+        Pixel::ABGR(byte(b), byte(g), byte(r), byte(a * opacity))
+      })
+      .collect();
+
+    let faded = Bitmap::assemble(mark.width(), mark.height() as i32, 32, Vec::new(), pixels);
+    let (width, height) = (self.width() as i64, self.height() as i64);
+    let (mark_width, mark_height) = (mark.width() as i64, mark.height() as i64);
+
+    let (x, y) = match position {
+      WatermarkPosition::TopLeft | WatermarkPosition::Tiled => (0, 0),
+      WatermarkPosition::TopRight => (width - mark_width, 0),
+      WatermarkPosition::BottomLeft => (0, height - mark_height),
+      WatermarkPosition::BottomRight => (width - mark_width, height - mark_height),
+      WatermarkPosition::Center => ((width - mark_width) / 2, (height - mark_height) / 2)
+    };
+
+    if position != WatermarkPosition::Tiled {
+      self.composite(&faded, x as i32, y as i32, BlendMode::SourceOver);
+      return
+    }
+
+    for y in (0 .. height).step_by(mark_height as usize) {
+      for x in (0 .. width).step_by(mark_width as usize) {
+        self.composite(&faded, x as i32, y as i32, BlendMode::SourceOver);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  use crate::{Bitmap, BlendMode, Pixel};
+
+  #[test]
+  fn composites_source_over() {
+    let mut bitmap = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(0, 0, 255, 255), Pixel::ABGR(0, 0, 0, 0)]).unwrap();
+    let half_blue = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(255, 0, 0, 128); 2]).unwrap();
+
+    // 128/255 of blue over opaque red, and the source alone over nothing.
+    bitmap.composite(&half_blue, 0, 0, BlendMode::SourceOver);
+    assert_eq!(bitmap.pixels, [Pixel::ABGR(128, 0, 127, 255), Pixel::ABGR(255, 0, 0, 128)]);
+  }
+
+  #[test]
+  fn composites_blend_modes_within_the_image() {
+    let mut bitmap = Bitmap::from_parts(2, 1, 24, vec![], vec![Pixel::BGR(50, 100, 200); 2]).unwrap();
+    let source = Bitmap::from_parts(2, 1, 24, vec![], vec![Pixel::BGR(9, 9, 9), Pixel::BGR(0, 255, 128)]).unwrap();
+
+    // Only the source's second pixel lands on the image.
+    bitmap.composite(&source, -1, 0, BlendMode::Multiply);
+    assert_eq!(bitmap.pixels, [Pixel::BGR(0, 100, 100), Pixel::BGR(50, 100, 200)]);
+  }
+}
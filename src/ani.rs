@@ -0,0 +1,177 @@
+// Animated cursors (.ani): a RIFF "ACON" form holding ICO/CUR frames.
+
+use alloc::vec::Vec;
+
+use crate::decoder::read_u32;
+use crate::{Bitmap, BitmapError, BitmapResult, ErrorContext, Ico};
+
+const ANIH_SIZE: usize = 36;
+// Frames are stored as ICO/CUR files rather than raw bitmap data.
+const ICON_FLAG: u32 = 1;
+
+// One step of the animation: which frame to show, and for how long in
+// jiffies (1/60 s).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AniStep {
+  pub frame: u32,
+  pub jiffies: u32
+}
+
+impl AniStep {
+  pub fn duration_ms(&self) -> u32 {
+    (self.jiffies as u64 * 1000 / 60) as u32
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ani {
+  // Default display rate from the "anih" header, in jiffies.
+  pub default_rate: u32,
+  pub frames: Vec<Ico>,
+  pub steps: Vec<AniStep>
+}
+
+impl Ani {
+  pub fn from_bytes(data: &[u8]) -> BitmapResult<Ani> {
+    if data.len() < 12 || &data[0 .. 4] != b"RIFF" || &data[8 .. 12] != b"ACON" {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let end = (read_u32(data, 4)? as usize).saturating_add(8).min(data.len());
+    let mut header = None;
+    let mut rates = None;
+    let mut sequence = None;
+    let mut frames = Vec::new();
+
+    for (id, body) in chunks(&data[12 .. end]) {
+      match &id {
+        b"anih" => header = Some(body),
+        b"rate" => rates = Some(words(body)),
+        b"seq " => sequence = Some(words(body)),
+        b"LIST" if body.starts_with(b"fram") => {
+          for (id, body) in chunks(&body[4 ..]) {
+            if &id == b"icon" {
+              frames.push(Ico::from_bytes(body)?);
+            }
+          }
+        }
+        _ => {}
+      }
+    }
+
+    let header = header.filter(|header| header.len() >= ANIH_SIZE).ok_or(BitmapError::InvalidBitmapData)?;
+    let (frame_count, step_count) = (read_u32(header, 4)?, read_u32(header, 8)?);
+    let (default_rate, flags) = (read_u32(header, 28)?, read_u32(header, 32)?);
+
+    if flags & ICON_FLAG == 0 {
+      return Err(BitmapError::UnsupportedCompression)
+    }
+
+    if frames.len() != frame_count as usize {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    // Without a "seq " chunk, frames play in order, once each.
+    let sequence = sequence.unwrap_or_else(|| (0 .. frame_count).collect());
+    if sequence.len() != step_count as usize || sequence.iter().any(|frame| *frame >= frame_count) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    if rates.as_ref().is_some_and(|rates| rates.len() != sequence.len()) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let steps = sequence.iter().enumerate()
+      .map(|(step, frame)| AniStep {
+        frame: *frame,
+        jiffies: rates.as_ref().map_or(default_rate, |rates| rates[step])
+      })
+      .collect();
+
+    Ok(Ani { default_rate, frames, steps })
+  }
+
+  // Decodes the first image of `frame`; other sizes are in `frames[frame]`.
+  pub fn bitmap(&self, frame: usize) -> BitmapResult<Bitmap> {
+    self.frames.get(frame).ok_or(BitmapError::InvalidBitmapData)?.bitmap(0).map_err(|err| err.context(ErrorContext::AniFrame(frame)))
+  }
+
+  // Total length of one loop through the animation, in jiffies.
+  pub fn total_jiffies(&self) -> u64 {
+    self.steps.iter().map(|step| step.jiffies as u64).sum()
+  }
+}
+
+// Iterates the (id, body) chunks of a RIFF form, stopping at the first
+// truncated one. Bodies are padded to even lengths.
+fn chunks(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+  core::iter::from_fn(move || {
+    let id: [u8; 4] = data.get(0 .. 4)?.try_into().ok()?;
+    let len = read_u32(data, 4).ok()? as usize;
+    let body = data.get(8 .. 8usize.checked_add(len)?)?;
+    data = data.get(8 + len + (len & 1) ..).unwrap_or_default();
+    Some((id, body))
+  })
+}
+
+fn words(body: &[u8]) -> Vec<u32> {
+  body.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec::Vec;
+
+  use crate::encode::tests::sample;
+  use crate::ico::tests::ico;
+  use crate::{Ani, AniStep, Ico};
+
+  fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+      out.push(0);
+    }
+    out
+  }
+
+  fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+  }
+
+  // Two cursor frames played 0, 1, 0, with per-step rates when given.
+  fn ani(rates: Option<&[u32]>) -> Vec<u8> {
+    let mut body = b"ACON".to_vec();
+    body.extend(chunk(b"anih", &words(&[36, 2, 3, 0, 0, 0, 0, 10, 1])));
+    if let Some(rates) = rates {
+      body.extend(chunk(b"rate", &words(rates)));
+    }
+    body.extend(chunk(b"seq ", &words(&[0, 1, 0])));
+
+    let mut frames = b"fram".to_vec();
+    frames.extend(chunk(b"icon", &ico(Ico::CURSOR, &[sample(24, 5)])));
+    frames.extend(chunk(b"icon", &ico(Ico::CURSOR, &[sample(32, 3)])));
+    body.extend(chunk(b"LIST", &frames));
+    chunk(b"RIFF", &body)
+  }
+
+  #[test]
+  fn reads_frames_and_steps() {
+    let parsed = Ani::from_bytes(&ani(Some(&[5, 6, 7]))).unwrap();
+    assert_eq!(parsed.steps, [AniStep { frame: 0, jiffies: 5 }, AniStep { frame: 1, jiffies: 6 }, AniStep { frame: 0, jiffies: 7 }]);
+    assert_eq!(parsed.total_jiffies(), 18);
+    assert_eq!(parsed.bitmap(1).unwrap(), sample(32, 3));
+
+    // Without a "rate" chunk every step takes the header's default.
+    assert_eq!(Ani::from_bytes(&ani(None)).unwrap().total_jiffies(), 30);
+  }
+
+  #[test]
+  fn rejects_mismatched_counts() {
+    assert!(Ani::from_bytes(&ani(Some(&[5, 6]))).is_err());
+    let file = ani(None);
+    assert!(Ani::from_bytes(&file[.. 40]).is_err());
+    assert!(Ani::from_bytes(&file[.. 11]).is_err());
+  }
+}
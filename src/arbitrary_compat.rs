@@ -0,0 +1,94 @@
+// `arbitrary` support for fuzz targets and property tests. Header types
+// derive `Arbitrary`; bitmaps and files are built so that they stay
+// structurally valid unless a defect is asked for.
+
+use alloc::vec::Vec;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::decoder::FILE_HEADER_SIZE;
+use crate::{Bitmap, Pixel, Rgba};
+
+// Largest width and height generated, to keep inputs cheap to decode.
+const MAX_DIMENSION: u32 = 64;
+
+impl<'a> Arbitrary<'a> for Bitmap {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<Bitmap> {
+    let width = u.int_in_range(1 ..= MAX_DIMENSION)?;
+    let height = u.int_in_range(1 ..= MAX_DIMENSION)?;
+    let bpp = *u.choose(&[1u16, 4, 8, 24, 32])?;
+
+    // Palettes are stored without alpha, so entries are opaque to round-trip.
+    let mut palette = Vec::new();
+    if bpp <= 8 {
+      for _ in 0 .. u.int_in_range(1 ..= 1usize << bpp)? {
+        palette.push(Rgba { a: 255, ..Rgba::arbitrary(u)? });
+      }
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for _ in 0 .. width * height {
+      pixels.push(match bpp {
+        1 | 4 | 8 => Pixel::PaletteColor(u.int_in_range(0 ..= (palette.len() - 1) as u8)?),
+        24 => Pixel::BGR(u.arbitrary()?, u.arbitrary()?, u.arbitrary()?),
+        _ => Pixel::ABGR(u.arbitrary()?, u.arbitrary()?, u.arbitrary()?, u.arbitrary()?)
+      });
+    }
+
+    let pix_height = if u.arbitrary()? { -(height as i32) } else { height as i32 };
+    Ok(Bitmap::assemble(width, pix_height, bpp, palette, pixels))
+  }
+}
+
+// One way `ArbitraryBmp` moves a file slightly out of spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Arbitrary)]
+pub enum Defect {
+  // This many bytes (at least one) cut from the end.
+  Truncated(u8),
+  FileSize,
+  PixelOffset,
+  HeaderSize,
+  Width,
+  Height,
+  BitsPerPixel,
+  ImageSize,
+  Colors
+}
+
+// Encoded bytes of an arbitrary `Bitmap`. About one in four have a
+// `defect`: one header field nudged by a small amount, or the tail cut off.
+#[derive(Debug, Clone)]
+pub struct ArbitraryBmp {
+  pub bytes: Vec<u8>,
+  pub defect: Option<Defect>
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryBmp {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<ArbitraryBmp> {
+    let mut bytes = Bitmap::arbitrary(u)?.encode();
+    let defect = if u.ratio(1, 4)? { Some(Defect::arbitrary(u)?) } else { None };
+
+    let field = match defect {
+      None => return Ok(ArbitraryBmp { bytes, defect }),
+      Some(Defect::Truncated(len)) => {
+        bytes.truncate(bytes.len().saturating_sub(len.max(1) as usize));
+        return Ok(ArbitraryBmp { bytes, defect })
+      }
+      Some(Defect::FileSize) => 2,
+      Some(Defect::PixelOffset) => 10,
+      Some(Defect::HeaderSize) => 14,
+      Some(Defect::Width) => FILE_HEADER_SIZE + 4,
+      Some(Defect::Height) => FILE_HEADER_SIZE + 8,
+      Some(Defect::BitsPerPixel) => FILE_HEADER_SIZE + 14,
+      Some(Defect::ImageSize) => FILE_HEADER_SIZE + 20,
+      Some(Defect::Colors) => FILE_HEADER_SIZE + 32
+    };
+
+    // Every field is at least 16 bits; nudging only the low word keeps the
+    // change small for the 32-bit ones too.
+    let delta = *u.choose(&[-2i16, -1, 1, 2, 16])?;
+    let value = u16::from_le_bytes([bytes[field], bytes[field + 1]]);
+    bytes[field .. field + 2].copy_from_slice(&value.wrapping_add_signed(delta).to_le_bytes());
+    Ok(ArbitraryBmp { bytes, defect })
+  }
+}
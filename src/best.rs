@@ -0,0 +1,121 @@
+// Picking the smallest of several lossless encodings.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::{Bitmap, CompressionMethod, Pixel, Rgba};
+
+// What `encode_smallest` wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodeChoice {
+  pub bpp: u16,
+  pub compression: CompressionMethod,
+  pub len: usize
+}
+
+impl Bitmap {
+  // Encodes each candidate and keeps the smallest file: uncompressed at
+  // this depth, plus RLE for 4- and 8-bpp images with the `rle` feature.
+  // With `change_depth`, also a rebuilt palette at the smallest depth that
+  // holds every color, for indexed images and for opaque truecolor ones
+  // with at most 256 colors. Every candidate decodes to the same colors
+  // through `Bitmap::from_bytes`, so depths and compressions this build
+  // can't read back are left out. Ties go to the earlier one.
+  pub fn encode_smallest(&self, change_depth: bool) -> (Vec<u8>, EncodeChoice) {
+    let mut candidates = Vec::new();
+    candidates.push(self.clone());
+    if change_depth {
+      candidates.extend(self.exact_indexed().filter(|indexed| indexed.header.bpp != self.header.bpp && (indexed.header.bpp >= 8 || cfg!(feature = "low-depth"))));
+    }
+
+    let mut best: Option<(Vec<u8>, EncodeChoice)> = None;
+    for candidate in &candidates {
+      let bpp = candidate.header.bpp;
+      let mut encodings = Vec::new();
+      encodings.push((candidate.encode(), CompressionMethod::None));
+      #[cfg(feature = "rle")]
+      if let Some(bytes) = candidate.encode_rle().ok().filter(|_| bpp == 8 || cfg!(feature = "low-depth")) {
+        encodings.push((bytes, if bpp == 8 { CompressionMethod::Rle8Bit } else { CompressionMethod::Rle4Bit }));
+      }
+
+      for (bytes, compression) in encodings {
+        if best.as_ref().is_none_or(|(smallest, _)| bytes.len() < smallest.len()) {
+          let choice = EncodeChoice { bpp, compression, len: bytes.len() };
+          best = Some((bytes, choice));
+        }
+      }
+    }
+
+    trace_event!(bpp = best.as_ref().map(|(_, choice)| choice.bpp), candidates = candidates.len(), "picked smallest encoding");
+    best.expect("the uncompressed encoding is always a candidate")
+  }
+
+  // Indexed copy at the smallest depth holding every color, if the image
+  // is opaque and has at most 256 of them.
+  fn exact_indexed(&self) -> Option<Bitmap> {
+    if self.header.bpp <= 8 {
+      let mut indexed = self.clone();
+      indexed.optimize_palette();
+      return Some(indexed)
+    }
+
+    let mut indices = BTreeMap::new();
+    let mut palette = Vec::new();
+    let mut pixels = Vec::with_capacity(self.pixels.len());
+    for pixel in &self.pixels {
+      let color = self.color(*pixel);
+      if color.a != 255 {
+        return None
+      }
+
+      let key = (color.r, color.g, color.b);
+      let index = match indices.get(&key) {
+        Some(index) => *index,
+        None if palette.len() < 256 => {
+          palette.push(Rgba { a: 255, ..color });
+          indices.insert(key, palette.len() as u8 - 1);
+          palette.len() as u8 - 1
+        }
+        None => return None
+      };
+      pixels.push(Pixel::PaletteColor(index));
+    }
+
+    let mut indexed = Bitmap::from_parts(self.width(), self.height(), 8, palette, pixels).ok()?;
+    indexed.optimize_palette();
+    Some(indexed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec::Vec;
+
+  use crate::{Bitmap, CompressionMethod, Pixel};
+
+  fn assert_decodes_to(bitmap: &Bitmap, change_depth: bool) -> CompressionMethod {
+    let (bytes, choice) = bitmap.encode_smallest(change_depth);
+    assert_eq!(choice.len, bytes.len());
+    let decoded = Bitmap::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.header.bpp, choice.bpp);
+    assert_eq!(decoded.to_rgba8(), bitmap.to_rgba8());
+    choice.compression
+  }
+
+  #[cfg(feature = "rle")]
+  #[test]
+  fn flat_indexed_image_picks_rle_and_decodes() {
+    let bitmap = Bitmap::from_parts(8, 8, 8, alloc::vec![crate::Rgba::BLACK, crate::Rgba::WHITE], alloc::vec![Pixel::PaletteColor(1); 64]).unwrap();
+    assert_eq!(assert_decodes_to(&bitmap, false), CompressionMethod::Rle8Bit);
+    assert_decodes_to(&bitmap, true);
+  }
+
+  #[test]
+  fn truecolor_candidates_decode_to_the_same_colors() {
+    let pixels: Vec<_> = (0 .. 48u32).map(|at| Pixel::BGR((at % 3 * 80) as u8, 0, (at / 16 * 100) as u8)).collect();
+    let bitmap = Bitmap::from_parts(8, 6, 24, Vec::new(), pixels).unwrap();
+    assert_decodes_to(&bitmap, false);
+    assert_decodes_to(&bitmap, true);
+  }
+}
@@ -0,0 +1,106 @@
+// Rewrites a BMP file at another depth, or exports it to another format
+// chosen by the output extension.
+//
+//   bmpconvert [--bpp 1|4|8|24|32] [--dither none|floyd|atkinson|bayer] [--rle] INPUT OUTPUT
+//
+// PPM and PAM are always available; PNG, QOI and TGA need the matching
+// crate features. `--rle` writes BMP output as RLE4 or RLE8, so needs 4 or
+// 8 bpp, and reads the file back before writing it.
+
+use std::process::ExitCode;
+
+use microbmp::{Bitmap, BitmapError, BitmapResult, Dither};
+
+const USAGE: &str = "usage: bmpconvert [--bpp 1|4|8|24|32] [--dither none|floyd|atkinson|bayer] [--rle] INPUT OUTPUT";
+
+struct Options {
+  bpp: Option<u16>,
+  dither: Dither,
+  rle: bool,
+  input: String,
+  output: String
+}
+
+fn parse_args() -> Result<Options, String> {
+  let mut args = std::env::args().skip(1);
+  let (mut bpp, mut dither, mut rle, mut paths) = (None, Dither::None, false, Vec::new());
+
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--bpp" => {
+        let value = args.next().ok_or("--bpp needs a value")?;
+        bpp = Some(value.parse().map_err(|_| format!("invalid depth {}", value))?);
+      }
+      "--dither" => {
+        dither = match args.next().as_deref() {
+          Some("none") => Dither::None,
+          Some("floyd") => Dither::FloydSteinberg,
+          Some("atkinson") => Dither::Atkinson,
+          Some("bayer") => Dither::Bayer,
+          _ => return Err("--dither needs none, floyd, atkinson or bayer".into())
+        };
+      }
+      "--rle" => rle = true,
+      _ => paths.push(arg)
+    }
+  }
+
+  let [input, output]: [String; 2] = paths.try_into().map_err(|_| USAGE.to_string())?;
+  Ok(Options { bpp, dither, rle, input, output })
+}
+
+// RLE output decoded again, failing unless it reproduces `bitmap`.
+fn verified_rle(bitmap: &Bitmap) -> BitmapResult<Vec<u8>> {
+  let bytes = bitmap.encode_rle()?;
+  if !Bitmap::from_bytes(&bytes)?.compare(bitmap, 0).matches {
+    return Err(BitmapError::BadRleStream)
+  }
+  Ok(bytes)
+}
+
+fn export(bitmap: &Bitmap, path: &str, rle: bool) -> BitmapResult<()> {
+  let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+  let bytes = match extension.as_deref() {
+    Some("ppm") => bitmap.to_ppm(),
+    Some("pam") => bitmap.to_pam(),
+    #[cfg(feature = "png")]
+    Some("png") => bitmap.encode_png()?,
+    #[cfg(feature = "qoi")]
+    Some("qoi") => bitmap.to_qoi(),
+    #[cfg(feature = "tga")]
+    Some("tga") => bitmap.to_tga(true)?,
+    _ if rle => verified_rle(bitmap)?,
+    _ => bitmap.encode()
+  };
+
+  std::fs::write(path, bytes)?;
+  Ok(())
+}
+
+fn run(options: &Options) -> BitmapResult<()> {
+  let bitmap = Bitmap::open(&options.input)?;
+  let bitmap = match options.bpp {
+    Some(bpp) => bitmap.to_depth(bpp, options.dither)?,
+    None => bitmap
+  };
+
+  export(&bitmap, &options.output, options.rle)
+}
+
+fn main() -> ExitCode {
+  let options = match parse_args() {
+    Ok(options) => options,
+    Err(err) => {
+      eprintln!("bmpconvert: {}", err);
+      return ExitCode::from(2)
+    }
+  };
+
+  match run(&options) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("bmpconvert: {}: {}", options.input, err);
+      ExitCode::FAILURE
+    }
+  }
+}
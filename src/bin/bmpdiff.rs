@@ -0,0 +1,84 @@
+// Compares two BMP files pixel by pixel, for golden-image tests.
+//
+//   bmpdiff [--tolerance N] [--diff OUTPUT] [--json] EXPECTED ACTUAL
+//
+// Exits 0 when the images match, 1 when they differ and 2 on errors.
+// `--json` prints the comparison stats as one object instead of text.
+
+use std::process::ExitCode;
+
+use microbmp::{Bitmap, BitmapResult};
+use serde_json::json;
+
+const USAGE: &str = "usage: bmpdiff [--tolerance N] [--diff OUTPUT] [--json] EXPECTED ACTUAL";
+
+struct Options {
+  tolerance: u8,
+  diff: Option<String>,
+  json: bool,
+  expected: String,
+  actual: String
+}
+
+fn parse_args() -> Result<Options, String> {
+  let mut args = std::env::args().skip(1);
+  let (mut tolerance, mut diff, mut json, mut paths) = (0, None, false, Vec::new());
+
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--tolerance" => {
+        let value = args.next().ok_or("--tolerance needs a value")?;
+        tolerance = value.parse().map_err(|_| format!("invalid tolerance {}", value))?;
+      }
+      "--diff" => diff = Some(args.next().ok_or("--diff needs a path")?),
+      "--json" => json = true,
+      _ => paths.push(arg)
+    }
+  }
+
+  let [expected, actual]: [String; 2] = paths.try_into().map_err(|_| USAGE.to_string())?;
+  Ok(Options { tolerance, diff, json, expected, actual })
+}
+
+fn run(options: &Options) -> BitmapResult<bool> {
+  let expected = Bitmap::open(&options.expected)?;
+  let actual = Bitmap::open(&options.actual)?;
+  let result = expected.compare(&actual, options.tolerance);
+
+  if options.json {
+    let (size, other_size) = ([expected.width(), expected.height()], [actual.width(), actual.height()]);
+    println!("{}", json!({ "expected": options.expected, "actual": options.actual, "tolerance": options.tolerance, "sizes": [size, other_size], "result": result }));
+  } else if !result.same_size {
+    println!("size differs: {}x{} vs {}x{}", expected.width(), expected.height(), actual.width(), actual.height());
+  } else if !result.matches {
+    let total = expected.width() as usize * expected.height() as usize;
+    println!("{} of {} pixels differ, by up to {}", result.differing_pixels, total, result.max_delta);
+  }
+
+  if let Some(path) = &options.diff {
+    if !result.matches {
+      expected.diff_image(&actual).save(path)?;
+    }
+  }
+
+  Ok(result.matches)
+}
+
+fn main() -> ExitCode {
+  let options = match parse_args() {
+    Ok(options) => options,
+    Err(err) => {
+      eprintln!("bmpdiff: {}", err);
+      return ExitCode::from(2)
+    }
+  };
+
+  match run(&options) {
+    Ok(true) => ExitCode::SUCCESS,
+    Ok(false) => ExitCode::FAILURE,
+    Err(err) => {
+      eprintln!("bmpdiff: {}", err);
+      ExitCode::from(2)
+    }
+  }
+}
@@ -0,0 +1,114 @@
+// Writes test patterns at a chosen size and depth.
+//
+//   bmpgen gradient|checker|bars|noise [--width N] [--height N] [--bpp N]
+//          [--cell N] [--seed N] [--rle] OUTPUT
+//
+// Indexed depths use a quantized palette; `--rle` needs 4 or 8 bpp. RLE
+// output is decoded again before it's written, so a stream this crate
+// can't read back never reaches disk.
+
+use std::process::ExitCode;
+
+use microbmp::patterns::{self, Direction};
+use microbmp::{Bitmap, BitmapError, BitmapResult, Dither, Rgba};
+
+const USAGE: &str = "usage: bmpgen gradient|checker|bars|noise [--width N] [--height N] [--bpp N] [--cell N] [--seed N] [--rle] OUTPUT";
+
+#[derive(Clone, Copy)]
+enum Pattern {
+  Gradient,
+  Checker,
+  Bars,
+  Noise
+}
+
+struct Options {
+  pattern: Pattern,
+  width: u32,
+  height: u32,
+  bpp: u16,
+  cell: u32,
+  seed: u32,
+  rle: bool,
+  output: String
+}
+
+fn parse_args() -> Result<Options, String> {
+  let mut args = std::env::args().skip(1);
+  let mut options = Options { pattern: Pattern::Gradient, width: 256, height: 256, bpp: 24, cell: 16, seed: 1, rle: false, output: String::new() };
+  let mut positional = Vec::new();
+
+  while let Some(arg) = args.next() {
+    let mut number = |name: &str| -> Result<u32, String> {
+      let value = args.next().ok_or(format!("{} needs a value", name))?;
+      value.parse().map_err(|_| format!("invalid {} {}", name, value))
+    };
+
+    match arg.as_str() {
+      "--width" => options.width = number("--width")?,
+      "--height" => options.height = number("--height")?,
+      "--bpp" => options.bpp = number("--bpp")? as u16,
+      "--cell" => options.cell = number("--cell")?.max(1),
+      "--seed" => options.seed = number("--seed")?,
+      "--rle" => options.rle = true,
+      _ => positional.push(arg)
+    }
+  }
+
+  let [pattern, output]: [String; 2] = positional.try_into().map_err(|_| USAGE.to_string())?;
+  options.pattern = match pattern.as_str() {
+    "gradient" => Pattern::Gradient,
+    "checker" => Pattern::Checker,
+    "bars" => Pattern::Bars,
+    "noise" => Pattern::Noise,
+    _ => return Err(format!("unknown pattern {}", pattern))
+  };
+  options.output = output;
+  Ok(options)
+}
+
+fn generate(options: &Options) -> BitmapResult<Bitmap> {
+  let (width, height) = (options.width, options.height);
+  let bitmap = match options.pattern {
+    Pattern::Gradient => patterns::gradient(width, height, Rgba::BLACK, Rgba::WHITE, Direction::Horizontal)?,
+    Pattern::Checker => patterns::checkerboard(width, height, options.cell, Rgba::WHITE, Rgba::BLACK)?,
+    Pattern::Bars => patterns::color_bars(width, height)?,
+    Pattern::Noise => patterns::noise(width, height, options.seed)?
+  };
+
+  bitmap.to_depth(options.bpp, Dither::None)
+}
+
+// RLE output decoded again, failing unless it reproduces `bitmap`.
+fn verified_rle(bitmap: &Bitmap) -> BitmapResult<Vec<u8>> {
+  let bytes = bitmap.encode_rle()?;
+  if !Bitmap::from_bytes(&bytes)?.compare(bitmap, 0).matches {
+    return Err(BitmapError::BadRleStream)
+  }
+  Ok(bytes)
+}
+
+fn run(options: &Options) -> BitmapResult<()> {
+  let bitmap = generate(options)?;
+  let bytes = if options.rle { verified_rle(&bitmap)? } else { bitmap.encode() };
+  std::fs::write(&options.output, bytes)?;
+  Ok(())
+}
+
+fn main() -> ExitCode {
+  let options = match parse_args() {
+    Ok(options) => options,
+    Err(err) => {
+      eprintln!("bmpgen: {}", err);
+      return ExitCode::from(2)
+    }
+  };
+
+  match run(&options) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("bmpgen: {}", err);
+      ExitCode::FAILURE
+    }
+  }
+}
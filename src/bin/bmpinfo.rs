@@ -0,0 +1,157 @@
+// Prints the headers and validation findings of BMP files.
+//
+//   bmpinfo [--json] FILE...
+//
+// `--json` prints one array with an object per file instead of text.
+
+use std::process::ExitCode;
+
+use microbmp::{identify, validate, HeaderVersion, Severity, ValidationReport, Variant};
+use serde_json::{json, Value};
+
+const INCHES_PER_METER: f64 = 39.3701;
+
+// Header fields of one file, as shown by either output mode.
+struct Info {
+  variant: Variant,
+  header_size: u32,
+  width: i32,
+  height: u32,
+  // Palette entries and how many are important, for indexed images.
+  palette: Option<(u32, u32)>,
+  dpi: Option<(f64, f64)>,
+  report: ValidationReport
+}
+
+fn field(data: &[u8], at: usize) -> Option<u32> {
+  Some(u32::from_le_bytes(data.get(at .. at + 4)?.try_into().ok()?))
+}
+
+fn label(severity: Severity) -> &'static str {
+  match severity {
+    Severity::Info => "note",
+    Severity::Warning => "warning",
+    Severity::Error => "error"
+  }
+}
+
+fn inspect(path: &str) -> Result<Info, String> {
+  let data = std::fs::read(path).map_err(|err| err.to_string())?;
+  let variant = identify(&data).map_err(|err| err.to_string())?;
+
+  // The core header packs 16-bit dimensions and has nothing past the bit depth.
+  let core = variant.header == HeaderVersion::Core;
+  let (width, height) = if core {
+    let word = |at: usize| data.get(at .. at + 2).map_or(0, |bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as i32);
+    (word(18), word(20))
+  } else {
+    (field(&data, 18).unwrap_or(0) as i32, field(&data, 22).unwrap_or(0) as i32)
+  };
+
+  let extended = |at: usize| if core { None } else { field(&data, at) };
+  let palette = (variant.bpp <= 8).then(|| {
+    let colors = match extended(46).unwrap_or(0) {
+      0 => 1 << variant.bpp,
+      n => n
+    };
+    (colors, extended(50).unwrap_or(0))
+  });
+
+  let dpi = match (extended(38), extended(42)) {
+    (Some(x), Some(y)) if x != 0 && y != 0 => {
+      let dpi = |ppm: u32| (ppm as i32 as f64 / INCHES_PER_METER).round();
+      Some((dpi(x), dpi(y)))
+    }
+    _ => None
+  };
+
+  Ok(Info {
+    variant,
+    header_size: field(&data, 14).unwrap_or(0),
+    width,
+    height: height.unsigned_abs(),
+    palette,
+    dpi,
+    report: validate(&data)
+  })
+}
+
+fn print_text(path: &str, info: &Info) {
+  let variant = &info.variant;
+  let order = if variant.top_down { "top-down" } else { "bottom-up" };
+
+  println!("{}", path);
+  println!("  header:      {:?} ({} bytes)", variant.header, info.header_size);
+  println!("  dimensions:  {}x{} {}", info.width, info.height, order);
+  println!("  bpp:         {}", variant.bpp);
+  println!("  compression: {:?} ({:?})", variant.compression, variant.format);
+  match info.palette {
+    Some((colors, 0)) => println!("  palette:     {} entries", colors),
+    Some((colors, important)) => println!("  palette:     {} entries, {} important", colors, important),
+    None => println!("  palette:     none")
+  }
+  match info.dpi {
+    Some((x, y)) => println!("  dpi:         {}x{}", x, y),
+    None => println!("  dpi:         unset")
+  }
+  for issue in &info.report.issues {
+    println!("  {}: {}", label(issue.severity()), issue);
+  }
+}
+
+fn to_json(path: &str, info: &Info) -> Value {
+  let issues: Vec<Value> = info.report.issues.iter()
+    .map(|issue| json!({ "severity": label(issue.severity()), "message": issue.to_string(), "issue": issue }))
+    .collect();
+
+  json!({
+    "path": path,
+    "variant": info.variant,
+    "header_size": info.header_size,
+    "width": info.width,
+    "height": info.height,
+    "palette": info.palette.map(|(colors, important)| json!({ "entries": colors, "important": important })),
+    "dpi": info.dpi.map(|(x, y)| json!([x, y])),
+    "valid": info.report.is_valid(),
+    "issues": issues
+  })
+}
+
+fn main() -> ExitCode {
+  let mut paths: Vec<String> = std::env::args().skip(1).collect();
+  let json = paths.iter().any(|arg| arg == "--json");
+  paths.retain(|arg| arg != "--json");
+  if paths.is_empty() {
+    eprintln!("usage: bmpinfo [--json] FILE...");
+    return ExitCode::from(2)
+  }
+
+  let mut ok = true;
+  let mut entries = Vec::new();
+  for path in &paths {
+    match inspect(path) {
+      Ok(info) => {
+        ok &= info.report.is_valid();
+        if json {
+          entries.push(to_json(path, &info));
+        } else {
+          print_text(path, &info);
+        }
+      }
+      Err(err) => {
+        ok = false;
+        if json {
+          entries.push(json!({ "path": path, "error": err }));
+        } else {
+          eprintln!("bmpinfo: {}: {}", path, err);
+        }
+      }
+    }
+  }
+
+  if json {
+    println!("{}", Value::Array(entries));
+  }
+
+  if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
@@ -0,0 +1,51 @@
+// Rewrites BMP files in minimal canonical form, dropping gaps, trailing
+// data, unreachable palette entries and reserved-field contents.
+//
+//   bmpstrip INPUT [OUTPUT]
+//
+// Without OUTPUT the input file is replaced.
+
+use std::process::ExitCode;
+
+use microbmp::{Bitmap, BitmapResult};
+
+fn run(input: &str, output: &str) -> BitmapResult<()> {
+  let bitmap = Bitmap::open(input)?;
+  let report = bitmap.forensics()?;
+
+  let removed = [
+    (report.trailing.len(), "trailing bytes"),
+    (report.gap.len(), "gap bytes"),
+    (report.excess_palette_entries, "unreachable palette entries"),
+    (report.palette_reserved_bytes, "nonzero palette reserved bytes"),
+    (report.padding_bytes, "nonzero padding bytes")
+  ];
+  for (count, what) in removed.iter().filter(|(count, _)| *count != 0) {
+    println!("{}: removed {} {}", input, count, what);
+  }
+  if report.file_reserved != [0, 0] || report.header_reserved != 0 {
+    println!("{}: zeroed reserved header fields", input);
+  }
+
+  bitmap.sanitized().save(output)
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let (input, output) = match &args[..] {
+    [input] => (input, input),
+    [input, output] => (input, output),
+    _ => {
+      eprintln!("usage: bmpstrip INPUT [OUTPUT]");
+      return ExitCode::from(2)
+    }
+  };
+
+  match run(input, output) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("bmpstrip: {}: {}", input, err);
+      ExitCode::FAILURE
+    }
+  }
+}
@@ -0,0 +1,192 @@
+// Bitmaps borrowing the caller's copy of the file, e.g. a network buffer or
+// a memory-mapped file, instead of owning one.
+
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use core::convert::Infallible;
+use core::ops::ControlFlow;
+
+use crate::{decode_parts, AlphaMode, Bitmap, BitmapResult, BitmapV5Header, ChannelMasks, ChannelScaling, DecodeOptions, Decoder, FileHeader, Pixel, Pixels, Rect, Rgba, ScanlineOrder};
+
+// Read-only counterpart of `Bitmap` over borrowed file bytes. Nothing is
+// copied: the palette and pixels are decoded from `data` as they're read.
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapRef<'a> {
+  pub data: &'a [u8],
+  pub file_header: FileHeader,
+  pub header: BitmapV5Header,
+  masks: Option<ChannelMasks>
+}
+
+impl<'a> BitmapRef<'a> {
+  // Parses the headers, checking the whole pixel array is in `data`.
+  pub fn new(data: &'a [u8]) -> BitmapResult<BitmapRef<'a>> {
+    let decoder = Decoder::new(data)?;
+    Ok(BitmapRef { data, file_header: decoder.file_header, header: decoder.header, masks: decoder.masks })
+  }
+
+  pub fn decoder(&self) -> Decoder<'a> {
+    Decoder { data: self.data, file_header: self.file_header, header: self.header, masks: self.masks, scaling: ChannelScaling::default() }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.header.pix_width.unsigned_abs()
+  }
+
+  pub fn height(&self) -> u32 {
+    self.header.pix_height.unsigned_abs()
+  }
+
+  // Pixel at column `x` of row `y`, counted from the top-left corner.
+  pub fn pixel(&self, x: u32, y: u32) -> Option<Pixel> {
+    self.decoder().pixel(x, y)
+  }
+
+  // Resolves a pixel through the palette; missing entries come out opaque black.
+  pub fn color(&self, pixel: Pixel) -> Rgba {
+    self.decoder().color(pixel)
+  }
+
+  // Palette entries as stored, up to the 256 a pixel can index.
+  pub fn palette(&self) -> impl Iterator<Item = Rgba> + 'a {
+    let decoder = self.decoder();
+    (0 .. decoder.palette_len().min(256)).map_while(move |index| decoder.palette_entry(index as u8)).map(Rgba::from_rgbquad)
+  }
+
+  // Every pixel in top-to-bottom, left-to-right order.
+  pub fn pixels(&self) -> Pixels<'a> {
+    self.decoder().pixels()
+  }
+
+  // Owned copy of the file, decoded in full with the default options.
+  // Unlike `pixel`, which returns alpha as stored, unused alpha bytes come
+  // out opaque.
+  pub fn to_bitmap(&self) -> Bitmap {
+    // The check can't break, so decoding always runs to the end.
+    let ControlFlow::Continue((file_header, header, palette, pixels, _)) = decode_parts(&self.decoder(), DecodeOptions::default(), || ControlFlow::<Infallible>::Continue(()));
+    let orientation = ScanlineOrder::from_height(header.pix_height);
+    Bitmap { data: self.data.to_vec(), file_header, header, palette, pixels, alpha_mode: AlphaMode::Straight, orientation }
+  }
+}
+
+// A bitmap that stays borrowed or shared until it's first written to, when
+// it's decoded or cloned into an owned `Bitmap`. Cloning a shared one only
+// bumps the reference count, so it can be handed to many threads at once.
+#[derive(Debug, Clone)]
+pub enum CowBitmap<'a> {
+  Borrowed(BitmapRef<'a>),
+  #[cfg(target_has_atomic = "ptr")]
+  Shared(Arc<Bitmap>),
+  Owned(Bitmap)
+}
+
+impl<'a> CowBitmap<'a> {
+  pub fn width(&self) -> u32 {
+    match self {
+      CowBitmap::Borrowed(bitmap) => bitmap.width(),
+      #[cfg(target_has_atomic = "ptr")]
+      CowBitmap::Shared(bitmap) => bitmap.width(),
+      CowBitmap::Owned(bitmap) => bitmap.width()
+    }
+  }
+
+  pub fn height(&self) -> u32 {
+    match self {
+      CowBitmap::Borrowed(bitmap) => bitmap.height(),
+      #[cfg(target_has_atomic = "ptr")]
+      CowBitmap::Shared(bitmap) => bitmap.height(),
+      CowBitmap::Owned(bitmap) => bitmap.height()
+    }
+  }
+
+  pub fn pixel(&self, x: u32, y: u32) -> Option<Pixel> {
+    match self {
+      CowBitmap::Borrowed(bitmap) => bitmap.pixel(x, y),
+      #[cfg(target_has_atomic = "ptr")]
+      CowBitmap::Shared(bitmap) => bitmap.pixel(x, y).copied(),
+      CowBitmap::Owned(bitmap) => bitmap.pixel(x, y).copied()
+    }
+  }
+
+  pub fn color(&self, pixel: Pixel) -> Rgba {
+    match self {
+      CowBitmap::Borrowed(bitmap) => bitmap.color(pixel),
+      #[cfg(target_has_atomic = "ptr")]
+      CowBitmap::Shared(bitmap) => bitmap.color(pixel),
+      CowBitmap::Owned(bitmap) => bitmap.color(pixel)
+    }
+  }
+
+  pub fn is_owned(&self) -> bool {
+    matches!(self, CowBitmap::Owned(_))
+  }
+
+  // The bitmap to write to, decoding a borrowed one first. A shared one is
+  // cloned unless this is its last reference. `data` keeps the original
+  // file bytes until the bitmap is re-encoded.
+  pub fn to_mut(&mut self) -> &mut Bitmap {
+    if let CowBitmap::Borrowed(bitmap) = self {
+      *self = CowBitmap::Owned(bitmap.to_bitmap());
+    }
+
+    match self {
+      CowBitmap::Borrowed(_) => unreachable!(),
+      #[cfg(target_has_atomic = "ptr")]
+      CowBitmap::Shared(bitmap) => Arc::make_mut(bitmap),
+      CowBitmap::Owned(bitmap) => bitmap
+    }
+  }
+
+  pub fn into_owned(self) -> Bitmap {
+    match self {
+      CowBitmap::Borrowed(bitmap) => bitmap.to_bitmap(),
+      #[cfg(target_has_atomic = "ptr")]
+      CowBitmap::Shared(bitmap) => Arc::unwrap_or_clone(bitmap),
+      CowBitmap::Owned(bitmap) => bitmap
+    }
+  }
+
+  // Drawing operations of `Bitmap`, each taking ownership on first use.
+  pub fn set_pixel(&mut self, x: u32, y: u32, pixel: Pixel) {
+    self.to_mut().set_pixel(x, y, pixel);
+  }
+
+  pub fn fill_rect(&mut self, rect: Rect, color: Rgba) {
+    self.to_mut().fill_rect(rect, color);
+  }
+
+  pub fn draw_rect(&mut self, rect: Rect, color: Rgba) {
+    self.to_mut().draw_rect(rect, color);
+  }
+
+  pub fn draw_line(&mut self, from: (i64, i64), to: (i64, i64), color: Rgba) {
+    self.to_mut().draw_line(from, to, color);
+  }
+
+  pub fn draw_circle(&mut self, centre: (i64, i64), radius: u32, color: Rgba) {
+    self.to_mut().draw_circle(centre, radius, color);
+  }
+
+  pub fn blit_from(&mut self, source: &Bitmap, source_rect: Rect, destination: (i32, i32)) {
+    self.to_mut().blit_from(source, source_rect, destination);
+  }
+}
+
+impl<'a> From<BitmapRef<'a>> for CowBitmap<'a> {
+  fn from(bitmap: BitmapRef<'a>) -> CowBitmap<'a> {
+    CowBitmap::Borrowed(bitmap)
+  }
+}
+
+impl<'a> From<Bitmap> for CowBitmap<'a> {
+  fn from(bitmap: Bitmap) -> CowBitmap<'a> {
+    CowBitmap::Owned(bitmap)
+  }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<'a> From<Arc<Bitmap>> for CowBitmap<'a> {
+  fn from(bitmap: Arc<Bitmap>) -> CowBitmap<'a> {
+    CowBitmap::Shared(bitmap)
+  }
+}
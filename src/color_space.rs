@@ -0,0 +1,335 @@
+// Color-space declarations of V4/V5 headers, the sRGB transfer function and
+// conversions to and from linear light.
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+use crate::decoder::{read_u32, FILE_HEADER_SIZE};
+use crate::Bitmap;
+#[cfg(feature = "std")]
+use crate::{BitmapError, BitmapResult, Pixel};
+
+// bV4CSType values.
+const LCS_CALIBRATED_RGB: u32 = 0;
+const LCS_SRGB: u32 = 0x7352_4742;
+const LCS_WINDOWS_COLOR_SPACE: u32 = 0x5769_6e20;
+const PROFILE_EMBEDDED: u32 = 0x4d42_4544;
+
+// Offsets of the V4 fields within the info header.
+const CS_TYPE: usize = 56;
+const ENDPOINTS: usize = 60;
+const GAMMA_RED: usize = 96;
+const V4_HEADER_SIZE: u32 = 108;
+
+// Offsets of the V5 fields within the info header.
+const INTENT: usize = 108;
+const PROFILE_DATA: usize = 112;
+const PROFILE_SIZE: usize = 116;
+const V5_HEADER_SIZE: u32 = 124;
+
+// CIE XYZ to linear sRGB, for a D65 white point.
+#[cfg(feature = "std")]
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+  [3.240_454_2, -1.537_138_5, -0.498_531_4],
+  [-0.969_266, 1.876_010_8, 0.041_556],
+  [0.055_643_4, -0.204_025_9, 1.057_225_2]
+];
+
+// bV5Intent, the ICC rendering intent requested for gamut mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderingIntent {
+  // LCS_GM_IMAGES.
+  Perceptual,
+  // LCS_GM_GRAPHICS.
+  RelativeColorimetric,
+  // LCS_GM_BUSINESS.
+  Saturation,
+  // LCS_GM_ABS_COLORIMETRIC.
+  AbsoluteColorimetric
+}
+
+impl RenderingIntent {
+  pub const fn from_lcs(value: u32) -> Option<RenderingIntent> {
+    match value {
+      1 => Some(RenderingIntent::Saturation),
+      2 => Some(RenderingIntent::RelativeColorimetric),
+      4 => Some(RenderingIntent::Perceptual),
+      8 => Some(RenderingIntent::AbsoluteColorimetric),
+      _ => None
+    }
+  }
+
+  pub const fn to_lcs(self) -> u32 {
+    match self {
+      RenderingIntent::Saturation => 1,
+      RenderingIntent::RelativeColorimetric => 2,
+      RenderingIntent::Perceptual => 4,
+      RenderingIntent::AbsoluteColorimetric => 8
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+  if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+  if value <= 0.003_130_8 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+}
+
+impl Bitmap {
+  fn cs_type(&self) -> Option<u32> {
+    if self.header.size < V4_HEADER_SIZE {
+      return None
+    }
+
+    read_u32(&self.data, FILE_HEADER_SIZE + CS_TYPE).ok()
+  }
+
+  // Plain info headers carry no declaration and are taken to be sRGB, as
+  // are V4/V5 headers declaring sRGB or the Windows default space.
+  pub fn is_srgb(&self) -> bool {
+    matches!(self.cs_type(), None | Some(LCS_SRGB | LCS_WINDOWS_COLOR_SPACE))
+  }
+
+  // Red, green and blue tone-response gammas of a calibrated-RGB header,
+  // decoded from 16.16 fixed point.
+  pub fn header_gamma(&self) -> Option<[f32; 3]> {
+    if self.cs_type() != Some(LCS_CALIBRATED_RGB) {
+      return None
+    }
+
+    let mut gamma = [0.0; 3];
+    for (channel, value) in gamma.iter_mut().enumerate() {
+      let fixed = read_u32(&self.data, FILE_HEADER_SIZE + GAMMA_RED + channel * 4).ok()?;
+      *value = fixed as f32 / 65536.0;
+    }
+
+    Some(gamma).filter(|gamma| gamma.iter().all(|value| *value > 0.0))
+  }
+
+  // CIE XYZ coordinates of the red, green and blue primaries of a
+  // calibrated-RGB header, decoded from 2.30 fixed point. `None` when the
+  // header leaves them zero.
+  pub fn header_endpoints(&self) -> Option<[[f32; 3]; 3]> {
+    if self.cs_type() != Some(LCS_CALIBRATED_RGB) {
+      return None
+    }
+
+    let mut endpoints = [[0.0; 3]; 3];
+    for (primary, xyz) in endpoints.iter_mut().enumerate() {
+      for (axis, value) in xyz.iter_mut().enumerate() {
+        let fixed = read_u32(&self.data, FILE_HEADER_SIZE + ENDPOINTS + (primary * 3 + axis) * 4).ok()?;
+        *value = fixed as f32 / (1 << 30) as f32;
+      }
+    }
+
+    Some(endpoints).filter(|endpoints| endpoints.iter().all(|xyz| xyz[1] > 0.0))
+  }
+
+  // Rendering intent of a V5 header; `None` for older headers or values
+  // outside the four defined ones.
+  pub fn rendering_intent(&self) -> Option<RenderingIntent> {
+    if self.header.size < V5_HEADER_SIZE {
+      return None
+    }
+
+    RenderingIntent::from_lcs(read_u32(&self.data, FILE_HEADER_SIZE + INTENT).ok()?)
+  }
+
+  // ICC profile embedded in a V5 file. Its offset is counted from the start
+  // of the info header, not the file.
+  pub fn embedded_profile(&self) -> Option<&[u8]> {
+    if self.header.size < V5_HEADER_SIZE || self.cs_type() != Some(PROFILE_EMBEDDED) {
+      return None
+    }
+
+    let offset = read_u32(&self.data, FILE_HEADER_SIZE + PROFILE_DATA).ok()? as usize;
+    let size = read_u32(&self.data, FILE_HEADER_SIZE + PROFILE_SIZE).ok()? as usize;
+    let start = FILE_HEADER_SIZE.checked_add(offset)?;
+    self.data.get(start .. start.checked_add(size)?).filter(|profile| !profile.is_empty())
+  }
+}
+
+#[cfg(feature = "std")]
+impl Bitmap {
+  // Rewrites a calibrated-RGB V4/V5 header in `data` to declare sRGB, with
+  // the endpoints and gammas it no longer uses zeroed.
+  pub(crate) fn declare_srgb(&mut self) {
+    if self.cs_type() != Some(LCS_CALIBRATED_RGB) {
+      return
+    }
+
+    let start = FILE_HEADER_SIZE + CS_TYPE;
+    if let Some(fields) = self.data.get_mut(start .. start + GAMMA_RED - CS_TYPE + 12) {
+      fields[.. 4].copy_from_slice(&LCS_SRGB.to_le_bytes());
+      fields[4 ..].fill(0);
+    }
+  }
+
+  // Per-channel tables from stored values to linear light: the header's
+  // gammas for calibrated RGB, the sRGB curve for everything else.
+  fn linear_tables(&self) -> [Vec<f32>; 3] {
+    let gamma = self.header_gamma();
+    core::array::from_fn(|channel| {
+      (0 ..= 255u8)
+        .map(|value| {
+          let value = value as f32 / 255.0;
+          match gamma {
+            Some(gamma) => value.powf(gamma[channel]),
+            None => srgb_to_linear(value)
+          }
+        })
+        .collect()
+    })
+  }
+
+  // Row-major straight-alpha RGBA in linear light, four floats per pixel.
+  pub fn to_linear_f32(&self) -> Vec<f32> {
+    let [red, green, blue] = self.linear_tables();
+    self.pixels.iter()
+      .flat_map(|pixel| {
+        let color = self.straight_color(*pixel);
+        [red[color.r as usize], green[color.g as usize], blue[color.b as usize], color.a as f32 / 255.0]
+      })
+      .collect()
+  }
+
+  // Scene-referred linear RGBA with the header's transfer curve undone and
+  // calibrated primaries mapped to sRGB's. Colors outside the sRGB gamut
+  // are kept, as components below 0 or above 1; alpha is straight.
+  pub fn to_rgba_f32_linear(&self) -> Vec<f32> {
+    let mut linear = self.to_linear_f32();
+    let Some(endpoints) = self.header_endpoints() else {
+      return linear
+    };
+
+    // The endpoints are the XYZ columns of RGB to XYZ.
+    let matrix: [[f32; 3]; 3] = core::array::from_fn(|row| {
+      core::array::from_fn(|column| (0 .. 3).map(|k| XYZ_TO_SRGB[row][k] * endpoints[column][k]).sum())
+    });
+    for rgba in linear.chunks_exact_mut(4) {
+      let rgb = [rgba[0], rgba[1], rgba[2]];
+      for (out, row) in rgba.iter_mut().zip(&matrix) {
+        *out = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+      }
+    }
+    linear
+  }
+
+  // Row-major RGBA bytes re-encoded to sRGB when the header declares
+  // another curve; identical to `to_rgba8` for sRGB images.
+  pub fn to_srgb8(&self) -> Vec<u8> {
+    if self.header_gamma().is_none() {
+      return self.to_rgba8()
+    }
+
+    self.to_linear_f32().into_iter().enumerate()
+      .map(|(index, value)| {
+        let value = if index % 4 == 3 { value } else { linear_to_srgb(value) };
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+      })
+      .collect()
+  }
+
+  // 32-bpp sRGB bitmap from linear straight-alpha RGBA floats, the inverse
+  // of `to_linear_f32` for sRGB images.
+  pub fn from_linear_f32(width: u32, height: u32, linear: &[f32]) -> BitmapResult<Bitmap> {
+    if linear.len() as u64 != width as u64 * height as u64 * 4 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let pixels = linear.chunks_exact(4)
+      .map(|rgba| {
+        let channel = |value: f32| byte(linear_to_srgb(value));
+        Pixel::ABGR(channel(rgba[2]), channel(rgba[1]), channel(rgba[0]), byte(rgba[3]))
+      })
+      .collect();
+
+    Bitmap::from_parts(width, height, 32, Vec::new(), pixels)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  #[cfg(feature = "std")]
+  use alloc::vec::Vec;
+
+  use crate::encode::tests::sample;
+  #[cfg(feature = "std")]
+  use crate::Pixel;
+  use crate::{Bitmap, RenderingIntent, FILE_HEADER_SIZE};
+
+  // `sample(24, 4)` with its info header widened to a V4 one declaring
+  // `cs_type` and the 16.16 gammas `gamma`.
+  fn v4(cs_type: u32, gamma: [u32; 3]) -> Bitmap {
+    let data = sample(24, 4).encode();
+    let header_end = FILE_HEADER_SIZE + 40;
+    let mut file = data[.. header_end].to_vec();
+    file.resize(header_end + 68, 0);
+    file.extend_from_slice(&data[header_end ..]);
+
+    let len = file.len() as u32;
+    file[2 .. 6].copy_from_slice(&len.to_le_bytes());
+    file[10 .. 14].copy_from_slice(&(header_end as u32 + 68).to_le_bytes());
+    file[14 .. 18].copy_from_slice(&108u32.to_le_bytes());
+    file[FILE_HEADER_SIZE + 56 .. FILE_HEADER_SIZE + 60].copy_from_slice(&cs_type.to_le_bytes());
+    for (channel, value) in gamma.iter().enumerate() {
+      let at = FILE_HEADER_SIZE + 96 + channel * 4;
+      file[at .. at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    Bitmap::from_bytes(&file).unwrap()
+  }
+
+  #[test]
+  fn reads_v4_declarations() {
+    assert!(sample(24, 4).is_srgb());
+    assert!(v4(0x7352_4742, [0; 3]).is_srgb());
+
+    let calibrated = v4(0, [0x2_0000, 0x1_0000, 0x8000]);
+    assert!(!calibrated.is_srgb());
+    assert_eq!(calibrated.header_gamma(), Some([2.0, 1.0, 0.5]));
+    assert_eq!(calibrated.header_endpoints(), None);
+  }
+
+  #[cfg(feature = "std")]
+  #[test]
+  fn applies_header_gammas() {
+    let calibrated = v4(0, [0x2_0000, 0x1_0000, 0x8000]);
+    let color = calibrated.straight_color(calibrated.pixels[5]);
+    let linear = calibrated.to_linear_f32();
+
+    let expected = [(color.r as f32 / 255.0).powi(2), color.g as f32 / 255.0, (color.b as f32 / 255.0).sqrt(), 1.0];
+    for (value, expected) in linear[20 .. 24].iter().zip(expected) {
+      assert!((value - expected).abs() < 1e-6, "{} against {}", value, expected);
+    }
+    assert_eq!(calibrated.to_srgb8().len(), calibrated.pixels.len() * 4);
+  }
+
+  #[cfg(feature = "std")]
+  #[test]
+  fn linear_light_round_trips_srgb_bytes() {
+    let levels = (0 ..= 255u8).flat_map(|level| [level, level, level, 255 - level]).collect::<Vec<_>>();
+    let pixels = levels.chunks_exact(4).map(|rgba| Pixel::ABGR(rgba[2], rgba[1], rgba[0], rgba[3])).collect();
+    let bitmap = Bitmap::from_parts(256, 1, 32, Vec::new(), pixels).unwrap();
+
+    let linear = bitmap.to_linear_f32();
+    assert!((linear[128 * 4] - 0.2158).abs() < 1e-3);
+    assert_eq!(Bitmap::from_linear_f32(256, 1, &linear).unwrap().to_rgba8(), levels);
+    assert!(Bitmap::from_linear_f32(256, 2, &linear).is_err());
+  }
+
+  #[test]
+  fn maps_rendering_intents() {
+    for intent in [RenderingIntent::Perceptual, RenderingIntent::RelativeColorimetric, RenderingIntent::Saturation, RenderingIntent::AbsoluteColorimetric] {
+      assert_eq!(RenderingIntent::from_lcs(intent.to_lcs()), Some(intent));
+    }
+    assert_eq!(RenderingIntent::from_lcs(3), None);
+    // Only V5 headers carry one.
+    assert_eq!(v4(0, [0; 3]).rendering_intent(), None);
+  }
+}
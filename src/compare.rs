@@ -0,0 +1,157 @@
+// Pixel comparisons between bitmaps, for screenshot tests. Colors are
+// compared straight, as encoders write them, so a bitmap matches its own
+// premultiplied copy up to rounding; `same_image` premultiplies both sides
+// instead so fully transparent pixels match whatever their color.
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "perceptual")]
+use crate::{DeltaE, Lab};
+use crate::{AlphaMode, Bitmap, LumaWeights, Pixel, Rgba};
+
+// Outcome of `compare`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompareResult {
+  // Every pixel is within the tolerance and the sizes agree.
+  pub matches: bool,
+  // Whether both images have the same width and height; if not, every
+  // pixel counts as differing.
+  pub same_size: bool,
+  pub differing_pixels: usize,
+  // Largest difference seen in any channel, alpha included.
+  pub max_delta: u8
+}
+
+// Largest per-channel difference between two colors.
+pub(crate) fn channel_delta(a: Rgba, b: Rgba) -> u8 {
+  [a.r.abs_diff(b.r), a.g.abs_diff(b.g), a.b.abs_diff(b.b), a.a.abs_diff(b.a)]
+    .into_iter()
+    .max()
+    .unwrap_or(0)
+}
+
+impl Bitmap {
+  // Compares resolved RGBA colors, so images of different depths or
+  // palettes match when they render the same. Pixels differ when any
+  // channel is more than `tolerance` apart.
+  pub fn compare(&self, other: &Bitmap, tolerance: u8) -> CompareResult {
+    if (self.width(), self.height()) != (other.width(), other.height()) {
+      return CompareResult {
+        matches: false,
+        same_size: false,
+        differing_pixels: self.pixels.len().max(other.pixels.len()),
+        max_delta: 255
+      }
+    }
+
+    let (differing_pixels, max_delta) = self.pixels.iter()
+      .zip(&other.pixels)
+      .map(|(a, b)| channel_delta(self.straight_color(*a), other.straight_color(*b)))
+      .fold((0, 0), |(count, max), delta| (count + (delta > tolerance) as usize, max.max(delta)));
+
+    CompareResult { matches: differing_pixels == 0, same_size: true, differing_pixels, max_delta }
+  }
+
+  // As `compare`, but pixels differ when their colors are more than
+  // `tolerance` delta E apart under `formula`, or their alpha differs by
+  // more than the same number of levels. `max_delta` is still the largest
+  // channel difference.
+  #[cfg(feature = "perceptual")]
+  pub fn compare_perceptual(&self, other: &Bitmap, tolerance: f32, formula: DeltaE) -> CompareResult {
+    if (self.width(), self.height()) != (other.width(), other.height()) {
+      return self.compare(other, 0)
+    }
+
+    let (differing_pixels, max_delta) = self.pixels.iter()
+      .zip(&other.pixels)
+      .map(|(a, b)| (self.straight_color(*a), other.straight_color(*b)))
+      .fold((0, 0), |(count, max), (a, b)| {
+        let differs = formula.difference(Lab::from_rgba(a), Lab::from_rgba(b)) > tolerance || a.a.abs_diff(b.a) as f32 > tolerance;
+        (count + differs as usize, max.max(channel_delta(a, b)))
+      });
+
+    CompareResult { matches: differing_pixels == 0, same_size: true, differing_pixels, max_delta }
+  }
+
+  // Row-major first pixel where the images differ by more than `tolerance`,
+  // within the area both cover.
+  pub fn first_difference(&self, other: &Bitmap, tolerance: u8) -> Option<(u32, u32)> {
+    let (width, height) = (self.width().min(other.width()), self.height().min(other.height()));
+    (0 .. height)
+      .flat_map(|y| (0 .. width).map(move |x| (x, y)))
+      .find(|(x, y)| {
+        let (a, b) = (self.pixel(*x, *y), other.pixel(*x, *y));
+        a.zip(b).is_some_and(|(a, b)| channel_delta(self.straight_color(*a), other.straight_color(*b)) > tolerance)
+      })
+  }
+
+  // Whether both images look the same, whatever their depth, palette, row
+  // order or padding. Colors are compared premultiplied, so transparent
+  // pixels match whatever their color, and a 32-bpp image whose alpha
+  // bytes are all zero counts as opaque, the way readers treat the unused
+  // fourth byte of plain 32-bpp files.
+  pub fn same_image(&self, other: &Bitmap) -> bool {
+    (self.width(), self.height()) == (other.width(), other.height())
+      && self.rendered_colors().eq(other.rendered_colors())
+  }
+
+  fn rendered_colors(&self) -> impl Iterator<Item = Rgba> + '_ {
+    let unused_alpha = self.pixels.iter().all(|pixel| matches!(pixel, Pixel::ABGR(_, _, _, 0)));
+    let scale = |value: u8, alpha: u8| ((value as u16 * alpha as u16 + 127) / 255) as u8;
+
+    self.pixels.iter().map(move |pixel| {
+      let mut color = self.color(*pixel);
+      if unused_alpha {
+        color.a = 255;
+      } else if self.alpha_mode == AlphaMode::Straight {
+        color = Rgba::new(scale(color.r, color.a), scale(color.g, color.a), scale(color.b, color.a), color.a);
+      }
+      color
+    })
+  }
+
+  // 24-bpp picture of where `other` differs: matching pixels as this image
+  // in faded grayscale, differing ones solid red. Covers the larger of
+  // the two sizes, with pixels missing from either image marked as differing.
+  pub fn diff_image(&self, other: &Bitmap) -> Bitmap {
+    let (width, height) = (self.width().max(other.width()), self.height().max(other.height()));
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+    for y in 0 .. height {
+      for x in 0 .. width {
+        let pixel = match (self.pixel(x, y), other.pixel(x, y)) {
+          (Some(a), Some(b)) if channel_delta(self.straight_color(*a), other.straight_color(*b)) == 0 => {
+            let level = 191 + LumaWeights::REC709.luma(self.straight_color(*a)) / 4;
+            Pixel::BGR(level, level, level)
+          }
+          _ => Pixel::BGR(0, 0, 255)
+        };
+        pixels.push(pixel);
+      }
+    }
+
+    Bitmap::assemble(width, height as i32, 24, Vec::new(), pixels)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  use crate::{hamming_distance, Bitmap, Pixel};
+
+  #[test]
+  fn premultiplied_copies_compare_straight() {
+    let straight = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(200, 100, 50, 128), Pixel::ABGR(10, 20, 30, 255)]).unwrap();
+    let mut premultiplied = straight.clone();
+    premultiplied.premultiply_alpha();
+
+    assert!(straight.compare(&premultiplied, 1).matches);
+    assert_eq!(straight.first_difference(&premultiplied, 1), None);
+    assert_eq!(straight.histogram().alpha, premultiplied.histogram().alpha);
+    // Blue 200 at half alpha is stored as 100 once premultiplied.
+    assert_eq!(premultiplied.histogram().blue[100], 0);
+    assert_eq!(hamming_distance(straight.dhash(), premultiplied.dhash()), 0);
+  }
+}
@@ -0,0 +1,293 @@
+// Conversions from decoded pixels into packed buffers for displays and GPUs.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapError, BitmapResult, Decoder, PackedSink, Rgba};
+
+// 4x4 ordered-dither thresholds, in sixteenths of a quantization step.
+pub(crate) const BAYER_4X4: [[u8; 4]; 4] = [
+  [0, 8, 2, 10],
+  [12, 4, 14, 6],
+  [3, 11, 1, 9],
+  [15, 7, 13, 5]
+];
+
+// Byte order of the four channels in packed 32-bit output: `Rgba` for web
+// canvases and most GPU formats, `Bgra` for Win32 DIBs and `Bgra8Unorm`
+// swapchains, `Argb` and `Abgr` for some video and Java APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelOrder {
+  #[default]
+  Rgba,
+  Bgra,
+  Argb,
+  Abgr
+}
+
+impl ChannelOrder {
+  pub const fn arrange(self, color: Rgba) -> [u8; 4] {
+    let Rgba { r, g, b, a } = color;
+    match self {
+      ChannelOrder::Rgba => [r, g, b, a],
+      ChannelOrder::Bgra => [b, g, r, a],
+      ChannelOrder::Argb => [a, r, g, b],
+      ChannelOrder::Abgr => [a, b, g, r]
+    }
+  }
+}
+
+impl Bitmap {
+  // Row-major colors with palettes resolved; castable to bytes with the `bytemuck` feature.
+  pub fn to_rgba(&self) -> Vec<Rgba> {
+    self.pixels.iter().map(|pixel| self.straight_color(*pixel)).collect()
+  }
+
+  // Row-major RGBA bytes with palettes resolved. With the `icc` feature,
+  // colors are mapped from an embedded profile to sRGB using the header's
+  // rendering intent.
+  pub fn to_rgba8(&self) -> Vec<u8> {
+    #[allow(unused_mut)]
+    let mut rgba: Vec<u8> = self.pixels
+      .iter()
+      .flat_map(|pixel| {
+        let color = self.straight_color(*pixel);
+        [color.r, color.g, color.b, color.a]
+      })
+      .collect();
+
+    #[cfg(feature = "icc")]
+    self.apply_profile(&mut rgba);
+    rgba
+  }
+
+  // As `to_rgba8`, with the channels of each pixel in `order`.
+  pub fn to_bytes(&self, order: ChannelOrder) -> Vec<u8> {
+    let mut bytes = self.to_rgba8();
+    if order != ChannelOrder::Rgba {
+      for pixel in bytes.chunks_exact_mut(4) {
+        let arranged = order.arrange(Rgba::new(pixel[0], pixel[1], pixel[2], pixel[3]));
+        pixel.copy_from_slice(&arranged);
+      }
+    }
+    bytes
+  }
+
+  // Row-major RGB bytes with palettes resolved and alpha dropped.
+  pub fn to_rgb8(&self) -> Vec<u8> {
+    self.pixels
+      .iter()
+      .flat_map(|pixel| {
+        let color = self.straight_color(*pixel);
+        [color.r, color.g, color.b]
+      })
+      .collect()
+  }
+
+  // Row-major 0xAARRGGBB words, the framebuffer layout of minifb and
+  // softbuffer (which ignore the alpha byte).
+  pub fn to_argb32_u32(&self) -> Vec<u32> {
+    self.pixels
+      .iter()
+      .map(|pixel| {
+        let color = self.straight_color(*pixel);
+        u32::from_be_bytes([color.a, color.r, color.g, color.b])
+      })
+      .collect()
+  }
+}
+
+impl Decoder<'_> {
+  // Writes RGBA bytes straight into `framebuffer`, row `y` starting at
+  // `y * dst_stride`, without decoding into pixels first. Bytes past each
+  // row are left alone. Unlike `Bitmap::to_rgba8` no ICC profile is applied.
+  pub fn decode_rgba_into(&self, framebuffer: &mut [u8], dst_stride: usize) -> BitmapResult<()> {
+    self.decode_bytes_into(framebuffer, dst_stride, ChannelOrder::Rgba)
+  }
+
+  // As `decode_rgba_into`, with the channels of each pixel in `order`.
+  pub fn decode_bytes_into(&self, framebuffer: &mut [u8], dst_stride: usize, order: ChannelOrder) -> BitmapResult<()> {
+    self.decode_to_sink(&mut PackedSink { buffer: framebuffer, stride: dst_stride, order })
+  }
+}
+
+impl Bitmap {
+  // Decodes the file in `source` into `framebuffer` as `Decoder::decode_rgba_into`
+  // does, returning the image's width and height.
+  pub fn decode_rgba_into(source: &[u8], framebuffer: &mut [u8], dst_stride: usize) -> BitmapResult<(u32, u32)> {
+    let decoder = Decoder::new(source)?;
+    decoder.decode_rgba_into(framebuffer, dst_stride)?;
+    Ok((decoder.width(), decoder.height()))
+  }
+}
+
+// Settings for packing pixels into 16-bit RGB565 words.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb565Options {
+  // Swap the two bytes of every word, for peripherals that shift out the low byte first.
+  pub swap_bytes: bool,
+  // Apply ordered dithering to hide banding from the dropped low bits.
+  pub dither: bool
+}
+
+// Packs one color, optionally nudged by the dither threshold for pixel (x, y).
+pub fn rgb565(color: Rgba, x: u32, y: u32, dither: bool) -> u16 {
+  let threshold = if dither { BAYER_4X4[y as usize % 4][x as usize % 4] as u16 } else { 0 };
+  let quantize = |value: u8, bits: u32| {
+    let step = 1u16 << (8 - bits);
+    (value as u16 + threshold * step / 16).min(255) >> (8 - bits)
+  };
+
+  (quantize(color.r, 5) << 11) | (quantize(color.g, 6) << 5) | quantize(color.b, 5)
+}
+
+impl Bitmap {
+  // Row-major RGB565 words for the whole image; alpha is discarded.
+  pub fn to_rgb565(&self, options: Rgb565Options) -> Vec<u16> {
+    let width = self.width().max(1);
+
+    self.pixels
+      .iter()
+      .enumerate()
+      .map(|(index, pixel)| {
+        let (x, y) = (index as u32 % width, index as u32 / width);
+        let word = rgb565(self.straight_color(*pixel), x, y, options.dither);
+        if options.swap_bytes { word.swap_bytes() } else { word }
+      })
+      .collect()
+  }
+
+  // RGB565 serialized low byte first.
+  pub fn to_rgb565_le(&self) -> Vec<u8> {
+    self.to_rgb565(Rgb565Options::default())
+      .iter()
+      .flat_map(|word| word.to_le_bytes())
+      .collect()
+  }
+
+  // RGB565 serialized high byte first, the order most SPI TFT controllers expect.
+  pub fn to_rgb565_be(&self) -> Vec<u8> {
+    self.to_rgb565(Rgb565Options::default())
+      .iter()
+      .flat_map(|word| word.to_be_bytes())
+      .collect()
+  }
+}
+
+// Placement rules for packed rows, in bytes; zero means no constraint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowLayout {
+  // Alignment of the first byte of every row.
+  pub align: usize,
+  // The stride is rounded up to a multiple of this, e.g. a DMA burst size.
+  pub stride_multiple: usize
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+  if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl RowLayout {
+  // Smallest stride for `row_len` bytes that satisfies both constraints;
+  // `None` if it doesn't fit in a `usize`.
+  pub fn stride(&self, row_len: usize) -> Option<usize> {
+    let (align, multiple) = (self.align.max(1), self.stride_multiple.max(1));
+    let unit = (align / gcd(align, multiple)).checked_mul(multiple)?;
+    row_len.div_ceil(unit).checked_mul(unit)
+  }
+}
+
+// Packed image whose rows start on `RowLayout` boundaries, ready to hand to a DMA engine.
+#[derive(Debug)]
+pub struct AlignedBuffer {
+  storage: Vec<u8>,
+  start: usize,
+  pub width: u32,
+  pub height: u32,
+  pub stride: usize
+}
+
+impl AlignedBuffer {
+  // Zeroed buffer; over-allocates so the aligned window can be carved out
+  // of one `Vec`. Sizes past `usize` are a `DimensionOverflow`.
+  pub fn new(width: u32, height: u32, bytes_per_pixel: usize, layout: RowLayout) -> BitmapResult<AlignedBuffer> {
+    let align = layout.align.max(1);
+    let stride = (width as usize).checked_mul(bytes_per_pixel)
+      .and_then(|row_len| layout.stride(row_len))
+      .ok_or(BitmapError::DimensionOverflow)?;
+    let len = stride.checked_mul(height as usize)
+      .and_then(|len| len.checked_add(align - 1))
+      .ok_or(BitmapError::DimensionOverflow)?;
+    let storage = alloc::vec![0; len];
+    let start = (align - storage.as_ptr() as usize % align) % align;
+
+    Ok(AlignedBuffer { storage, start, width, height, stride })
+  }
+
+  // The whole image including row padding, starting at the aligned address.
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.storage[self.start .. self.start + self.stride * self.height as usize]
+  }
+
+  pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+    let len = self.stride * self.height as usize;
+    &mut self.storage[self.start .. self.start + len]
+  }
+
+  pub fn row(&self, y: u32) -> Option<&[u8]> {
+    let at = y as usize * self.stride;
+    self.as_bytes().get(at .. at + self.stride)
+  }
+
+  pub fn row_mut(&mut self, y: u32) -> Option<&mut [u8]> {
+    let (at, stride) = (y as usize * self.stride, self.stride);
+    self.as_bytes_mut().get_mut(at .. at + stride)
+  }
+}
+
+impl Bitmap {
+  // Packs every pixel through `pack`, which fills `bytes_per_pixel` output bytes.
+  pub(crate) fn pack_aligned<F>(&self, bytes_per_pixel: usize, layout: RowLayout, pack: F) -> BitmapResult<AlignedBuffer>
+  where
+    F: Fn(Rgba, u32, u32, &mut [u8])
+  {
+    let mut buffer = AlignedBuffer::new(self.width(), self.height(), bytes_per_pixel, layout)?;
+
+    for y in 0 .. self.height() {
+      let row = buffer.row_mut(y).unwrap();
+      for (x, out) in row.chunks_exact_mut(bytes_per_pixel).take(self.width() as usize).enumerate() {
+        if let Some(pixel) = self.pixel(x as u32, y) {
+          pack(self.straight_color(*pixel), x as u32, y, out);
+        }
+      }
+    }
+
+    Ok(buffer)
+  }
+
+  // RGB565 rows laid out per `layout`, serialized high byte first if `big_endian`.
+  pub fn to_rgb565_aligned(&self, options: Rgb565Options, big_endian: bool, layout: RowLayout) -> BitmapResult<AlignedBuffer> {
+    self.pack_aligned(2, layout, |color, x, y, out| {
+      let word = rgb565(color, x, y, options.dither);
+      let word = if options.swap_bytes { word.swap_bytes() } else { word };
+      out.copy_from_slice(&if big_endian { word.to_be_bytes() } else { word.to_le_bytes() });
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{AlignedBuffer, BitmapError, RowLayout};
+
+  #[test]
+  fn aligns_rows_and_rejects_overflow() {
+    let layout = RowLayout { align: 16, stride_multiple: 12 };
+    let buffer = AlignedBuffer::new(5, 3, 2, layout).unwrap();
+    assert_eq!(buffer.stride, 48);
+    assert_eq!(buffer.as_bytes().as_ptr() as usize % 16, 0);
+    assert_eq!(buffer.as_bytes().len(), 48 * 3);
+
+    assert!(matches!(AlignedBuffer::new(u32::MAX, u32::MAX, usize::MAX, layout), Err(BitmapError::DimensionOverflow)));
+    assert!(matches!(AlignedBuffer::new(1, 2, 1, RowLayout { align: 1, stride_multiple: usize::MAX }), Err(BitmapError::DimensionOverflow)));
+  }
+}
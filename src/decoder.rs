@@ -0,0 +1,622 @@
+// Allocation-free header parsing and scanline decoding over a borrowed slice.
+
+use crate::masks::read_masks;
+use crate::{BitmapError, BitmapResult, BitmapV5Header, ChannelMasks, ChannelScaling, CompressionMethod, ErrorContext, FileHeader, HeaderVersion, Pixel, Rgba};
+
+// Size of the BITMAPFILEHEADER preceding the info header.
+pub const FILE_HEADER_SIZE: usize = 14;
+
+// Offset of `bV5Reserved` within a 124-byte info header.
+const V5_RESERVED: usize = 120;
+
+// Little-endian field readers, failing on truncated input rather than panicking.
+pub(crate) fn read_u16(buf: &[u8], at: usize) -> BitmapResult<u16> {
+  match buf.get(at .. at + 2) {
+    Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+    None => Err(BitmapError::TruncatedHeader)
+  }
+}
+
+pub(crate) fn read_u32(buf: &[u8], at: usize) -> BitmapResult<u32> {
+  match buf.get(at .. at + 4) {
+    Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+    None => Err(BitmapError::TruncatedHeader)
+  }
+}
+
+pub(crate) fn read_i32(buf: &[u8], at: usize) -> BitmapResult<i32> {
+  read_u32(buf, at).map(|n| n as i32)
+}
+
+// Bytes per stored row of `width` pixels, padded to a 4-byte boundary;
+// `None` when that doesn't fit in a `usize`. Callers that can fail report
+// it as `DimensionOverflow`.
+pub(crate) const fn row_stride(width: u32, bpp: u16) -> Option<usize> {
+  match (width as usize).checked_mul(bpp as usize) {
+    Some(bits) => Some(bits.div_ceil(32) * 4),
+    None => None
+  }
+}
+
+// Dimensions readable in const contexts, e.g. straight from an `include_bytes!` asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageInfo {
+  pub width: u32,
+  pub height: u32,
+  pub bpp: u16,
+  pub top_down: bool
+}
+
+impl ImageInfo {
+  // Reads the file and info headers; `None` for anything that isn't a plausible bitmap.
+  pub const fn parse(data: &[u8]) -> Option<ImageInfo> {
+    if data.len() < 30 || data[0] != b'B' || data[1] != b'M' {
+      return None
+    }
+
+    let header_size = u32::from_le_bytes([data[14], data[15], data[16], data[17]]);
+    let width = i32::from_le_bytes([data[18], data[19], data[20], data[21]]);
+    let height = i32::from_le_bytes([data[22], data[23], data[24], data[25]]);
+    let bpp = u16::from_le_bytes([data[28], data[29]]);
+
+    if header_size < 40 || width <= 0 || height == 0 || bpp == 0 {
+      return None
+    }
+
+    match row_stride(width as u32, bpp) {
+      Some(stride) if stride.checked_mul(height.unsigned_abs() as usize).is_some() => {}
+      _ => return None
+    }
+
+    Some(ImageInfo {
+      width: width as u32,
+      height: height.unsigned_abs(),
+      bpp,
+      top_down: height < 0
+    })
+  }
+
+  // As `parse`, but fails const evaluation (or panics at runtime) on bad input.
+  pub const fn expect(data: &[u8]) -> ImageInfo {
+    match ImageInfo::parse(data) {
+      Some(info) => info,
+      None => panic!("not a supported bitmap")
+    }
+  }
+
+  pub const fn pixel_count(&self) -> usize {
+    self.width as usize * self.height as usize
+  }
+
+  // Saturates for hand-built infos too large to address; `parse` never
+  // returns those.
+  pub const fn stride(&self) -> usize {
+    match row_stride(self.width, self.bpp) {
+      Some(stride) => stride,
+      None => usize::MAX
+    }
+  }
+
+  // Size of the uncompressed pixel array, handy for sizing static buffers.
+  pub const fn image_size(&self) -> usize {
+    self.stride().saturating_mul(self.height as usize)
+  }
+}
+
+// Parsed headers plus a borrowed view of the file; owns no heap memory.
+#[derive(Debug, Clone)]
+pub struct Decoder<'a> {
+  pub(crate) data: &'a [u8],
+  pub file_header: FileHeader,
+  pub header: BitmapV5Header,
+  // Bitfield masks when they differ from plain BGRA.
+  pub(crate) masks: Option<ChannelMasks>,
+  // How mask channels narrower than 8 bits are widened.
+  pub scaling: ChannelScaling
+}
+
+impl<'a> Decoder<'a> {
+  // Parses and validates the headers, checking the pixel array fits in `data`.
+  pub fn new(data: &'a [u8]) -> BitmapResult<Decoder<'a>> {
+    let decoder = Decoder::headers(data)?;
+
+    // Compressed rows can't be found without reading the whole stream;
+    // `Decoder::decompress` expands them into a file this accepts.
+    if decoder.is_compressed() {
+      return Err(BitmapError::UnsupportedCompression)
+    }
+
+    if decoder.image_end() > data.len() {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    Ok(decoder)
+  }
+
+  // Parses and validates only the headers, for data whose pixel array may
+  // still be arriving; rows past the end of `data` decode as `None`.
+  pub fn headers(data: &'a [u8]) -> BitmapResult<Decoder<'a>> {
+    // magic number check
+    if data.get(0..2) != Some(&b"BM"[..]) {
+      return Err(BitmapError::BadMagic)
+    }
+
+    let file_header = FileHeader::parse(data).map_err(|err| err.context(ErrorContext::FileHeader))?;
+    let header = read_info_header(data).map_err(|err| err.context(ErrorContext::InfoHeader))?;
+    let masks = read_custom_masks(data, &header).map_err(|err| err.context(ErrorContext::InfoHeader))?;
+    let decoder = Decoder { data, file_header, header, masks, scaling: ChannelScaling::default() };
+
+    match decoder.header.bpp {
+      8 | 24 | 32 => {}
+      #[cfg(feature = "low-depth")]
+      1 | 4 => {}
+      _ => return Err(BitmapError::UnsupportedBitsPerPixel)
+    }
+
+    match (&decoder.header.method, decoder.header.bpp) {
+      (CompressionMethod::None, _) => {}
+      // BI_BITFIELDS and BI_ALPHABITFIELDS, with masks after a 40-byte
+      // header or inside a V2 or later one.
+      #[cfg(feature = "bitfields")]
+      (CompressionMethod::Huffman1D | CompressionMethod::Other(6), 32) => {}
+      #[cfg(feature = "rle")]
+      (CompressionMethod::Rle8Bit, 8) => {}
+      #[cfg(all(feature = "rle", feature = "low-depth"))]
+      (CompressionMethod::Rle4Bit, 4) => {}
+      _ => return Err(BitmapError::UnsupportedCompression)
+    }
+
+    if decoder.header.pix_width <= 0 || decoder.header.pix_height == 0 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    row_stride(decoder.width(), decoder.header.bpp)
+      .and_then(|stride| stride.checked_mul(decoder.height() as usize))
+      .and_then(|len| len.checked_add(file_header.pixel_offset as usize))
+      .ok_or(BitmapError::DimensionOverflow)?;
+
+    trace_event!(
+      size = decoder.header.size,
+      width = decoder.header.pix_width,
+      height = decoder.header.pix_height,
+      bpp = decoder.header.bpp,
+      method = ?decoder.header.method,
+      offset = file_header.pixel_offset,
+      "parsed headers"
+    );
+    Ok(decoder)
+  }
+
+  // Offset just past the pixel array, i.e. how much of the file decoding
+  // needs. For RLE files that's the stream length from `biSizeImage`, or
+  // the rest of the file when it's left at 0.
+  pub fn image_end(&self) -> usize {
+    let offset = self.file_header.pixel_offset as usize;
+    if self.is_compressed() {
+      let len = if self.header.size >= 24 { read_u32(self.data, 34) } else { Ok(0) };
+      return match len {
+        Ok(len) if len > 0 => offset.saturating_add(len as usize),
+        _ => self.data.len().max(offset)
+      }
+    }
+    offset.saturating_add(self.stride().saturating_mul(self.height() as usize))
+  }
+
+  // Whether the pixel array is an RLE4 or RLE8 stream rather than rows.
+  pub fn is_compressed(&self) -> bool {
+    matches!(self.header.method, CompressionMethod::Rle8Bit | CompressionMethod::Rle4Bit)
+  }
+
+  pub fn width(&self) -> u32 {
+    self.header.pix_width as u32
+  }
+
+  pub fn height(&self) -> u32 {
+    self.header.pix_height.unsigned_abs()
+  }
+
+  // Negative heights mark rows stored first-to-last instead of bottom-up.
+  pub fn is_top_down(&self) -> bool {
+    self.header.pix_height < 0
+  }
+
+  // Bytes per stored row, including the padding to a 4-byte boundary.
+  // `headers` rejects files where this overflows, so it only saturates
+  // after `header` is edited by hand.
+  pub fn stride(&self) -> usize {
+    row_stride(self.width(), self.header.bpp).unwrap_or(usize::MAX)
+  }
+
+  // Number of palette entries following the info header.
+  pub fn palette_len(&self) -> usize {
+    match (self.header.colors, self.header.bpp) {
+      // OS/2 writers size the palette to fit the gap before the pixels
+      // rather than filling in a count.
+      (0, bpp) if bpp <= 8 && HeaderVersion::from_size(self.header.size).is_os2() => {
+        let entry = if self.header.size == 12 { 3 } else { 4 };
+        let gap = (self.file_header.pixel_offset as usize).saturating_sub(FILE_HEADER_SIZE + self.header.size as usize);
+        (gap / entry).min(1 << bpp)
+      }
+      (0, bpp) if bpp <= 8 => 1 << bpp,
+      (n, _) => n as usize
+    }
+  }
+
+  // Raw RGBQUAD (blue, green, red, reserved) for a palette index, if present.
+  pub fn palette_entry(&self, index: u8) -> Option<[u8; 4]> {
+    if index as usize >= self.palette_len() {
+      return None
+    }
+
+    // Core headers are followed by 3-byte RGBTRIPLEs.
+    if self.header.size == 12 {
+      let at = FILE_HEADER_SIZE + 12 + index as usize * 3;
+      return self.data.get(at .. at + 3).map(|bytes| [bytes[0], bytes[1], bytes[2], 0])
+    }
+
+    let at = FILE_HEADER_SIZE + self.header.size as usize + index as usize * 4;
+    self.data.get(at .. at + 4).map(|bytes| [bytes[0], bytes[1], bytes[2], bytes[3]])
+  }
+
+  // Whether any palette entry has a non-zero fourth byte, i.e. may carry
+  // alpha for readers that use it that way.
+  pub fn palette_has_alpha(&self) -> bool {
+    (0 .. self.palette_len().min(256)).map_while(|index| self.palette_entry(index as u8)).any(|quad| quad[3] != 0)
+  }
+
+  // Resolves a pixel through the palette; missing entries come out opaque black.
+  pub fn color(&self, pixel: Pixel) -> Rgba {
+    match pixel {
+      Pixel::ABGR(b, g, r, a) => Rgba { r, g, b, a },
+      Pixel::BGR(b, g, r) => Rgba { r, g, b, a: 255 },
+      Pixel::PaletteColor(index) => self.palette_entry(index).map(Rgba::from_rgbquad).unwrap_or(Rgba::BLACK)
+    }
+  }
+
+  // Stored bytes of row `y`, counted from the top of the image. Compressed
+  // files have none.
+  pub fn row_bytes(&self, y: u32) -> Option<&'a [u8]> {
+    if y >= self.height() || self.is_compressed() {
+      return None
+    }
+
+    let stored = if self.is_top_down() { y } else { self.height() - 1 - y };
+    let at = self.file_header.pixel_offset as usize + stored as usize * self.stride();
+    self.data.get(at .. at + self.stride())
+  }
+
+  // Decodes a single pixel without touching any other row.
+  pub fn pixel(&self, x: u32, y: u32) -> Option<Pixel> {
+    if x >= self.width() {
+      return None
+    }
+
+    self.row_bytes(y).map(|row| self.decode(row, x as usize))
+  }
+
+  // Pixel `x` of a stored row, through the masks if there are any.
+  pub(crate) fn decode(&self, row: &[u8], x: usize) -> Pixel {
+    match self.masks {
+      Some(masks) => {
+        let value = u32::from_le_bytes([row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]]);
+        let color = masks.color(value, self.scaling);
+        Pixel::ABGR(color.b, color.g, color.r, color.a)
+      }
+      None => decode_pixel(row, x, self.header.bpp)
+    }
+  }
+
+  // Decodes row `y` into the front of `buf`, returning the filled part.
+  pub fn read_row<'b>(&self, y: u32, buf: &'b mut [Pixel]) -> BitmapResult<&'b [Pixel]> {
+    let width = self.width() as usize;
+    let row = self.row_bytes(y).ok_or_else(|| BitmapError::InvalidBitmapData.context(ErrorContext::Row(y)))?;
+    let out = buf.get_mut(.. width).ok_or(BitmapError::BufferTooSmall)?;
+
+    for (x, pixel) in out.iter_mut().enumerate() {
+      *pixel = self.decode(row, x);
+    }
+
+    Ok(out)
+  }
+
+  // Decodes the whole image into the front of `out`, which must hold `width * height` pixels.
+  pub fn decode_into<'b>(&self, out: &'b mut [Pixel]) -> BitmapResult<&'b [Pixel]> {
+    let count = self.width() as usize * self.height() as usize;
+    let out = out.get_mut(.. count).ok_or(BitmapError::BufferTooSmall)?;
+
+    for (slot, pixel) in out.iter_mut().zip(self.pixels()) {
+      *slot = pixel;
+    }
+
+    Ok(out)
+  }
+
+  // Decodes into a fixed-capacity vector, failing rather than truncating when `N` is too small.
+  #[cfg(feature = "heapless")]
+  pub fn decode_heapless<const N: usize>(&self) -> BitmapResult<heapless::Vec<Pixel, N>> {
+    if self.width() as usize * self.height() as usize > N {
+      return Err(BitmapError::BufferTooSmall)
+    }
+
+    let mut out = heapless::Vec::new();
+
+    for pixel in self.pixels() {
+      out.push(pixel).map_err(|_| BitmapError::BufferTooSmall)?;
+    }
+
+    Ok(out)
+  }
+
+  // Every pixel in top-to-bottom, left-to-right order, decoded on the fly.
+  pub fn pixels(&self) -> Pixels<'a> {
+    Pixels { decoder: self.clone(), x: 0, y: 0 }
+  }
+
+  // Row-at-a-time decoding reusing a caller buffer of at least `width` pixels.
+  pub fn scanlines<'b>(&self, buf: &'b mut [Pixel]) -> Scanlines<'a, 'b> {
+    Scanlines { decoder: self.clone(), buf, y: 0 }
+  }
+}
+
+pub(crate) fn decode_pixel(row: &[u8], x: usize, bpp: u16) -> Pixel {
+  match bpp {
+    #[cfg(feature = "low-depth")]
+    1 => Pixel::PaletteColor((row[x / 8] >> (7 - x % 8)) & 0x1),
+    #[cfg(feature = "low-depth")]
+    4 => Pixel::PaletteColor((row[x / 2] >> (4 - x % 2 * 4)) & 0xF),
+    8 => Pixel::PaletteColor(row[x]),
+    24 => Pixel::BGR(row[x * 3], row[x * 3 + 1], row[x * 3 + 2]),
+    _ => Pixel::ABGR(row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3])
+  }
+}
+
+// The info header fields the decoder keeps.
+fn read_info_header(data: &[u8]) -> BitmapResult<BitmapV5Header> {
+  let header_size = read_u32(data, 14)?;
+
+  #[cfg(feature = "os2")]
+  match HeaderVersion::from_size(header_size) {
+    HeaderVersion::Core => return read_core_header(data),
+    HeaderVersion::Os2 => return read_os2_header(data, header_size),
+    _ => {}
+  }
+
+  // Only the 40-byte BITMAPINFOHEADER layout and its extensions are understood.
+  if header_size < 40 {
+    return Err(BitmapError::InvalidBitmapData)
+  }
+
+  let method = match read_u32(data, 30)? {
+    0 => CompressionMethod::None,
+    1 => CompressionMethod::Rle8Bit,
+    2 => CompressionMethod::Rle4Bit,
+    3 => CompressionMethod::Huffman1D,
+    4 => CompressionMethod::Jpeg,
+    5 => CompressionMethod::Png,
+    n => CompressionMethod::Other(n)
+  };
+
+  Ok(BitmapV5Header {
+    size: header_size,
+    pix_width: read_i32(data, 18)?,
+    pix_height: read_i32(data, 22)?,
+    bpp: read_u16(data, 28)?,
+    method,
+    x_pels_per_meter: read_i32(data, 38)?,
+    y_pels_per_meter: read_i32(data, 42)?,
+    colors: read_u32(data, 46)?,
+    important_colors: read_u32(data, 50)?,
+    reserved: if header_size >= 124 { read_u32(data, FILE_HEADER_SIZE + V5_RESERVED)? } else { 0 }
+  })
+}
+
+// The 12-byte BITMAPCOREHEADER of Windows 2.x and OS/2 1.x: 16-bit
+// dimensions, no compression, and a palette of RGBTRIPLEs.
+#[cfg(feature = "os2")]
+fn read_core_header(data: &[u8]) -> BitmapResult<BitmapV5Header> {
+  Ok(BitmapV5Header {
+    size: 12,
+    pix_width: read_u16(data, 18)? as i32,
+    pix_height: read_u16(data, 20)? as i32,
+    bpp: read_u16(data, 24)?,
+    method: CompressionMethod::None,
+    x_pels_per_meter: 0,
+    y_pels_per_meter: 0,
+    colors: 0,
+    important_colors: 0,
+    reserved: 0
+  })
+}
+
+// OS/2 2.x BITMAPINFOHEADER2, laid out like BITMAPINFOHEADER but cut short
+// anywhere past the depth; missing fields read as 0. Its methods 3 and 4
+// are Huffman and RLE24 rather than bitfields and JPEG, neither of which
+// is supported.
+#[cfg(feature = "os2")]
+fn read_os2_header(data: &[u8], header_size: u32) -> BitmapResult<BitmapV5Header> {
+  let end = FILE_HEADER_SIZE + header_size as usize;
+  let field = |at: usize| if at + 4 <= end { read_u32(data, at) } else { Ok(0) };
+
+  let method = match field(30)? {
+    0 => CompressionMethod::None,
+    1 => CompressionMethod::Rle8Bit,
+    2 => CompressionMethod::Rle4Bit,
+    _ => return Err(BitmapError::UnsupportedCompression)
+  };
+
+  Ok(BitmapV5Header {
+    size: header_size,
+    pix_width: read_i32(data, 18)?,
+    pix_height: read_i32(data, 22)?,
+    bpp: read_u16(data, 28)?,
+    method,
+    x_pels_per_meter: field(38)? as i32,
+    y_pels_per_meter: field(42)? as i32,
+    colors: field(46)?,
+    important_colors: field(50)?,
+    reserved: 0
+  })
+}
+
+// Masks of a 32-bpp bitfields image, unless they only say the channels are
+// in BGRA order, which `decode_pixel` reads directly. The fourth byte is
+// then kept as alpha even if no alpha mask is declared.
+fn read_custom_masks(data: &[u8], header: &BitmapV5Header) -> BitmapResult<Option<ChannelMasks>> {
+  let method = match header.method {
+    CompressionMethod::Huffman1D => 3,
+    CompressionMethod::Other(6) => 6,
+    _ => return Ok(None)
+  };
+  if header.bpp != 32 {
+    return Ok(None)
+  }
+
+  match read_masks(data, header.size, method)? {
+    [0xff_0000, 0xff00, 0xff, 0 | 0xff00_0000] => Ok(None),
+    [red, green, blue, alpha] => Ok(Some(ChannelMasks::new(red, green, blue, alpha)))
+  }
+}
+
+// Iterator over decoded pixels, see `Decoder::pixels`.
+#[derive(Debug, Clone)]
+pub struct Pixels<'a> {
+  decoder: Decoder<'a>,
+  x: u32,
+  y: u32
+}
+
+impl<'a> Iterator for Pixels<'a> {
+  type Item = Pixel;
+
+  fn next(&mut self) -> Option<Pixel> {
+    let pixel = self.decoder.pixel(self.x, self.y)?;
+
+    self.x += 1;
+    if self.x == self.decoder.width() {
+      self.x = 0;
+      self.y += 1;
+    }
+
+    Some(pixel)
+  }
+}
+
+// Lending row decoder, see `Decoder::scanlines`.
+#[derive(Debug)]
+pub struct Scanlines<'a, 'b> {
+  decoder: Decoder<'a>,
+  buf: &'b mut [Pixel],
+  y: u32
+}
+
+impl<'a, 'b> Scanlines<'a, 'b> {
+  // Decodes the next row into the buffer, or returns `None` after the last row.
+  pub fn next_row(&mut self) -> Option<BitmapResult<&[Pixel]>> {
+    if self.y >= self.decoder.height() {
+      return None
+    }
+
+    let row = self.decoder.read_row(self.y, self.buf);
+    self.y += 1;
+    Some(row)
+  }
+
+  // Index of the row the next call to `next_row` will produce.
+  pub fn position(&self) -> u32 {
+    self.y
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  #[cfg(feature = "os2")]
+  use alloc::vec::Vec;
+
+  use crate::encode::tests::sample;
+  #[cfg(feature = "os2")]
+  use crate::{identify, validate, Issue, Pixel, Rgba};
+  use crate::{Bitmap, BitmapError, Decoder, ImageInfo};
+
+  #[test]
+  fn rejects_truncated_headers() {
+    let file = sample(24, 5).encode();
+    assert!(matches!(Bitmap::from_bytes(&file[.. 1]), Err(BitmapError::BadMagic)));
+    for len in [2, 10, 14, 30, 53] {
+      let err = Bitmap::from_bytes(&file[.. len]).unwrap_err();
+      assert!(matches!(err.root_cause(), BitmapError::TruncatedHeader), "{} bytes: {:?}", len, err);
+    }
+    assert_eq!(ImageInfo::parse(&file[.. 29]), None);
+    assert!(matches!(Bitmap::from_bytes(&file[.. file.len() - 1]), Err(BitmapError::InvalidBitmapData)));
+  }
+
+  #[test]
+  fn rejects_overflowing_headers() {
+    let file = sample(32, 5).encode();
+    let with = |at: usize, value: u32| {
+      let mut file = file.clone();
+      file[at .. at + 4].copy_from_slice(&value.to_le_bytes());
+      file
+    };
+
+    // Dimensions far past the data, an offset past the end and a
+    // negative width.
+    for file in [with(18, i32::MAX as u32), with(22, i32::MIN as u32), with(10, u32::MAX)] {
+      assert!(matches!(Decoder::new(&file).map(|_| ()), Err(BitmapError::InvalidBitmapData)));
+    }
+    assert!(matches!(Decoder::headers(&with(18, -5i32 as u32)).map(|_| ()), Err(BitmapError::InvalidBitmapData)));
+    assert!(matches!(Bitmap::from_parts(u32::MAX, 1, 24, Default::default(), Default::default()), Err(BitmapError::DimensionOverflow)));
+  }
+
+  // A 2x2 8-bpp file with an info header of `size` bytes, of which only
+  // the first 16 are filled in, and a palette of `entry`-byte colors.
+  #[cfg(feature = "os2")]
+  fn legacy_file(size: u32, entry: usize) -> Vec<u8> {
+    let offset = 14 + size as usize + 2 * entry;
+    let mut file = Vec::new();
+    file.extend_from_slice(b"BM");
+    file.extend_from_slice(&(offset as u32 + 8).to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&(offset as u32).to_le_bytes());
+    file.extend_from_slice(&size.to_le_bytes());
+    if size == 12 {
+      file.extend_from_slice(&[2, 0, 2, 0, 1, 0, 8, 0]);
+    } else {
+      file.extend_from_slice(&[2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 8, 0]);
+      file.resize(14 + size as usize, 0);
+    }
+    for color in [[10, 20, 30, 0], [40, 50, 60, 0]] {
+      file.extend_from_slice(&color[.. entry]);
+    }
+    // Bottom row first.
+    file.extend_from_slice(&[1, 0, 0, 0, 0, 1, 0, 0]);
+    file
+  }
+
+  #[cfg(feature = "os2")]
+  #[test]
+  fn reads_core_and_os2_headers() {
+    for (size, entry) in [(12, 3), (16, 4), (64, 4)] {
+      let bitmap = Bitmap::from_bytes(&legacy_file(size, entry)).unwrap();
+      assert_eq!((bitmap.width(), bitmap.height()), (2, 2));
+      assert_eq!(bitmap.palette, [Rgba::new(30, 20, 10, 255), Rgba::new(60, 50, 40, 255)]);
+      assert_eq!(bitmap.pixels, [Pixel::PaletteColor(0), Pixel::PaletteColor(1), Pixel::PaletteColor(1), Pixel::PaletteColor(0)]);
+    }
+  }
+
+  // Core dimensions are unsigned, so heights past `i16::MAX` are still
+  // bottom-up rather than negative.
+  #[cfg(feature = "os2")]
+  #[test]
+  fn reads_tall_core_headers() {
+    let mut file = legacy_file(12, 3);
+    file[20 .. 22].copy_from_slice(&0x8000u16.to_le_bytes());
+    file.resize(14 + 12 + 6 + 4 * 0x8000, 0);
+
+    let bitmap = Bitmap::from_bytes(&file).unwrap();
+    assert_eq!(bitmap.height(), 0x8000);
+    assert!(!identify(&file).unwrap().top_down);
+    assert!(!validate(&file).issues.iter().any(|issue| matches!(issue, Issue::InvalidDimensions { .. })));
+  }
+}
@@ -0,0 +1,71 @@
+// Decoding every bitmap under a directory, in parallel with the `rayon`
+// feature.
+
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{Bitmap, BitmapResult, DecodeOptions};
+
+// `.bmp` files under `dir` and its subdirectories, sorted by path, not
+// following symlinked directories. Subdirectories that can't be read come
+// back as errors in place.
+fn bmp_files(dir: &Path) -> io::Result<Vec<(PathBuf, io::Result<()>)>> {
+  let mut found = Vec::new();
+  let mut pending = vec![dir.to_path_buf()];
+  let mut root = true;
+
+  while let Some(dir) = pending.pop() {
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(err) if root => return Err(err),
+      Err(err) => {
+        found.push((dir, Err(err)));
+        continue
+      }
+    };
+    root = false;
+
+    for entry in entries {
+      let (path, is_dir) = match entry.and_then(|entry| Ok((entry.path(), entry.file_type()?.is_dir()))) {
+        Ok(entry) => entry,
+        Err(err) => {
+          found.push((dir.clone(), Err(err)));
+          continue
+        }
+      };
+
+      if is_dir {
+        pending.push(path);
+      } else if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("bmp")) {
+        found.push((path, Ok(())));
+      }
+    }
+  }
+
+  found.sort_by(|a, b| a.0.cmp(&b.0));
+  Ok(found)
+}
+
+fn decode_file(path: PathBuf, listed: io::Result<()>, options: DecodeOptions) -> (PathBuf, BitmapResult<Bitmap>) {
+  let bitmap = listed.and_then(|_| fs::read(&path)).map_err(Into::into).and_then(|data| Bitmap::from_vec_with(data, options));
+  (path, bitmap)
+}
+
+// Every `.bmp` file under `dir`, decoded across rayon's thread pool. Fails
+// only if `dir` itself can't be read.
+#[cfg(feature = "rayon")]
+pub fn decode_dir<P: AsRef<Path>>(dir: P, options: DecodeOptions) -> BitmapResult<impl ParallelIterator<Item = (PathBuf, BitmapResult<Bitmap>)>> {
+  let files = bmp_files(dir.as_ref())?;
+  Ok(files.into_par_iter().map(move |(path, listed)| decode_file(path, listed, options)))
+}
+
+// Every `.bmp` file under `dir`, decoded one at a time in path order.
+// Fails only if `dir` itself can't be read.
+#[cfg(not(feature = "rayon"))]
+pub fn decode_dir<P: AsRef<Path>>(dir: P, options: DecodeOptions) -> BitmapResult<impl Iterator<Item = (PathBuf, BitmapResult<Bitmap>)>> {
+  let files = bmp_files(dir.as_ref())?;
+  Ok(files.into_iter().map(move |(path, listed)| decode_file(path, listed, options)))
+}
@@ -0,0 +1,387 @@
+// Copying and drawing directly into the pixel buffer.
+
+use crate::palette::weighted_distance;
+use crate::{AlphaMode, Bitmap, Pixel, Rgba};
+
+// Axis-aligned rectangle in pixels, from the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32
+}
+
+impl Rect {
+  pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+    Rect { x, y, width, height }
+  }
+}
+
+impl Bitmap {
+  // Straight-alpha `color` as this bitmap stores pixels, premultiplied if
+  // it is. Indexed images get the nearest palette entry by RGB distance, as
+  // `Palette::nearest` picks by default, so an exact match wins; an empty
+  // palette gives index 0.
+  pub(crate) fn pixel_for(&self, color: Rgba) -> Pixel {
+    let channels = [color.r as i32, color.g as i32, color.b as i32];
+    match self.header.bpp {
+      1 | 4 | 8 => {
+        let nearest = self.palette.iter()
+          .enumerate()
+          .min_by_key(|(_, entry)| weighted_distance([1; 3], channels, [entry.r as i32, entry.g as i32, entry.b as i32]))
+          .map_or(0, |(index, _)| index as u8);
+        Pixel::PaletteColor(nearest)
+      }
+      24 => Pixel::BGR(color.b, color.g, color.r),
+      _ if self.alpha_mode == AlphaMode::Premultiplied => {
+        let scale = |value: u8| ((value as u16 * color.a as u16 + 127) / 255) as u8;
+        Pixel::ABGR(scale(color.b), scale(color.g), scale(color.r), color.a)
+      }
+      _ => Pixel::ABGR(color.b, color.g, color.r, color.a)
+    }
+  }
+
+  // `pixel` of `source`, converted to how this bitmap stores pixels.
+  pub(crate) fn convert_from(&self, source: &Bitmap, pixel: Pixel) -> Pixel {
+    match pixel {
+      Pixel::PaletteColor(_) if self.header.bpp <= 8 && source.palette == self.palette => pixel,
+      Pixel::ABGR(..) if self.header.bpp == 32 && source.alpha_mode == self.alpha_mode => pixel,
+      pixel => self.pixel_for(source.straight_color(pixel))
+    }
+  }
+
+  // Sets one pixel, ignoring coordinates outside the image.
+  pub(crate) fn plot(&mut self, x: i64, y: i64, pixel: Pixel) {
+    if x >= 0 && y >= 0 && x < self.width() as i64 && y < self.height() as i64 {
+      let index = y as usize * self.width() as usize + x as usize;
+      self.pixels[index] = pixel;
+    }
+  }
+
+  // Sets the pixel at column `x` of row `y`, ignoring coordinates outside
+  // the image. `pixel` should match how this bitmap stores pixels.
+  pub fn set_pixel(&mut self, x: u32, y: u32, pixel: Pixel) {
+    self.plot(x as i64, y as i64, pixel);
+  }
+
+  // Copies `source_rect` of `source` so its corner lands at `destination`
+  // (which may be negative), clipping to both images and converting pixels
+  // to this bitmap's depth.
+  pub fn blit_from(&mut self, source: &Bitmap, source_rect: Rect, destination: (i32, i32)) {
+    let source_end = |start: u32, len: u32, limit: u32| start.saturating_add(len).min(limit);
+    let (x_end, y_end) = (source_end(source_rect.x, source_rect.width, source.width()), source_end(source_rect.y, source_rect.height, source.height()));
+
+    for sy in source_rect.y .. y_end {
+      let dy = destination.1 as i64 + (sy - source_rect.y) as i64;
+      if dy < 0 || dy >= self.height() as i64 {
+        continue
+      }
+
+      for sx in source_rect.x .. x_end {
+        let dx = destination.0 as i64 + (sx - source_rect.x) as i64;
+        if dx < 0 || dx >= self.width() as i64 {
+          continue
+        }
+
+        let pixel = source.pixels[sy as usize * source.width() as usize + sx as usize];
+        let pixel = self.convert_from(source, pixel);
+        let index = dy as usize * self.width() as usize + dx as usize;
+        self.pixels[index] = pixel;
+      }
+    }
+  }
+
+  pub fn fill_rect(&mut self, rect: Rect, color: Rgba) {
+    let pixel = self.pixel_for(color);
+    let x_end = rect.x.saturating_add(rect.width).min(self.width());
+    let y_end = rect.y.saturating_add(rect.height).min(self.height());
+
+    for y in rect.y .. y_end {
+      let row = y as usize * self.width() as usize;
+      if rect.x < x_end {
+        self.pixels[row + rect.x as usize .. row + x_end as usize].fill(pixel);
+      }
+    }
+  }
+
+  // Repeats `pattern` across `rect`, starting from its top-left corner and
+  // clipped to both the rectangle and the image.
+  pub fn tile_fill(&mut self, pattern: &Bitmap, rect: Rect) {
+    let x_end = rect.x.saturating_add(rect.width).min(self.width());
+    let y_end = rect.y.saturating_add(rect.height).min(self.height());
+    let (pattern_width, pattern_height) = (pattern.width(), pattern.height());
+    if pattern_width == 0 || pattern_height == 0 {
+      return
+    }
+
+    for y in rect.y .. y_end {
+      let source_row = ((y - rect.y) % pattern_height) as usize * pattern_width as usize;
+      for x in rect.x .. x_end {
+        let pixel = pattern.pixels[source_row + ((x - rect.x) % pattern_width) as usize];
+        let index = y as usize * self.width() as usize + x as usize;
+        self.pixels[index] = self.convert_from(pattern, pixel);
+      }
+    }
+  }
+
+  // One-pixel outline along the inside edge of `rect`.
+  pub fn draw_rect(&mut self, rect: Rect, color: Rgba) {
+    if rect.width == 0 || rect.height == 0 {
+      return
+    }
+
+    let (left, top) = (rect.x as i64, rect.y as i64);
+    let (right, bottom) = (left + rect.width as i64 - 1, top + rect.height as i64 - 1);
+    self.draw_line((left, top), (right, top), color);
+    self.draw_line((left, bottom), (right, bottom), color);
+    self.draw_line((left, top), (left, bottom), color);
+    self.draw_line((right, top), (right, bottom), color);
+  }
+
+  // Bresenham line including both end points, which may lie outside the
+  // image. The segment is clipped to the image first, so only pixels inside
+  // it are stepped through.
+  pub fn draw_line(&mut self, from: (i64, i64), to: (i64, i64), color: Rgba) {
+    if self.width() == 0 || self.height() == 0 {
+      return
+    }
+    let Some((from, to)) = clip_line(from, to, self.width() as i64 - 1, self.height() as i64 - 1) else {
+      return
+    };
+
+    let pixel = self.pixel_for(color);
+    let (mut x, mut y) = from;
+    let (dx, dy) = (to.0.saturating_sub(x).saturating_abs(), to.1.saturating_sub(y).saturating_abs().saturating_neg());
+    let (step_x, step_y) = (to.0.saturating_sub(x).signum(), to.1.saturating_sub(y).signum());
+    let mut error = dx + dy;
+
+    loop {
+      self.plot(x, y, pixel);
+      if (x, y) == to {
+        break
+      }
+
+      let doubled = 2 * error;
+      if doubled >= dy {
+        error += dy;
+        x += step_x;
+      }
+      if doubled <= dx {
+        error += dx;
+        y += step_y;
+      }
+    }
+  }
+
+  // Midpoint circle outline around `centre`. Only the steps that can land
+  // in the image are walked, so huge circles clipped down to a few pixels
+  // stay cheap.
+  pub fn draw_circle(&mut self, centre: (i64, i64), radius: u32, color: Rgba) {
+    let pixel = self.pixel_for(color);
+    let (cx, cy) = centre;
+    let (width, height, r) = (self.width() as i64, self.height() as i64, radius as i64);
+    if cx.saturating_add(r) < 0 || cy.saturating_add(r) < 0 || cx.saturating_sub(r) >= width || cy.saturating_sub(r) >= height {
+      return
+    }
+
+    // Step `y` plots rows `cy ± y` and columns `cx ± y`, so only the steps
+    // whose offsets fall inside the image along one axis matter.
+    let mut spans = [
+      (cy.saturating_neg(), (height - 1).saturating_sub(cy)),
+      (cy.saturating_sub(height - 1), cy),
+      (cx.saturating_neg(), (width - 1).saturating_sub(cx)),
+      (cx.saturating_sub(width - 1), cx)
+    ];
+    spans.sort_unstable();
+
+    let mut next = 0;
+    for (start, end) in spans {
+      let (start, end) = (start.max(next), end.min(r));
+      if start > end {
+        continue
+      }
+
+      // Resume the midpoint walk at step `start`: `x` is where it would be,
+      // and `error` is x² - x + y² + 2y + 1 - r², which it tracks.
+      let (mut x, mut y) = (circle_x(radius, start), start);
+      let mut error = (x as i128 * (x as i128 - 1) + y as i128 * (y as i128 + 2) + 1 - r as i128 * r as i128) as i64;
+      while x >= y && y <= end {
+        for (px, py) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+          self.plot(cx.saturating_add(px), cy.saturating_add(py), pixel);
+        }
+
+        y += 1;
+        if error < 0 {
+          error += 2 * y + 1;
+        } else {
+          x -= 1;
+          error += 2 * (y - x) + 1;
+        }
+      }
+      next = end + 1;
+    }
+  }
+}
+
+// Column the midpoint circle walk reaches at step `y`: the largest `x`
+// with x² - x < r² - y², or `radius` itself on the first step.
+fn circle_x(radius: u32, y: i64) -> i64 {
+  if y == 0 {
+    return radius as i64
+  }
+
+  let squared = (radius as u64).pow(2) - (y as u64).pow(2);
+  let mut x = squared.isqrt();
+  if (x + 1) * x < squared {
+    x += 1;
+  }
+  while x > 0 && x * (x - 1) >= squared {
+    x -= 1;
+  }
+  x as i64
+}
+
+// `start` moved toward `end` by `part / whole` of the way, rounded to the
+// nearest pixel. Exact for any coordinates: each product fits in a u128.
+fn interpolate(start: i64, end: i64, part: u64, whole: u64) -> i64 {
+  let offset = ((end.abs_diff(start) as u128 * part as u128 + whole as u128 / 2) / whole as u128) as u64;
+  if end >= start { start.wrapping_add_unsigned(offset) } else { start.wrapping_sub_unsigned(offset) }
+}
+
+// Cohen-Sutherland clipping of the segment from `a` to `b` to the
+// rectangle from the origin to `(right, bottom)`, inclusive. `None` when
+// the segment misses it.
+fn clip_line(mut a: (i64, i64), mut b: (i64, i64), right: i64, bottom: i64) -> Option<((i64, i64), (i64, i64))> {
+  const LEFT: u8 = 1;
+  const RIGHT: u8 = 2;
+  const TOP: u8 = 4;
+  const BOTTOM: u8 = 8;
+  let outcode = |(x, y): (i64, i64)| {
+    let horizontal = if x < 0 { LEFT } else if x > right { RIGHT } else { 0 };
+    let vertical = if y < 0 { TOP } else if y > bottom { BOTTOM } else { 0 };
+    horizontal | vertical
+  };
+
+  loop {
+    let (code_a, code_b) = (outcode(a), outcode(b));
+    if code_a | code_b == 0 {
+      return Some((a, b))
+    }
+    if code_a & code_b != 0 {
+      return None
+    }
+
+    // Move an end point outside the rectangle onto an edge it crosses.
+    let (code, point, other) = if code_a != 0 { (code_a, a, b) } else { (code_b, b, a) };
+    let along_x = |edge: i64| (edge, interpolate(point.1, other.1, point.0.abs_diff(edge), point.0.abs_diff(other.0)));
+    let along_y = |edge: i64| (interpolate(point.0, other.0, point.1.abs_diff(edge), point.1.abs_diff(other.1)), edge);
+    let moved = if code & TOP != 0 {
+      along_y(0)
+    } else if code & BOTTOM != 0 {
+      along_y(bottom)
+    } else if code & LEFT != 0 {
+      along_x(0)
+    } else {
+      along_x(right)
+    };
+
+    if code_a != 0 {
+      a = moved;
+    } else {
+      b = moved;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+  use alloc::vec::Vec;
+
+  use crate::{Bitmap, Pixel, Rect, Rgba};
+
+  const WHITE: Rgba = Rgba::new(255, 255, 255, 255);
+
+  fn canvas() -> Bitmap {
+    Bitmap::from_parts(4, 4, 24, vec![], vec![Pixel::BGR(0, 0, 0); 16]).unwrap()
+  }
+
+  fn lit(bitmap: &Bitmap) -> Vec<(u32, u32)> {
+    bitmap.enumerate_pixels().filter(|(_, _, pixel)| **pixel != Pixel::BGR(0, 0, 0)).map(|(x, y, _)| (x, y)).collect()
+  }
+
+  #[test]
+  fn clips_lines_reaching_far_outside() {
+    let mut bitmap = canvas();
+    bitmap.draw_line((i64::MIN, i64::MIN), (i64::MAX, i64::MAX), WHITE);
+    assert_eq!(lit(&bitmap), [(0, 0), (1, 1), (2, 2), (3, 3)]);
+
+    let mut bitmap = canvas();
+    bitmap.draw_line((i64::MAX, 2), (i64::MIN, 2), WHITE);
+    assert_eq!(lit(&bitmap), [(0, 2), (1, 2), (2, 2), (3, 2)]);
+
+    let mut bitmap = canvas();
+    bitmap.draw_line((-10, 5), (10, 5), WHITE);
+    bitmap.draw_line((i64::MIN, 0), (-1, i64::MAX), WHITE);
+    assert!(lit(&bitmap).is_empty());
+  }
+
+  #[test]
+  fn lines_inside_are_unchanged() {
+    let mut bitmap = canvas();
+    bitmap.draw_line((0, 0), (3, 1), WHITE);
+    assert_eq!(lit(&bitmap), [(0, 0), (1, 0), (2, 1), (3, 1)]);
+  }
+
+  #[test]
+  fn indexed_images_get_the_nearest_entry() {
+    let palette = vec![Rgba::new(0, 0, 0, 255), Rgba::new(250, 10, 10, 255), Rgba::new(10, 10, 250, 255)];
+    let mut bitmap = Bitmap::from_parts(2, 1, 8, palette, vec![Pixel::PaletteColor(0); 2]).unwrap();
+    bitmap.fill_rect(Rect::new(0, 0, 1, 1), Rgba::new(255, 0, 0, 255));
+    bitmap.fill_rect(Rect::new(1, 0, 1, 1), Rgba::new(10, 10, 250, 255));
+    assert_eq!(bitmap.pixels, [Pixel::PaletteColor(1), Pixel::PaletteColor(2)]);
+  }
+
+  #[test]
+  fn clipped_circles_match_the_full_walk() {
+    // The unclipped midpoint walk, plotted into a 9x7 image.
+    let reference = |centre: (i64, i64), radius: u32| {
+      let mut lit = Vec::new();
+      let (mut x, mut y, mut error) = (radius as i64, 0i64, 1 - radius as i64);
+      while x >= y {
+        for (px, py) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+          let (px, py) = (centre.0 + px, centre.1 + py);
+          if (0 .. 9).contains(&px) && (0 .. 7).contains(&py) {
+            lit.push((px as u32, py as u32));
+          }
+        }
+        y += 1;
+        if error < 0 {
+          error += 2 * y + 1;
+        } else {
+          x -= 1;
+          error += 2 * (y - x) + 1;
+        }
+      }
+      lit.sort_unstable_by_key(|(x, y)| (*y, *x));
+      lit.dedup();
+      lit
+    };
+
+    for radius in 0 .. 40 {
+      for centre in [(4, 3), (-5, 3), (20, -6), (0, 30), (-12, -12), (11, 9)] {
+        let mut bitmap = Bitmap::from_parts(9, 7, 24, vec![], vec![Pixel::BGR(0, 0, 0); 63]).unwrap();
+        bitmap.draw_circle(centre, radius, WHITE);
+        assert_eq!(lit(&bitmap), reference(centre, radius), "radius {} around {:?}", radius, centre);
+      }
+    }
+
+    // Only the bottom of this one crosses the image.
+    let mut bitmap = canvas();
+    bitmap.draw_circle((1, -1_000_000_000 + 2), 1_000_000_000, WHITE);
+    assert_eq!(lit(&bitmap), [(0, 2), (1, 2), (2, 2), (3, 2)]);
+    bitmap.draw_circle((i64::MAX, i64::MIN), u32::MAX, WHITE);
+  }
+}
@@ -0,0 +1,19 @@
+// Conversion to `egui::ColorImage` for display in immediate-mode GUIs.
+
+use egui::ColorImage;
+
+use crate::Bitmap;
+
+impl Bitmap {
+  // Bitmap channels are sRGB-encoded with straight alpha, which egui
+  // premultiplies in gamma space as it expects. Images without an alpha
+  // channel come out opaque.
+  pub fn to_color_image(&self) -> ColorImage {
+    let size = [self.width() as usize, self.height() as usize];
+    if self.header.bpp == 32 {
+      ColorImage::from_rgba_unmultiplied(size, &self.to_rgba8())
+    } else {
+      ColorImage::from_rgb(size, &self.to_rgb8())
+    }
+  }
+}
@@ -0,0 +1,81 @@
+// embedded-graphics drawing for decoded bitmaps and streaming decoders.
+
+use embedded_graphics::image::ImageDrawable;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::{Bitmap, Decoder, Rgba};
+
+fn rgb888(color: Rgba) -> Rgb888 {
+  Rgb888::new(color.r, color.g, color.b)
+}
+
+// Both wrappers draw through a per-pixel color lookup, clipped to `area`.
+fn draw_area<D, F>(target: &mut D, size: Size, area: &Rectangle, color_at: F) -> Result<(), D::Error>
+where
+  D: DrawTarget<Color = Rgb888>,
+  F: Fn(u32, u32) -> Rgba
+{
+  let area = area.intersection(&Rectangle::new(Point::zero(), size));
+  let (left, top) = (area.top_left.x as u32, area.top_left.y as u32);
+
+  let colors = (top .. top + area.size.height)
+    .flat_map(|y| (left .. left + area.size.width).map(move |x| (x, y)))
+    .map(|(x, y)| rgb888(color_at(x, y)));
+
+  target.fill_contiguous(&Rectangle::new(Point::zero(), area.size), colors)
+}
+
+impl OriginDimensions for Bitmap {
+  fn size(&self) -> Size {
+    Size::new(self.width(), self.height())
+  }
+}
+
+impl ImageDrawable for Bitmap {
+  type Color = Rgb888;
+
+  fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+  where
+    D: DrawTarget<Color = Rgb888>
+  {
+    self.draw_sub_image(target, &self.bounding_box())
+  }
+
+  fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+  where
+    D: DrawTarget<Color = Rgb888>
+  {
+    draw_area(target, self.size(), area, |x, y| {
+      self.pixel(x, y).map(|pixel| self.color(*pixel)).unwrap_or(Rgba::BLACK)
+    })
+  }
+}
+
+// Decodes straight from the source slice while drawing, with no pixel buffer.
+impl OriginDimensions for Decoder<'_> {
+  fn size(&self) -> Size {
+    Size::new(self.width(), self.height())
+  }
+}
+
+impl ImageDrawable for Decoder<'_> {
+  type Color = Rgb888;
+
+  fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+  where
+    D: DrawTarget<Color = Rgb888>
+  {
+    self.draw_sub_image(target, &self.bounding_box())
+  }
+
+  fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+  where
+    D: DrawTarget<Color = Rgb888>
+  {
+    draw_area(target, self.size(), area, |x, y| {
+      self.pixel(x, y).map(|pixel| self.color(pixel)).unwrap_or(Rgba::BLACK)
+    })
+  }
+}
@@ -0,0 +1,303 @@
+// Serializing bitmaps back into BITMAPINFOHEADER files.
+
+use alloc::vec::Vec;
+
+use crate::decoder::{row_stride, FILE_HEADER_SIZE};
+use crate::{AlphaMode, Bitmap, BitmapError, BitmapResult, BitmapV5Header, CompressionMethod, EncodeOptions, FileHeader, Pixel, Rgba, ScanlineOrder};
+
+const INFO_HEADER_SIZE: u32 = 40;
+
+impl Bitmap {
+  // Builds a bottom-up bitmap from decoded parts, encoding `data` to match.
+  pub fn from_parts(width: u32, height: u32, bpp: u16, palette: Vec<Rgba>, pixels: Vec<Pixel>) -> BitmapResult<Bitmap> {
+    match bpp {
+      1 | 4 | 8 | 24 | 32 => {}
+      _ => return Err(BitmapError::UnsupportedBitsPerPixel)
+    }
+
+    if width == 0 || height == 0 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    if width > i32::MAX as u32 || height > i32::MAX as u32 {
+      return Err(BitmapError::DimensionOverflow)
+    }
+
+    row_stride(width, bpp)
+      .and_then(|stride| stride.checked_mul(height as usize))
+      .ok_or(BitmapError::DimensionOverflow)?;
+
+    if pixels.len() as u64 != width as u64 * height as u64 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    if palette.len() > 256 {
+      return Err(BitmapError::BadPalette)
+    }
+
+    // Indexed images must only reference entries that exist.
+    if bpp <= 8 && pixels.iter().any(|pixel| match pixel {
+      Pixel::PaletteColor(index) => *index as usize >= palette.len() || *index as u32 >= 1 << bpp,
+      _ => true
+    }) {
+      return Err(BitmapError::BadPalette)
+    }
+
+    Ok(Bitmap::assemble(width, height as i32, bpp, palette, pixels))
+  }
+
+  // New bitmap with this one's depth, palette, resolution and row order but
+  // different dimensions and pixels. Callers ensure `pixels` has `width * height` entries.
+  pub(crate) fn with_pixels(&self, width: u32, height: u32, pixels: Vec<Pixel>) -> Bitmap {
+    let resolution = (self.header.x_pels_per_meter, self.header.y_pels_per_meter);
+    self.with_pixels_at(width, height, resolution, pixels)
+  }
+
+  // `with_pixels` for a quarter turn: `height` wide and `width` tall, with
+  // the horizontal and vertical resolution swapped to match.
+  pub(crate) fn turned_with_pixels(&self, pixels: Vec<Pixel>) -> Bitmap {
+    let resolution = (self.header.y_pels_per_meter, self.header.x_pels_per_meter);
+    self.with_pixels_at(self.height(), self.width(), resolution, pixels)
+  }
+
+  fn with_pixels_at(&self, width: u32, height: u32, (x, y): (i32, i32), pixels: Vec<Pixel>) -> Bitmap {
+    let height = self.orientation.signed_height(height);
+    let mut bitmap = Bitmap::assemble(width, height, self.header.bpp, self.palette.clone(), pixels);
+    bitmap.alpha_mode = self.alpha_mode;
+    (bitmap.header.x_pels_per_meter, bitmap.header.y_pels_per_meter) = (x, y);
+    // `assemble` encoded the file without a resolution.
+    bitmap.data[FILE_HEADER_SIZE + 24 .. FILE_HEADER_SIZE + 28].copy_from_slice(&x.to_le_bytes());
+    bitmap.data[FILE_HEADER_SIZE + 28 .. FILE_HEADER_SIZE + 32].copy_from_slice(&y.to_le_bytes());
+    bitmap
+  }
+
+  pub(crate) fn assemble(width: u32, pix_height: i32, bpp: u16, palette: Vec<Rgba>, pixels: Vec<Pixel>) -> Bitmap {
+    let mut bitmap = Bitmap {
+      data: Vec::new(),
+      file_header: FileHeader { magic: *b"BM", file_size: 0, reserved1: 0, reserved2: 0, pixel_offset: 0 },
+      header: BitmapV5Header {
+        size: INFO_HEADER_SIZE,
+        pix_width: width as i32,
+        pix_height,
+        bpp,
+        method: CompressionMethod::None,
+        x_pels_per_meter: 0,
+        y_pels_per_meter: 0,
+        colors: if bpp <= 8 { palette.len() as u32 } else { 0 },
+        important_colors: 0,
+        reserved: 0
+      },
+      palette: if bpp <= 8 { palette } else { Vec::new() },
+      pixels,
+      alpha_mode: AlphaMode::Straight,
+      orientation: ScanlineOrder::from_height(pix_height)
+    };
+
+    bitmap.data = bitmap.encode();
+    bitmap.file_header.pixel_offset = FILE_HEADER_SIZE as u32 + INFO_HEADER_SIZE + bitmap.palette.len() as u32 * 4;
+    bitmap.file_header.file_size = bitmap.data.len() as u32;
+    bitmap
+  }
+
+  // Encodes the current header, palette and pixels as an uncompressed file.
+  // Pixels that don't match the depth are converted: colors are looked up
+  // or dropped to index 0 for indexed images, and alpha defaults to opaque.
+  // Rows are stored in `orientation` order.
+  pub fn encode(&self) -> Vec<u8> {
+    trace_span!("encode", width = self.width(), height = self.height(), bpp = self.header.bpp);
+    let stride = self.encoded_stride();
+    let mut out = self.encode_headers();
+    out.reserve(stride * self.height() as usize);
+
+    for stored in 0 .. self.height() {
+      let start = out.len();
+      out.resize(start + stride, 0);
+      self.encode_stored_row(stored, &mut out[start ..]);
+    }
+
+    trace_event!(len = out.len(), "encoded");
+    out
+  }
+
+  // `encode`, with the choices in `options`.
+  pub fn encode_with(&self, options: EncodeOptions) -> Vec<u8> {
+    let mut out = self.encode();
+    if options.palette_alpha && self.header.bpp <= 8 {
+      let start = FILE_HEADER_SIZE + INFO_HEADER_SIZE as usize;
+      for (quad, color) in out[start ..].chunks_exact_mut(4).zip(&self.palette) {
+        quad[3] = color.a;
+      }
+    }
+    out
+  }
+
+  // Checked when bitmaps are built, so this only saturates after the
+  // header is edited by hand.
+  pub(crate) fn encoded_stride(&self) -> usize {
+    row_stride(self.width(), self.header.bpp).unwrap_or(usize::MAX)
+  }
+
+  // File header, info header and palette: everything before the pixel array.
+  // The file header's reserved fields are carried over; `header.reserved`
+  // is not, since only a 40-byte info header is written.
+  pub(crate) fn encode_headers(&self) -> Vec<u8> {
+    let (width, bpp) = (self.width(), self.header.bpp);
+    let palette_len = if bpp <= 8 { self.palette.len() } else { 0 };
+    let image_size = self.encoded_stride() * self.height() as usize;
+    let offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE as usize + palette_len * 4;
+
+    let file_header = FileHeader {
+      magic: *b"BM",
+      file_size: (offset + image_size) as u32,
+      reserved1: self.file_header.reserved1,
+      reserved2: self.file_header.reserved2,
+      pixel_offset: offset as u32
+    };
+
+    let mut out = Vec::with_capacity(offset);
+    out.extend_from_slice(&file_header.to_bytes());
+
+    out.extend_from_slice(&INFO_HEADER_SIZE.to_le_bytes());
+    out.extend_from_slice(&(width as i32).to_le_bytes());
+    out.extend_from_slice(&self.orientation.signed_height(self.height()).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&bpp.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(image_size as u32).to_le_bytes());
+    out.extend_from_slice(&self.header.x_pels_per_meter.to_le_bytes());
+    out.extend_from_slice(&self.header.y_pels_per_meter.to_le_bytes());
+    out.extend_from_slice(&(palette_len as u32).to_le_bytes());
+    out.extend_from_slice(&self.header.important_colors.min(palette_len as u32).to_le_bytes());
+
+    for color in &self.palette[.. palette_len] {
+      out.extend_from_slice(&[color.b, color.g, color.r, 0]);
+    }
+
+    out
+  }
+
+  // Fills a zeroed `row` with the `stored`-th row of the pixel array, in file order.
+  pub(crate) fn encode_stored_row(&self, stored: u32, row: &mut [u8]) {
+    let height = self.height();
+    let y = match self.orientation {
+      ScanlineOrder::TopDown => stored,
+      ScanlineOrder::BottomUp => height - 1 - stored
+    };
+    self.encode_row(y, row);
+  }
+
+  // Writes `encode()` to the file at `path`, replacing it if it exists.
+  #[cfg(feature = "fs")]
+  pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> BitmapResult<()> {
+    std::fs::write(path, self.encode())?;
+    Ok(())
+  }
+
+  pub(crate) fn encode_row(&self, y: u32, row: &mut [u8]) {
+    let bpp = self.header.bpp;
+
+    for x in 0 .. self.width() {
+      let pixel = match self.pixel(x, y) {
+        Some(pixel) => *pixel,
+        None => continue
+      };
+      let x = x as usize;
+
+      match bpp {
+        1 | 4 | 8 => {
+          let index = self.palette_index(pixel);
+          let per_byte = 8 / bpp as usize;
+          let shift = 8 - bpp as usize * (x % per_byte + 1);
+          row[x / per_byte] |= (index & ((1u16 << bpp) - 1) as u8) << shift;
+        }
+
+        24 => {
+          let color = self.straight_color(pixel);
+          row[x * 3 .. x * 3 + 3].copy_from_slice(&[color.b, color.g, color.r]);
+        }
+
+        _ => {
+          let color = self.straight_color(pixel);
+          row[x * 4 .. x * 4 + 4].copy_from_slice(&[color.b, color.g, color.r, color.a]);
+        }
+      }
+    }
+  }
+
+  // Index for writing `pixel` into an indexed image, via exact palette match if needed.
+  pub(crate) fn palette_index(&self, pixel: Pixel) -> u8 {
+    match pixel {
+      Pixel::PaletteColor(index) => index,
+      other => {
+        let color = self.color(other);
+        self.palette.iter()
+          .position(|entry| entry.r == color.r && entry.g == color.g && entry.b == color.b)
+          .unwrap_or(0) as u8
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use alloc::vec::Vec;
+
+  use crate::{AlphaHandling, Bitmap, DecodeOptions, EncodeOptions, Pixel, Rgba};
+
+  // Depths `encode` writes and `from_bytes` reads back in this build.
+  pub(crate) const DEPTHS: &[u16] = if cfg!(feature = "low-depth") { &[1, 4, 8, 24, 32] } else { &[8, 24, 32] };
+
+  // A `width` x 3 image at `bpp` whose neighbouring pixels differ, with a
+  // full palette for indexed depths.
+  pub(crate) fn sample(bpp: u16, width: u32) -> Bitmap {
+    let count = width as usize * 3;
+    let (palette, pixels) = match bpp {
+      1 | 4 | 8 => {
+        let colors = 1usize << bpp;
+        let palette = (0 .. colors).map(|index| Rgba::new(index as u8, (index * 7) as u8, 255 - index as u8, 255)).collect();
+        (palette, (0 .. count).map(|at| Pixel::PaletteColor((at * 5 % colors) as u8)).collect())
+      }
+      24 => (Vec::new(), (0 .. count).map(|at| Pixel::BGR(at as u8, (at * 3) as u8, (at * 7) as u8)).collect()),
+      _ => (Vec::new(), (0 .. count).map(|at| Pixel::ABGR(at as u8, (at * 3) as u8, (at * 7) as u8, (at * 11 + 1) as u8)).collect())
+    };
+    Bitmap::from_parts(width, 3, bpp, palette, pixels).unwrap()
+  }
+
+  #[test]
+  fn round_trips_every_depth() {
+    for bpp in DEPTHS {
+      // Widths with and without row padding.
+      for width in [4, 5] {
+        let bitmap = sample(*bpp, width);
+        let decoded = Bitmap::from_bytes(&bitmap.encode()).unwrap();
+        assert_eq!(decoded, bitmap, "{} bpp, width {}", bpp, width);
+      }
+    }
+  }
+
+  #[test]
+  fn round_trips_palette_alpha() {
+    let mut bitmap = sample(8, 5);
+    bitmap.palette[1].a = 128;
+
+    let file = bitmap.encode_with(EncodeOptions { palette_alpha: true });
+    let decoded = Bitmap::from_bytes_with(&file, DecodeOptions { palette_alpha: AlphaHandling::Trust, ..DecodeOptions::default() }).unwrap();
+    assert_eq!(decoded.palette, bitmap.palette);
+    assert_eq!(Bitmap::from_bytes(&file).unwrap().palette[1].a, 255);
+  }
+
+  #[test]
+  fn transforms_keep_the_resolution() {
+    let mut bitmap = sample(24, 5);
+    (bitmap.header.x_pels_per_meter, bitmap.header.y_pels_per_meter) = (3780, 2835);
+
+    let resolution = |bitmap: &Bitmap| {
+      let decoded = Bitmap::from_bytes(&bitmap.data).unwrap();
+      (decoded.header.x_pels_per_meter, decoded.header.y_pels_per_meter)
+    };
+    assert_eq!(resolution(&bitmap.rotate180()), (3780, 2835));
+    assert_eq!(resolution(&bitmap.crop(1, 1, 2, 2).unwrap()), (3780, 2835));
+    assert_eq!(resolution(&bitmap.rotate90()), (2835, 3780));
+    assert_eq!(resolution(&bitmap.rotate270()), (2835, 3780));
+  }
+}
@@ -0,0 +1,146 @@
+// C interface: opaque decoded bitmaps handed out through raw pointers.
+//
+// Pixels are always served as row-major RGBA8, so C callers never see `Pixel`.
+
+use alloc::boxed::Box;
+use core::ffi::c_char;
+use core::{ptr, slice};
+
+use crate::{Bitmap, BitmapError};
+
+// Result codes returned by every fallible call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicrobmpStatus {
+  Ok = 0,
+  NullPointer = 1,
+  InvalidData = 2,
+  UnsupportedBitsPerPixel = 3,
+  UnsupportedCompression = 4,
+  BufferTooSmall = 5,
+  IoError = 6
+}
+
+impl From<&BitmapError> for MicrobmpStatus {
+  fn from(err: &BitmapError) -> MicrobmpStatus {
+    match err.root_cause() {
+      BitmapError::UnsupportedBitsPerPixel => MicrobmpStatus::UnsupportedBitsPerPixel,
+      BitmapError::UnsupportedCompression => MicrobmpStatus::UnsupportedCompression,
+      BitmapError::BufferTooSmall => MicrobmpStatus::BufferTooSmall,
+      #[cfg(feature = "std")]
+      BitmapError::BitmapIOError(_) => MicrobmpStatus::IoError,
+      _ => MicrobmpStatus::InvalidData
+    }
+  }
+}
+
+// Opaque handle; C sees only a pointer to it.
+pub struct MicrobmpBitmap(Bitmap);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicrobmpInfo {
+  pub width: u32,
+  pub height: u32,
+  pub bpp: u16,
+  pub top_down: bool,
+  // Bytes `microbmp_get_pixels` needs: width * height * 4.
+  pub rgba_len: usize
+}
+
+/// Decodes `len` bytes at `data`, storing a new handle in `*out` on success.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to writable storage
+/// for one pointer. The handle must be released with `microbmp_free`.
+#[no_mangle]
+pub unsafe extern "C" fn microbmp_decode(data: *const u8, len: usize, out: *mut *mut MicrobmpBitmap) -> MicrobmpStatus {
+  if data.is_null() || out.is_null() {
+    return MicrobmpStatus::NullPointer
+  }
+
+  match Bitmap::from_bytes(slice::from_raw_parts(data, len)) {
+    Ok(bitmap) => {
+      *out = Box::into_raw(Box::new(MicrobmpBitmap(bitmap)));
+      MicrobmpStatus::Ok
+    }
+    Err(err) => {
+      *out = ptr::null_mut();
+      MicrobmpStatus::from(&err)
+    }
+  }
+}
+
+/// Fills `*out` with the dimensions of a decoded bitmap.
+///
+/// # Safety
+///
+/// `bitmap` must come from `microbmp_decode` and not yet be freed; `out`
+/// must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn microbmp_get_info(bitmap: *const MicrobmpBitmap, out: *mut MicrobmpInfo) -> MicrobmpStatus {
+  if bitmap.is_null() || out.is_null() {
+    return MicrobmpStatus::NullPointer
+  }
+
+  let bitmap = &(*bitmap).0;
+  *out = MicrobmpInfo {
+    width: bitmap.width(),
+    height: bitmap.height(),
+    bpp: bitmap.header.bpp,
+    top_down: bitmap.header.pix_height < 0,
+    rgba_len: bitmap.pixels.len() * 4
+  };
+
+  MicrobmpStatus::Ok
+}
+
+/// Copies the pixels as top-down RGBA8 into `out`, which holds `out_len` bytes.
+///
+/// # Safety
+///
+/// `bitmap` must come from `microbmp_decode` and not yet be freed; `out`
+/// must point to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn microbmp_get_pixels(bitmap: *const MicrobmpBitmap, out: *mut u8, out_len: usize) -> MicrobmpStatus {
+  if bitmap.is_null() || out.is_null() {
+    return MicrobmpStatus::NullPointer
+  }
+
+  let rgba = (*bitmap).0.to_rgba8();
+  if out_len < rgba.len() {
+    return MicrobmpStatus::BufferTooSmall
+  }
+
+  ptr::copy_nonoverlapping(rgba.as_ptr(), out, rgba.len());
+  MicrobmpStatus::Ok
+}
+
+/// Releases a handle from `microbmp_decode`. Null is ignored.
+///
+/// # Safety
+///
+/// `bitmap` must be null or a live handle, and is invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn microbmp_free(bitmap: *mut MicrobmpBitmap) {
+  if !bitmap.is_null() {
+    drop(Box::from_raw(bitmap));
+  }
+}
+
+// Static, NUL-terminated description of a status code.
+#[no_mangle]
+pub extern "C" fn microbmp_status_message(status: MicrobmpStatus) -> *const c_char {
+  let message: &'static [u8] = match status {
+    MicrobmpStatus::Ok => b"ok\0",
+    MicrobmpStatus::NullPointer => b"null pointer argument\0",
+    MicrobmpStatus::InvalidData => b"invalid bitmap data\0",
+    MicrobmpStatus::UnsupportedBitsPerPixel => b"unsupported bits per pixel\0",
+    MicrobmpStatus::UnsupportedCompression => b"unsupported compression method\0",
+    MicrobmpStatus::BufferTooSmall => b"output buffer too small\0",
+    MicrobmpStatus::IoError => b"I/O error\0"
+  };
+
+  message.as_ptr() as *const c_char
+}
@@ -0,0 +1,250 @@
+// Convolution filters over decoded pixels.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::adjust::gray_ramp;
+use crate::{AlphaMode, Bitmap, BitmapError, BitmapResult, LumaWeights, Pixel};
+
+// Horizontal Sobel taps; the vertical ones are the transpose.
+const SOBEL: [[i32; 3]; 3] = [[-1, 0, 1], [-2, 0, 2], [-1, 0, 1]];
+
+// How samples beyond the image border are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeMode {
+  // Repeat the nearest border pixel.
+  #[default]
+  Clamp,
+  // Continue from the opposite side, for tiling textures.
+  Wrap,
+  // Reflect about the border pixel without repeating it.
+  Mirror,
+  // Treat everything outside as transparent black.
+  Transparent
+}
+
+impl EdgeMode {
+  // Source index for `at` along an axis of `len`, or `None` for no sample.
+  fn resolve(self, at: isize, len: usize) -> Option<usize> {
+    let len = len as isize;
+    if (0 .. len).contains(&at) {
+      return Some(at as usize)
+    }
+
+    match self {
+      EdgeMode::Clamp => Some(at.clamp(0, len - 1) as usize),
+      EdgeMode::Wrap => Some(at.rem_euclid(len) as usize),
+      EdgeMode::Mirror if len == 1 => Some(0),
+      EdgeMode::Mirror => {
+        let at = at.rem_euclid(2 * (len - 1));
+        Some(if at < len { at } else { 2 * (len - 1) - at } as usize)
+      }
+      EdgeMode::Transparent => None
+    }
+  }
+}
+
+// Odd-sized grid of weights, row-major, centred on the output pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+  width: usize,
+  height: usize,
+  weights: Vec<f32>
+}
+
+impl Kernel {
+  // Both dimensions must be odd and `weights` must hold width * height values.
+  pub fn new(width: usize, height: usize, weights: Vec<f32>) -> BitmapResult<Kernel> {
+    if width.is_multiple_of(2) || height.is_multiple_of(2) || weights.len() != width * height {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    Ok(Kernel { width, height, weights })
+  }
+
+  // Equal weights over a (2 * radius + 1) square.
+  pub fn box_blur(radius: usize) -> Kernel {
+    let side = radius * 2 + 1;
+    Kernel { width: side, height: side, weights: vec![1.0 / (side * side) as f32; side * side] }
+  }
+
+  // The pixel boosted against its four neighbours.
+  pub fn sharpen() -> Kernel {
+    Kernel { width: 3, height: 3, weights: vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0] }
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  pub fn weights(&self) -> &[f32] {
+    &self.weights
+  }
+
+  // Premultiplied RGBA samples filtered through the kernel.
+  fn apply(&self, samples: &[[f32; 4]], width: usize, height: usize, edges: EdgeMode) -> Vec<[f32; 4]> {
+    let (half_x, half_y) = ((self.width / 2) as isize, (self.height / 2) as isize);
+    let mut out = vec![[0.0; 4]; samples.len()];
+
+    for (index, sum) in out.iter_mut().enumerate() {
+      let (x, y) = ((index % width) as isize, (index / width) as isize);
+
+      for (tap, weight) in self.weights.iter().enumerate() {
+        let (dx, dy) = ((tap % self.width) as isize - half_x, (tap / self.width) as isize - half_y);
+        let source = edges.resolve(x + dx, width).zip(edges.resolve(y + dy, height));
+
+        if let Some((sx, sy)) = source {
+          let sample = samples[sy * width + sx];
+          sum.iter_mut().zip(sample).for_each(|(sum, value)| *sum += value * weight);
+        }
+      }
+    }
+
+    out
+  }
+}
+
+impl Bitmap {
+  // Colors in 0 to 255, premultiplied so transparent pixels don't bleed.
+  fn filter_samples(&self) -> Vec<[f32; 4]> {
+    self.pixels.iter()
+      .map(|pixel| {
+        let color = self.color(*pixel);
+        let alpha = if self.alpha_mode == AlphaMode::Premultiplied { 1.0 } else { color.a as f32 / 255.0 };
+        [color.r as f32 * alpha, color.g as f32 * alpha, color.b as f32 * alpha, color.a as f32]
+      })
+      .collect()
+  }
+
+  // Inverse of `filter_samples`: 32-bpp images keep their alpha and
+  // `premultiplied` flag, everything else comes out 24-bpp.
+  fn filtered(&self, samples: Vec<[f32; 4]>) -> Bitmap {
+    let keep_alpha = self.header.bpp == 32;
+    let byte = |value: f32| (value.clamp(0.0, 255.0) + 0.5) as u8;
+
+    let pixels = samples.into_iter()
+      .map(|[r, g, b, a]| {
+        let alpha = a.clamp(0.0, 255.0) / 255.0;
+        let scale = if self.alpha_mode == AlphaMode::Premultiplied || !keep_alpha { 1.0 } else if alpha > 0.0 { 1.0 / alpha } else { 0.0 };
+
+        if keep_alpha {
+          Pixel::ABGR(byte(b * scale), byte(g * scale), byte(r * scale), byte(a))
+        } else {
+          Pixel::BGR(byte(b * scale), byte(g * scale), byte(r * scale))
+        }
+      })
+      .collect();
+
+    if keep_alpha {
+      self.with_pixels(self.width(), self.height(), pixels)
+    } else {
+      let pix_height = self.header.pix_height.signum() * self.height() as i32;
+      Bitmap::assemble(self.width(), pix_height, 24, Vec::new(), pixels)
+    }
+  }
+
+  // Filters the image through `kernel`, sampling past the borders per `edges`.
+  pub fn convolve(&self, kernel: &Kernel, edges: EdgeMode) -> Bitmap {
+    let (width, height) = (self.width() as usize, self.height() as usize);
+    let samples = kernel.apply(&self.filter_samples(), width, height, edges);
+    self.filtered(samples)
+  }
+
+  // Box blur over a (2 * radius + 1) square, run as two one-dimensional passes.
+  pub fn blur(&self, radius: usize) -> Bitmap {
+    let side = radius * 2 + 1;
+    let weights = vec![1.0 / side as f32; side];
+    let horizontal = Kernel { width: side, height: 1, weights: weights.clone() };
+    let vertical = Kernel { width: 1, height: side, weights };
+
+    let (width, height) = (self.width() as usize, self.height() as usize);
+    let samples = horizontal.apply(&self.filter_samples(), width, height, EdgeMode::Clamp);
+    let samples = vertical.apply(&samples, width, height, EdgeMode::Clamp);
+    self.filtered(samples)
+  }
+
+  pub fn sharpen(&self) -> Bitmap {
+    self.convolve(&Kernel::sharpen(), EdgeMode::Clamp)
+  }
+
+  // 8-bpp grayscale map of Sobel gradient magnitude over Rec. 709 luma,
+  // scaled so a hard black-to-white edge reads 255.
+  pub fn edges(&self) -> Bitmap {
+    let (width, height) = (self.width() as usize, self.height() as usize);
+    let lumas = self.pixels.iter().map(|pixel| LumaWeights::REC709.luma(self.straight_color(*pixel)) as i32).collect::<Vec<_>>();
+
+    let levels = (0 .. lumas.len()).map(|index| {
+      let (x, y) = ((index % width) as isize, (index / width) as isize);
+      let (mut gx, mut gy) = (0, 0);
+
+      for (dy, row) in SOBEL.iter().enumerate() {
+        for (dx, weight) in row.iter().enumerate() {
+          let sx = EdgeMode::Clamp.resolve(x + dx as isize - 1, width).unwrap_or(0);
+          let sy = EdgeMode::Clamp.resolve(y + dy as isize - 1, height).unwrap_or(0);
+          gx += weight * lumas[sy * width + sx];
+
+          let sx = EdgeMode::Clamp.resolve(x + dy as isize - 1, width).unwrap_or(0);
+          let sy = EdgeMode::Clamp.resolve(y + dx as isize - 1, height).unwrap_or(0);
+          gy += weight * lumas[sy * width + sx];
+        }
+      }
+
+      ((gx * gx + gy * gy) as u32).isqrt().div_ceil(4).min(255) as u8
+    });
+
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    gray_ramp(self.width(), pix_height, levels)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+  use alloc::vec::Vec;
+
+  use crate::{Bitmap, EdgeMode, Kernel, Pixel};
+
+  fn grays(width: u32, levels: &[u8]) -> Bitmap {
+    let pixels = levels.iter().map(|level| Pixel::BGR(*level, *level, *level)).collect();
+    Bitmap::from_parts(width, levels.len() as u32 / width, 24, Vec::new(), pixels).unwrap()
+  }
+
+  fn levels(bitmap: &Bitmap) -> Vec<u8> {
+    bitmap.pixels.iter().map(|pixel| bitmap.straight_color(*pixel).g).collect()
+  }
+
+  #[test]
+  fn box_blurs_sample_past_the_border_per_edge_mode() {
+    let bitmap = grays(3, &[0, 90, 180]);
+    let kernel = Kernel::box_blur(1);
+
+    assert_eq!(levels(&bitmap.convolve(&kernel, EdgeMode::Clamp)), [30, 90, 150]);
+    assert_eq!(levels(&bitmap.convolve(&kernel, EdgeMode::Wrap)), [90, 90, 90]);
+    assert_eq!(levels(&bitmap.convolve(&kernel, EdgeMode::Mirror)), [60, 90, 120]);
+    assert_eq!(levels(&bitmap.convolve(&kernel, EdgeMode::Transparent)), [10, 30, 30]);
+    assert_eq!(levels(&bitmap.blur(1)), [30, 90, 150]);
+
+    let flat = grays(3, &[77; 9]);
+    assert_eq!(levels(&flat.sharpen()), [77; 9]);
+    assert!(Kernel::new(2, 1, vec![0.5; 2]).is_err());
+  }
+
+  #[test]
+  fn blurs_without_bleeding_transparent_colors() {
+    let bitmap = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(0, 0, 255, 255), Pixel::ABGR(255, 0, 0, 0)]).unwrap();
+    assert_eq!(bitmap.blur(1).pixels, [Pixel::ABGR(0, 0, 255, 170), Pixel::ABGR(0, 0, 255, 85)]);
+  }
+
+  #[test]
+  fn finds_hard_edges() {
+    let edges = grays(4, &[0, 0, 255, 255].repeat(3)).edges();
+
+    assert_eq!(edges.header.bpp, 8);
+    assert_eq!(levels(&edges), [0, 255, 255, 0].repeat(3));
+    assert_eq!(levels(&grays(2, &[40; 4]).edges()), [0; 4]);
+  }
+}
@@ -0,0 +1,144 @@
+// Embedded 8x8 bitmap font for labelling images, from the public-domain
+// font8x8 glyphs (IBM VGA derived). Covers printable ASCII.
+
+use crate::draw::Rect;
+use crate::{Bitmap, Rgba};
+
+pub const GLYPH_SIZE: u32 = 8;
+
+// One byte per row, least significant bit leftmost, for ' ' ..= '~'.
+const GLYPHS: [[u8; 8]; 95] = [
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+  [0x18, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x18, 0x00],
+  [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+  [0x36, 0x36, 0x7f, 0x36, 0x7f, 0x36, 0x36, 0x00],
+  [0x0c, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x0c, 0x00],
+  [0x00, 0x63, 0x33, 0x18, 0x0c, 0x66, 0x63, 0x00],
+  [0x1c, 0x36, 0x1c, 0x6e, 0x3b, 0x33, 0x6e, 0x00],
+  [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+  [0x18, 0x0c, 0x06, 0x06, 0x06, 0x0c, 0x18, 0x00],
+  [0x06, 0x0c, 0x18, 0x18, 0x18, 0x0c, 0x06, 0x00],
+  [0x00, 0x66, 0x3c, 0xff, 0x3c, 0x66, 0x00, 0x00],
+  [0x00, 0x0c, 0x0c, 0x3f, 0x0c, 0x0c, 0x00, 0x00],
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x06],
+  [0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00],
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x00],
+  [0x60, 0x30, 0x18, 0x0c, 0x06, 0x03, 0x01, 0x00],
+  [0x3e, 0x63, 0x73, 0x7b, 0x6f, 0x67, 0x3e, 0x00],
+  [0x0c, 0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x3f, 0x00],
+  [0x1e, 0x33, 0x30, 0x1c, 0x06, 0x33, 0x3f, 0x00],
+  [0x1e, 0x33, 0x30, 0x1c, 0x30, 0x33, 0x1e, 0x00],
+  [0x38, 0x3c, 0x36, 0x33, 0x7f, 0x30, 0x78, 0x00],
+  [0x3f, 0x03, 0x1f, 0x30, 0x30, 0x33, 0x1e, 0x00],
+  [0x1c, 0x06, 0x03, 0x1f, 0x33, 0x33, 0x1e, 0x00],
+  [0x3f, 0x33, 0x30, 0x18, 0x0c, 0x0c, 0x0c, 0x00],
+  [0x1e, 0x33, 0x33, 0x1e, 0x33, 0x33, 0x1e, 0x00],
+  [0x1e, 0x33, 0x33, 0x3e, 0x30, 0x18, 0x0e, 0x00],
+  [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x00],
+  [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x06],
+  [0x18, 0x0c, 0x06, 0x03, 0x06, 0x0c, 0x18, 0x00],
+  [0x00, 0x00, 0x3f, 0x00, 0x00, 0x3f, 0x00, 0x00],
+  [0x06, 0x0c, 0x18, 0x30, 0x18, 0x0c, 0x06, 0x00],
+  [0x1e, 0x33, 0x30, 0x18, 0x0c, 0x00, 0x0c, 0x00],
+  [0x3e, 0x63, 0x7b, 0x7b, 0x7b, 0x03, 0x1e, 0x00],
+  [0x0c, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x00],
+  [0x3f, 0x66, 0x66, 0x3e, 0x66, 0x66, 0x3f, 0x00],
+  [0x3c, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3c, 0x00],
+  [0x1f, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1f, 0x00],
+  [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x46, 0x7f, 0x00],
+  [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x06, 0x0f, 0x00],
+  [0x3c, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7c, 0x00],
+  [0x33, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x33, 0x00],
+  [0x1e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00],
+  [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e, 0x00],
+  [0x67, 0x66, 0x36, 0x1e, 0x36, 0x66, 0x67, 0x00],
+  [0x0f, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7f, 0x00],
+  [0x63, 0x77, 0x7f, 0x7f, 0x6b, 0x63, 0x63, 0x00],
+  [0x63, 0x67, 0x6f, 0x7b, 0x73, 0x63, 0x63, 0x00],
+  [0x1c, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1c, 0x00],
+  [0x3f, 0x66, 0x66, 0x3e, 0x06, 0x06, 0x0f, 0x00],
+  [0x1e, 0x33, 0x33, 0x33, 0x3b, 0x1e, 0x38, 0x00],
+  [0x3f, 0x66, 0x66, 0x3e, 0x36, 0x66, 0x67, 0x00],
+  [0x1e, 0x33, 0x07, 0x0e, 0x38, 0x33, 0x1e, 0x00],
+  [0x3f, 0x2d, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00],
+  [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3f, 0x00],
+  [0x33, 0x33, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00],
+  [0x63, 0x63, 0x63, 0x6b, 0x7f, 0x77, 0x63, 0x00],
+  [0x63, 0x63, 0x36, 0x1c, 0x1c, 0x36, 0x63, 0x00],
+  [0x33, 0x33, 0x33, 0x1e, 0x0c, 0x0c, 0x1e, 0x00],
+  [0x7f, 0x63, 0x31, 0x18, 0x4c, 0x66, 0x7f, 0x00],
+  [0x1e, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1e, 0x00],
+  [0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x40, 0x00],
+  [0x1e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1e, 0x00],
+  [0x08, 0x1c, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00],
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff],
+  [0x0c, 0x0c, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00],
+  [0x00, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00],
+  [0x07, 0x06, 0x06, 0x3e, 0x66, 0x66, 0x3b, 0x00],
+  [0x00, 0x00, 0x1e, 0x33, 0x03, 0x33, 0x1e, 0x00],
+  [0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6e, 0x00],
+  [0x00, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00],
+  [0x1c, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0f, 0x00],
+  [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x1f],
+  [0x07, 0x06, 0x36, 0x6e, 0x66, 0x66, 0x67, 0x00],
+  [0x0c, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00],
+  [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e],
+  [0x07, 0x06, 0x66, 0x36, 0x1e, 0x36, 0x67, 0x00],
+  [0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00],
+  [0x00, 0x00, 0x33, 0x7f, 0x7f, 0x6b, 0x63, 0x00],
+  [0x00, 0x00, 0x1f, 0x33, 0x33, 0x33, 0x33, 0x00],
+  [0x00, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00],
+  [0x00, 0x00, 0x3b, 0x66, 0x66, 0x3e, 0x06, 0x0f],
+  [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x78],
+  [0x00, 0x00, 0x3b, 0x6e, 0x66, 0x06, 0x0f, 0x00],
+  [0x00, 0x00, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x00],
+  [0x08, 0x0c, 0x3e, 0x0c, 0x0c, 0x2c, 0x18, 0x00],
+  [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00],
+  [0x00, 0x00, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00],
+  [0x00, 0x00, 0x63, 0x6b, 0x7f, 0x7f, 0x36, 0x00],
+  [0x00, 0x00, 0x63, 0x36, 0x1c, 0x36, 0x63, 0x00],
+  [0x00, 0x00, 0x33, 0x33, 0x33, 0x3e, 0x30, 0x1f],
+  [0x00, 0x00, 0x3f, 0x19, 0x0c, 0x26, 0x3f, 0x00],
+  [0x38, 0x0c, 0x0c, 0x07, 0x0c, 0x0c, 0x38, 0x00],
+  [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00],
+  [0x07, 0x0c, 0x0c, 0x38, 0x0c, 0x0c, 0x07, 0x00],
+  [0x6e, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+];
+
+impl Bitmap {
+  // Draws `text` with its top-left corner at (x, y), clipped to the image.
+  // Newlines start a new line; characters outside printable ASCII draw as '?'.
+  pub fn draw_text(&mut self, x: i64, y: i64, text: &str, color: Rgba) {
+    let pixel = self.pixel_for(color);
+    let (mut pen_x, mut pen_y) = (x, y);
+
+    for ch in text.chars() {
+      if ch == '\n' {
+        (pen_x, pen_y) = (x, pen_y + GLYPH_SIZE as i64);
+        continue
+      }
+
+      let index = match ch {
+        ' ' ..= '~' => ch as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize
+      };
+
+      for (row, bits) in GLYPHS[index].iter().enumerate() {
+        for column in 0 .. GLYPH_SIZE as i64 {
+          if bits >> column & 1 == 1 {
+            self.plot(pen_x + column, pen_y + row as i64, pixel);
+          }
+        }
+      }
+
+      pen_x += GLYPH_SIZE as i64;
+    }
+  }
+}
+
+// Size `draw_text` would cover for `text`, as a rectangle at the origin.
+pub fn text_bounds(text: &str) -> Rect {
+  let columns = text.split('\n').map(|line| line.chars().count()).max().unwrap_or(0);
+  let rows = text.split('\n').count();
+  Rect::new(0, 0, columns as u32 * GLYPH_SIZE, rows as u32 * GLYPH_SIZE)
+}
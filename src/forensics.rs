@@ -0,0 +1,93 @@
+// Bytes of a file that the format doesn't account for, where payloads hide.
+
+use core::ops::Range;
+
+use crate::{Bitmap, BitmapResult, CompressionMethod, Decoder, FILE_HEADER_SIZE};
+
+// Findings of `forensics`; empty ranges and zero counts mean nothing unusual.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ForensicReport {
+  // Bytes after the pixel array.
+  pub trailing: Range<usize>,
+  // Bytes between the end of the palette (or masks) and the pixel array.
+  pub gap: Range<usize>,
+  // Palette entries that no pixel value of this depth can reach.
+  pub excess_palette_entries: usize,
+  // `bfReserved1` and `bfReserved2` from the file header.
+  pub file_reserved: [u16; 2],
+  // `bV5Reserved`, for headers long enough to have it.
+  pub header_reserved: u32,
+  // Palette entries whose fourth byte isn't zero.
+  pub palette_reserved_bytes: usize,
+  // Row padding bytes that aren't zero.
+  pub padding_bytes: usize,
+  // Whether `bfSize` disagrees with the actual length of the file.
+  pub size_mismatch: bool
+}
+
+impl ForensicReport {
+  pub fn is_clean(&self) -> bool {
+    self.trailing.is_empty()
+      && self.gap.is_empty()
+      && self.excess_palette_entries == 0
+      && self.file_reserved == [0, 0]
+      && self.header_reserved == 0
+      && self.palette_reserved_bytes == 0
+      && self.padding_bytes == 0
+      && !self.size_mismatch
+  }
+}
+
+impl Bitmap {
+  // Examines the file bytes in `data` (the parsed file, or the last
+  // encoding) for content a decoder would skip over.
+  pub fn forensics(&self) -> BitmapResult<ForensicReport> {
+    let data = &self.data[..];
+    let decoder = Decoder::new(data)?;
+    let header_end = FILE_HEADER_SIZE + decoder.header.size as usize;
+
+    // Bitfield masks follow a 40-byte header rather than sitting inside it.
+    let masks = match decoder.header.method {
+      CompressionMethod::Huffman1D if decoder.header.size == 40 => 12,
+      _ => 0
+    };
+    let palette_start = header_end + masks;
+    let palette_end = (palette_start + decoder.palette_len() * 4).min(decoder.file_header.pixel_offset as usize).max(palette_start);
+    let palette_end = palette_end.min(data.len());
+
+    let reachable = if decoder.header.bpp <= 8 { 1 << decoder.header.bpp } else { 0 };
+    let palette_reserved_bytes = data.get(palette_start .. palette_end)
+      .map_or(0, |palette| palette.chunks_exact(4).filter(|quad| quad[3] != 0).count());
+
+    let row_len = (decoder.width() as usize * decoder.header.bpp as usize).div_ceil(8);
+    let padding_bytes = (0 .. decoder.height())
+      .filter_map(|y| decoder.row_bytes(y))
+      .map(|row| row[row_len ..].iter().filter(|byte| **byte != 0).count())
+      .sum();
+
+    let image_end = decoder.image_end();
+    Ok(ForensicReport {
+      trailing: image_end .. data.len(),
+      gap: palette_end .. (decoder.file_header.pixel_offset as usize).max(palette_end),
+      excess_palette_entries: decoder.palette_len().saturating_sub(reachable),
+      file_reserved: [decoder.file_header.reserved1, decoder.file_header.reserved2],
+      header_reserved: decoder.header.reserved,
+      palette_reserved_bytes,
+      padding_bytes,
+      size_mismatch: decoder.file_header.file_size as usize != data.len()
+    })
+  }
+
+  // Minimal canonical copy with nothing for `forensics` to find: a 40-byte
+  // header, zeroed reserved fields and padding, only the reachable palette
+  // entries and no gaps or trailing bytes. V4/V5 color-space fields go too.
+  pub fn sanitized(&self) -> Bitmap {
+    let reachable = if self.header.bpp <= 8 { 1 << self.header.bpp } else { 0 };
+    let palette = self.palette.iter().take(reachable).copied().collect();
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+
+    let mut bitmap = Bitmap::assemble(self.width(), pix_height, self.header.bpp, palette, self.pixels.clone());
+    bitmap.alpha_mode = self.alpha_mode;
+    bitmap
+  }
+}
@@ -0,0 +1,177 @@
+// Content and perceptual hashes for finding identical or near-identical images.
+
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+use crate::{Bitmap, LumaWeights};
+
+// Number of bits that differ between two hashes; small distances mean
+// similar images.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+  (a ^ b).count_ones()
+}
+
+// 64-bit FNV-1a parameters.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// Bitmaps are equal when they have the same dimensions, depth and alpha
+// mode and hold the same pixels, and for indexed images the same palette.
+// Row order, padding and the original file bytes don't matter; see
+// `same_image` for matching what images look like.
+impl PartialEq for Bitmap {
+  fn eq(&self, other: &Bitmap) -> bool {
+    (self.width(), self.height(), self.header.bpp, self.alpha_mode) == (other.width(), other.height(), other.header.bpp, other.alpha_mode)
+      && (self.header.bpp > 8 || self.palette == other.palette)
+      && self.pixels == other.pixels
+  }
+}
+
+impl Eq for Bitmap {}
+
+impl Hash for Bitmap {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    (self.width(), self.height(), self.header.bpp, self.alpha_mode).hash(state);
+    if self.header.bpp <= 8 {
+      self.palette.hash(state);
+    }
+    self.pixels.hash(state);
+  }
+}
+
+impl Bitmap {
+  // FNV-1a hash of the dimensions and the resolved top-down RGBA pixels.
+  // Files that render identically hash alike whatever their header version,
+  // depth, palette order, padding or row order. Stable across releases, but
+  // not collision resistant against deliberate tampering. Like compare and
+  // histogram, this works on straight colors, the ones an encoder would
+  // write, so a premultiplied copy of a bitmap hashes like the original up
+  // to rounding.
+  pub fn pixel_digest(&self) -> u64 {
+    let dimensions = self.width().to_le_bytes().into_iter().chain(self.height().to_le_bytes());
+    let colors = self.pixels.iter().flat_map(|pixel| {
+      let color = self.straight_color(*pixel);
+      [color.r, color.g, color.b, color.a]
+    });
+
+    dimensions.chain(colors).fold(FNV_OFFSET, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+  }
+
+  // Mean luma of each cell when the image is cut into `columns` x `rows`,
+  // row-major. Images smaller than the grid reuse pixels across cells.
+  fn luma_grid(&self, columns: u32, rows: u32) -> Vec<f32> {
+    let (width, height) = (self.width() as u64, self.height() as u64);
+    let span = |cell: u32, cells: u32, len: u64| {
+      let start = cell as u64 * len / cells as u64;
+      start .. ((cell as u64 + 1) * len / cells as u64).max(start + 1)
+    };
+
+    let mut grid = Vec::with_capacity(columns as usize * rows as usize);
+    for row in 0 .. rows {
+      for column in 0 .. columns {
+        let (xs, ys) = (span(column, columns, width), span(row, rows, height));
+        let count = (xs.end - xs.start) * (ys.end - ys.start);
+        let sum = ys
+          .flat_map(|y| xs.clone().map(move |x| (x, y)))
+          .filter_map(|(x, y)| self.pixel(x as u32, y as u32))
+          .map(|pixel| LumaWeights::REC709.luma(self.straight_color(*pixel)) as u64)
+          .sum::<u64>();
+        grid.push(sum as f32 / count as f32);
+      }
+    }
+
+    grid
+  }
+
+  // Difference hash: bit set where a cell of a 9x8 luma grid is brighter
+  // than its right-hand neighbour. Cheap, and robust to scaling and
+  // gamma changes.
+  pub fn dhash(&self) -> u64 {
+    let grid = self.luma_grid(9, 8);
+    (0 .. 64).fold(0, |hash, bit| {
+      let at = bit / 8 * 9 + bit % 8;
+      hash << 1 | (grid[at] > grid[at + 1]) as u64
+    })
+  }
+
+  // DCT hash: bit set where a low-frequency coefficient of a 32x32 luma
+  // grid exceeds their median. Slower than `dhash` but tolerates small
+  // crops, blur and recompression better.
+  #[cfg(feature = "std")]
+  pub fn phash(&self) -> u64 {
+    const SIZE: usize = 32;
+    let grid = self.luma_grid(SIZE as u32, SIZE as u32);
+    let basis = (0 .. 8)
+      .map(|frequency| {
+        (0 .. SIZE)
+          .map(|at| (core::f32::consts::PI * frequency as f32 * (at as f32 + 0.5) / SIZE as f32).cos())
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    let coefficients = (0 .. 64)
+      .map(|index| {
+        let (u, v) = (index % 8, index / 8);
+        (0 .. SIZE * SIZE)
+          .map(|at| grid[at] * basis[u][at % SIZE] * basis[v][at / SIZE])
+          .sum::<f32>()
+      })
+      .collect::<Vec<_>>();
+
+    // The DC term only tracks overall brightness, so it is left out of the median.
+    let mut sorted = coefficients[1 ..].to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    coefficients.iter().fold(0, |hash, coefficient| hash << 1 | (*coefficient > median) as u64)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec::Vec;
+
+  use crate::hash::hamming_distance;
+  use crate::{Bitmap, Pixel};
+
+  // A `width` x 24 image whose gray level falls steadily from left to right.
+  fn ramp(width: u32) -> Bitmap {
+    let pixels = (0 .. 24).flat_map(|_| (0 .. width).map(|x| 255 - (x * 200 / width) as u8)).map(|level| Pixel::BGR(level, level, level)).collect();
+    Bitmap::from_parts(width, 24, 24, Vec::new(), pixels).unwrap()
+  }
+
+  #[test]
+  fn digests_depend_only_on_the_colors() {
+    let bitmap = ramp(9);
+    let opaque = bitmap.pixels.iter().map(|pixel| match pixel {
+      Pixel::BGR(b, g, r) => Pixel::ABGR(*b, *g, *r, 255),
+      other => *other
+    });
+    let widened = Bitmap::from_parts(9, 24, 32, Vec::new(), opaque.collect()).unwrap();
+    assert_eq!(widened.pixel_digest(), bitmap.pixel_digest());
+
+    let mut changed = bitmap.clone();
+    changed.pixels[100] = Pixel::BGR(0, 0, 1);
+    assert_ne!(changed.pixel_digest(), bitmap.pixel_digest());
+    assert_ne!(ramp(9).rotate90().pixel_digest(), bitmap.pixel_digest());
+  }
+
+  #[test]
+  fn difference_hashes_follow_the_gradient() {
+    // Every cell is brighter than the one to its right.
+    assert_eq!(ramp(90).dhash(), u64::MAX);
+    assert_eq!(ramp(90).flipped_horizontal().dhash(), 0);
+    assert_eq!(ramp(90).resize_nearest(180, 48).unwrap().dhash(), u64::MAX);
+    assert_eq!(hamming_distance(ramp(90).dhash(), ramp(9).dhash()), 0);
+  }
+
+  #[cfg(feature = "std")]
+  #[test]
+  fn dct_hashes_survive_scaling() {
+    let bitmap = ramp(64);
+    let hash = bitmap.phash();
+
+    assert!(hamming_distance(hash, bitmap.resize_nearest(128, 48).unwrap().phash()) <= 2);
+    assert!(hamming_distance(hash, bitmap.rotate90().phash()) >= 8);
+  }
+}
@@ -0,0 +1,179 @@
+// Color statistics over decoded pixels, taken on straight colors whatever
+// `alpha_mode` a bitmap is in, so they describe what an encoder would write.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Bitmap, Pixel, Rgba};
+
+// Occurrences of every 8-bit value in each channel, with palettes resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+  pub red: [u32; 256],
+  pub green: [u32; 256],
+  pub blue: [u32; 256],
+  pub alpha: [u32; 256],
+  // Uses of each palette entry, for indexed images only.
+  pub indices: Option<Vec<u32>>
+}
+
+// A representative color and the fraction of pixels, 0 to 1, near it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DominantColor {
+  pub color: Rgba,
+  pub coverage: f32
+}
+
+// Spread of one channel's values over every pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChannelStats {
+  pub min: u8,
+  pub max: u8,
+  pub mean: f32,
+  // Population standard deviation.
+  pub stddev: f32
+}
+
+// Summary from `Bitmap::stats`, with palettes resolved. All zeros for
+// images without pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImageStats {
+  pub red: ChannelStats,
+  pub green: ChannelStats,
+  pub blue: ChannelStats,
+  pub alpha: ChannelStats,
+  // Fraction of pixels, 0 to 1, with zero alpha.
+  pub transparent: f32
+}
+
+impl Bitmap {
+  // Per-channel minimum, maximum, mean and spread in one pass, without
+  // building a histogram.
+  #[cfg(feature = "std")]
+  pub fn stats(&self) -> ImageStats {
+    if self.pixels.is_empty() {
+      return ImageStats::default()
+    }
+
+    // Per channel: min, max, sum and sum of squares, all exact.
+    let mut totals = [[255u64, 0, 0, 0]; 4];
+    let mut transparent = 0u64;
+    for pixel in &self.pixels {
+      let color = self.straight_color(*pixel);
+      for (total, value) in totals.iter_mut().zip([color.r, color.g, color.b, color.a]) {
+        let value = value as u64;
+        *total = [total[0].min(value), total[1].max(value), total[2] + value, total[3] + value * value];
+      }
+      transparent += (color.a == 0) as u64;
+    }
+
+    let count = self.pixels.len() as f64;
+    let channel = |[min, max, sum, squares]: [u64; 4]| {
+      let mean = sum as f64 / count;
+      let variance = (squares as f64 / count - mean * mean).max(0.0);
+      ChannelStats { min: min as u8, max: max as u8, mean: mean as f32, stddev: variance.sqrt() as f32 }
+    };
+    ImageStats {
+      red: channel(totals[0]),
+      green: channel(totals[1]),
+      blue: channel(totals[2]),
+      alpha: channel(totals[3]),
+      transparent: (transparent as f64 / count) as f32
+    }
+  }
+
+  pub fn histogram(&self) -> Histogram {
+    let mut histogram = Histogram {
+      red: [0; 256],
+      green: [0; 256],
+      blue: [0; 256],
+      alpha: [0; 256],
+      indices: if self.header.bpp <= 8 { Some(vec![0; 1 << self.header.bpp]) } else { None }
+    };
+
+    for pixel in &self.pixels {
+      let color = self.straight_color(*pixel);
+      histogram.red[color.r as usize] += 1;
+      histogram.green[color.g as usize] += 1;
+      histogram.blue[color.b as usize] += 1;
+      histogram.alpha[color.a as usize] += 1;
+
+      if let (Pixel::PaletteColor(index), Some(indices)) = (pixel, &mut histogram.indices) {
+        if let Some(count) = indices.get_mut(*index as usize) {
+          *count += 1;
+        }
+      }
+    }
+
+    histogram
+  }
+
+  // Number of distinct RGBA colors among the pixels.
+  pub fn unique_colors(&self) -> usize {
+    let mut colors = self.pixels.iter()
+      .map(|pixel| {
+        let color = self.straight_color(*pixel);
+        u32::from_be_bytes([color.r, color.g, color.b, color.a])
+      })
+      .collect::<Vec<_>>();
+
+    colors.sort_unstable();
+    colors.dedup();
+    colors.len()
+  }
+
+  // Up to `k` representative colors, most common first. Colors are binned
+  // at 4 bits per channel, the fullest bins seed the result, and a few
+  // weighted k-means passes over all bins settle the means and coverage.
+  pub fn dominant_colors(&self, k: usize) -> Vec<DominantColor> {
+    if k == 0 || self.pixels.is_empty() {
+      return Vec::new()
+    }
+
+    // Per bin: pixel count and channel sums.
+    let mut bins = vec![[0u64; 4]; 4096];
+    for pixel in &self.pixels {
+      let color = self.straight_color(*pixel);
+      let bin = &mut bins[(color.r as usize >> 4) << 8 | (color.g as usize >> 4) << 4 | color.b as usize >> 4];
+      bin[0] += 1;
+      bin[1] += color.r as u64;
+      bin[2] += color.g as u64;
+      bin[3] += color.b as u64;
+    }
+
+    let mut bins = bins.into_iter().filter(|bin| bin[0] > 0).collect::<Vec<_>>();
+    bins.sort_unstable_by(|a, b| b[0].cmp(&a[0]));
+
+    let mean = |bin: &[u64; 4]| [bin[1], bin[2], bin[3]].map(|sum| ((sum + bin[0] / 2) / bin[0]) as i32);
+    let mut centres = bins.iter().take(k).map(mean).collect::<Vec<_>>();
+    let mut totals = Vec::new();
+
+    for _ in 0 .. 4 {
+      totals = vec![[0u64; 4]; centres.len()];
+      for bin in &bins {
+        let color = mean(bin);
+        let nearest = centres.iter()
+          .enumerate()
+          .min_by_key(|(_, centre)| (0 .. 3).map(|at| (centre[at] - color[at]).pow(2)).sum::<i32>())
+          .map_or(0, |(index, _)| index);
+
+        totals[nearest].iter_mut().zip(bin).for_each(|(total, value)| *total += value);
+      }
+
+      centres = totals.iter().zip(&centres).map(|(total, centre)| if total[0] > 0 { mean(total) } else { *centre }).collect();
+    }
+
+    let count = self.pixels.len() as f32;
+    let mut colors = centres.iter()
+      .zip(&totals)
+      .filter(|(_, total)| total[0] > 0)
+      .map(|(centre, total)| DominantColor {
+        color: Rgba::new(centre[0] as u8, centre[1] as u8, centre[2] as u8, 255),
+        coverage: total[0] as f32 / count
+      })
+      .collect::<Vec<_>>();
+
+    colors.sort_by(|a, b| b.coverage.total_cmp(&a.coverage));
+    colors
+  }
+}
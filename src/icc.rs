@@ -0,0 +1,43 @@
+// Color management of embedded ICC profiles through moxcms.
+
+use alloc::vec;
+
+use moxcms::{ColorProfile, Layout, TransformOptions};
+
+use crate::{Bitmap, RenderingIntent};
+
+impl From<RenderingIntent> for moxcms::RenderingIntent {
+  fn from(intent: RenderingIntent) -> moxcms::RenderingIntent {
+    match intent {
+      RenderingIntent::Perceptual => moxcms::RenderingIntent::Perceptual,
+      RenderingIntent::RelativeColorimetric => moxcms::RenderingIntent::RelativeColorimetric,
+      RenderingIntent::Saturation => moxcms::RenderingIntent::Saturation,
+      RenderingIntent::AbsoluteColorimetric => moxcms::RenderingIntent::AbsoluteColorimetric
+    }
+  }
+}
+
+impl Bitmap {
+  // Converts RGBA bytes from the embedded profile to sRGB in place. Files
+  // without a profile, or with one moxcms can't use, are left untouched;
+  // a missing intent means perceptual, as it does for ICC itself.
+  pub(crate) fn apply_profile(&self, rgba: &mut [u8]) {
+    let Some(profile) = self.embedded_profile() else { return };
+    let Ok(source) = ColorProfile::new_from_slice(profile) else { return };
+
+    let options = TransformOptions {
+      rendering_intent: self.rendering_intent().unwrap_or(RenderingIntent::Perceptual).into(),
+      ..TransformOptions::default()
+    };
+    let Ok(transform) = source.create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, options) else {
+      return
+    };
+
+    let mut converted = vec![0; rgba.len()];
+    if transform.transform(rgba, &mut converted).is_err() {
+      return
+    }
+
+    rgba.copy_from_slice(&converted);
+  }
+}
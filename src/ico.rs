@@ -0,0 +1,407 @@
+// Windows icon (.ico) and cursor (.cur) containers: a directory of DIB or PNG images.
+
+use alloc::vec::Vec;
+
+use crate::decoder::{read_i32, read_u16, read_u32, row_stride, FILE_HEADER_SIZE};
+use crate::masks::masks_len;
+use crate::{AlphaMode, Bitmap, BitmapError, BitmapResult, ErrorContext, Pixel, Rgba};
+
+const DIR_SIZE: usize = 6;
+const ENTRY_SIZE: usize = 16;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// AND mask colors: set bits mark transparent (or, over a black color
+// pixel, screen-inverting) pixels.
+const MASK_PALETTE: [Rgba; 2] = [Rgba::BLACK, Rgba::new(255, 255, 255, 255)];
+
+// One ICONDIRENTRY. Zero widths and heights in the file mean 256. Cursors
+// store the hotspot in place of `planes` (x) and `bpp` (y).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcoEntry {
+  pub width: u32,
+  pub height: u32,
+  pub color_count: u8,
+  pub planes: u16,
+  pub bpp: u16,
+  pub size: u32,
+  pub offset: u32
+}
+
+#[derive(Debug, Clone)]
+pub struct Ico {
+  pub resource_type: u16,
+  pub entries: Vec<IcoEntry>,
+  data: Vec<u8>
+}
+
+impl Ico {
+  pub const ICON: u16 = 1;
+  pub const CURSOR: u16 = 2;
+
+  pub fn from_bytes(bytes: &[u8]) -> BitmapResult<Ico> {
+    Ico::from_vec(bytes.to_vec())
+  }
+
+  pub fn from_vec(data: Vec<u8>) -> BitmapResult<Ico> {
+    let (reserved, resource_type, count) = (read_u16(&data, 0)?, read_u16(&data, 2)?, read_u16(&data, 4)?);
+    if reserved != 0 || !(Ico::ICON ..= Ico::CURSOR).contains(&resource_type) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let entries = (0 .. count as usize)
+      .map(|index| {
+        let at = DIR_SIZE + index * ENTRY_SIZE;
+        let entry = data.get(at .. at + ENTRY_SIZE).ok_or(BitmapError::InvalidBitmapData)?;
+        let dimension = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+
+        let entry = IcoEntry {
+          width: dimension(entry[0]),
+          height: dimension(entry[1]),
+          color_count: entry[2],
+          planes: read_u16(entry, 4)?,
+          bpp: read_u16(entry, 6)?,
+          size: read_u32(entry, 8)?,
+          offset: read_u32(entry, 12)?
+        };
+
+        let end = (entry.offset as usize).checked_add(entry.size as usize);
+        if end.is_none_or(|end| end > data.len()) {
+          return Err(BitmapError::InvalidBitmapData)
+        }
+
+        Ok(entry)
+      })
+      .collect::<BitmapResult<Vec<_>>>()?;
+
+    Ok(Ico { resource_type, entries, data })
+  }
+
+  pub fn is_cursor(&self) -> bool {
+    self.resource_type == Ico::CURSOR
+  }
+
+  // Hotspot of cursor entry `index`, in pixels from the top-left corner.
+  pub fn hotspot(&self, index: usize) -> Option<(u16, u16)> {
+    let entry = self.entries.get(index).filter(|_| self.is_cursor())?;
+    Some((entry.planes, entry.bpp))
+  }
+
+  // Moves the hotspot of cursor entry `index`; returns false for icons or
+  // out-of-range entries.
+  pub fn set_hotspot(&mut self, index: usize, x: u16, y: u16) -> bool {
+    let is_cursor = self.is_cursor();
+    match self.entries.get_mut(index).filter(|_| is_cursor) {
+      Some(entry) => {
+        (entry.planes, entry.bpp) = (x, y);
+        true
+      }
+      None => false
+    }
+  }
+
+  // Raw image bytes of entry `index`: a headerless DIB or a complete PNG.
+  pub fn entry_data(&self, index: usize) -> Option<&[u8]> {
+    let entry = self.entries.get(index)?;
+    let start = entry.offset as usize;
+    self.data.get(start .. start.checked_add(entry.size as usize)?)
+  }
+
+  // Re-serializes the directory with the current entry fields, packing the
+  // image data of each entry right after it.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut out = Vec::with_capacity(self.data.len());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&self.resource_type.to_le_bytes());
+    out.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+
+    let mut offset = DIR_SIZE + self.entries.len() * ENTRY_SIZE;
+    for (index, entry) in self.entries.iter().enumerate() {
+      let size = self.entry_data(index).map_or(0, <[u8]>::len);
+      out.extend_from_slice(&[entry.width as u8, entry.height as u8, entry.color_count, 0]);
+      out.extend_from_slice(&entry.planes.to_le_bytes());
+      out.extend_from_slice(&entry.bpp.to_le_bytes());
+      out.extend_from_slice(&(size as u32).to_le_bytes());
+      out.extend_from_slice(&(offset as u32).to_le_bytes());
+      offset += size;
+    }
+
+    for index in 0 .. self.entries.len() {
+      out.extend_from_slice(self.entry_data(index).unwrap_or_default());
+    }
+
+    out
+  }
+
+  pub fn is_png(&self, index: usize) -> bool {
+    self.entry_data(index).is_some_and(|data| data.starts_with(PNG_SIGNATURE))
+  }
+
+  // Decodes the color (XOR) image of a DIB entry. PNG entries are left to the
+  // caller via `entry_data` and report `UnsupportedCompression`.
+  pub fn bitmap(&self, index: usize) -> BitmapResult<Bitmap> {
+    let data = self.entry_data(index).ok_or(BitmapError::InvalidBitmapData)?;
+    if data.starts_with(PNG_SIGNATURE) {
+      return Err(BitmapError::UnsupportedCompression)
+    }
+
+    dib_to_file(data).and_then(Bitmap::from_vec).map_err(|err| err.context(ErrorContext::IconEntry(index)))
+  }
+
+  // The 1-bpp AND mask of a DIB entry, if the file includes one.
+  pub fn mask(&self, index: usize) -> BitmapResult<Option<Bitmap>> {
+    let data = self.entry_data(index).ok_or(BitmapError::InvalidBitmapData)?;
+    if data.starts_with(PNG_SIGNATURE) {
+      return Err(BitmapError::UnsupportedCompression)
+    }
+
+    Bitmap::from_icon_dib(data).map(|(_, mask)| mask)
+  }
+
+  // Draws entry `index` over `background` as Windows does; see
+  // `Bitmap::composite_icon`. PNG entries report `UnsupportedCompression`.
+  pub fn composite_icon(&self, index: usize, background: &Bitmap) -> BitmapResult<Bitmap> {
+    let data = self.entry_data(index).ok_or(BitmapError::InvalidBitmapData)?;
+    if data.starts_with(PNG_SIGNATURE) {
+      return Err(BitmapError::UnsupportedCompression)
+    }
+
+    let (color, mask) = Bitmap::from_icon_dib(data).map_err(|err| err.context(ErrorContext::IconEntry(index)))?;
+    Ok(color.composite_icon(mask.as_ref(), background))
+  }
+
+  pub fn bitmaps(&self) -> impl Iterator<Item = BitmapResult<Bitmap>> + '_ {
+    (0 .. self.entries.len()).map(move |index| self.bitmap(index))
+  }
+
+  // Color depth of entry `index`, from the image itself: directory depths
+  // are often zero, and cursors keep the hotspot there. PNG entries count
+  // as 32-bpp.
+  pub fn entry_bpp(&self, index: usize) -> Option<u16> {
+    let data = self.entry_data(index)?;
+    if data.starts_with(PNG_SIGNATURE) {
+      return Some(32)
+    }
+
+    read_u16(data, 14).ok()
+  }
+
+  // Index of the entry Windows would load for a `width` x `height` icon on
+  // a display of `bpp_preference` bits: the size closest to the request,
+  // counting both dimensions and preferring larger sizes on ties since
+  // shrinking looks better than enlarging, then among those the deepest
+  // entry within `bpp_preference`, or the shallowest above it. Earlier
+  // entries win remaining ties. `None` only for empty directories.
+  pub fn select_best(&self, width: u32, height: u32, bpp_preference: u16) -> Option<usize> {
+    let size_score = |entry: &IcoEntry| {
+      let distance = entry.width.abs_diff(width) + entry.height.abs_diff(height);
+      (distance, core::cmp::Reverse(entry.width + entry.height))
+    };
+    let best_size = self.entries.iter().map(size_score).min()?;
+
+    let depth_score = |index: usize| {
+      let bpp = self.entry_bpp(index).unwrap_or(0);
+      if bpp <= bpp_preference { (0, bpp_preference - bpp) } else { (1, bpp - bpp_preference) }
+    };
+    (0 .. self.entries.len())
+      .filter(|index| size_score(&self.entries[*index]) == best_size)
+      .min_by_key(|index| depth_score(*index))
+  }
+}
+
+// Prefixes a BITMAPFILEHEADER and halves the height, which icon DIBs store
+// doubled to cover the AND mask that follows the color image.
+pub(crate) fn dib_to_file(dib: &[u8]) -> BitmapResult<Vec<u8>> {
+  let header_size = read_u32(dib, 0)?;
+  let height = read_i32(dib, 8)?;
+  let bpp = read_u16(dib, 14)?;
+  let method = read_u32(dib, 16)?;
+  let colors = read_u32(dib, 32)?;
+
+  let palette_len = match (colors, bpp) {
+    (0, bpp) if bpp <= 8 => 1 << bpp,
+    (n, _) => n
+  };
+  let masks = masks_len(header_size, method) as u32;
+  let offset = palette_len.checked_mul(4)
+    .and_then(|palette| palette.checked_add(FILE_HEADER_SIZE as u32 + masks))
+    .and_then(|len| len.checked_add(header_size))
+    .ok_or(BitmapError::InvalidBitmapData)?;
+
+  let mut file = Vec::with_capacity(FILE_HEADER_SIZE + dib.len());
+  file.extend_from_slice(b"BM");
+  file.extend_from_slice(&((FILE_HEADER_SIZE + dib.len()) as u32).to_le_bytes());
+  file.extend_from_slice(&[0; 4]);
+  file.extend_from_slice(&offset.to_le_bytes());
+  file.extend_from_slice(dib);
+  file[FILE_HEADER_SIZE + 8 .. FILE_HEADER_SIZE + 12].copy_from_slice(&(height / 2).to_le_bytes());
+
+  Ok(file)
+}
+
+impl Bitmap {
+  // Draws this icon color image over the top-left of `background` the way
+  // Windows renders icons, returning the result at the background's size.
+  // 32-bpp images with any alpha are blended source-over and the mask is
+  // ignored. Otherwise the background is ANDed with `mask`, keeping it
+  // where mask bits are set, and XORed with the colors, so set bits over
+  // black leave the background alone and set bits over color invert it.
+  // No mask means every pixel is opaque. Indexed backgrounds become 24-bpp.
+  pub fn composite_icon(&self, mask: Option<&Bitmap>, background: &Bitmap) -> Bitmap {
+    let mut out = background.clone();
+    let mode = out.alpha_mode;
+    out.unpremultiply_alpha();
+    out.promote_truecolor();
+
+    let blend = self.header.bpp == 32 && self.pixels.iter().any(|pixel| matches!(pixel, Pixel::ABGR(_, _, _, alpha) if *alpha != 0));
+    let (width, height) = (self.width().min(out.width()), self.height().min(out.height()));
+    for y in 0 .. height {
+      for x in 0 .. width {
+        let (Some(source), Some(target)) = (self.pixel(x, y), out.pixel(x, y)) else {
+          continue
+        };
+        let (source, target) = (self.straight_color(*source), out.color(*target));
+
+        let color = if blend {
+          let alpha = source.a as u32;
+          let mix = |from: u8, to: u8| ((from as u32 * alpha + to as u32 * (255 - alpha) + 127) / 255) as u8;
+          let covered = alpha + (target.a as u32 * (255 - alpha) + 127) / 255;
+          Rgba::new(mix(source.r, target.r), mix(source.g, target.g), mix(source.b, target.b), covered as u8)
+        } else {
+          let keep = mask.and_then(|mask| mask.pixel(x, y).map(|bit| mask.color(*bit).r != 0)).unwrap_or(false);
+          let and = if keep { 0xff } else { 0 };
+          let alpha = if keep { target.a } else { 255 };
+          Rgba::new(target.r & and ^ source.r, target.g & and ^ source.g, target.b & and ^ source.b, alpha)
+        };
+        out.set_pixel(x, y, out.pixel_for(color));
+      }
+    }
+
+    if mode == AlphaMode::Premultiplied {
+      out.premultiply_alpha();
+    }
+    out
+  }
+
+  // Splits a headerless icon DIB, whose height covers the color (XOR) image
+  // and the AND mask stacked after it, into the color image and the mask.
+  // The mask is `None` when the data stops after the color image, as some
+  // 32-bpp icons do.
+  pub fn from_icon_dib(dib: &[u8]) -> BitmapResult<(Bitmap, Option<Bitmap>)> {
+    let color = Bitmap::from_vec(dib_to_file(dib)?)?;
+    let (width, height) = (color.width(), color.height());
+
+    let mask_start = color.file_header.pixel_offset as usize - FILE_HEADER_SIZE + color.encoded_stride() * height as usize;
+    let stride = row_stride(width, 1).ok_or(BitmapError::DimensionOverflow)?;
+    let Some(rows) = dib.get(mask_start .. mask_start + stride * height as usize) else {
+      return Ok((color, None))
+    };
+
+    let top_down = color.header.pix_height < 0;
+    let pixels = (0 .. height)
+      .flat_map(|y| {
+        let stored = if top_down { y } else { height - 1 - y };
+        let row = &rows[stored as usize * stride ..];
+        (0 .. width as usize).map(move |x| Pixel::PaletteColor(row[x / 8] >> (7 - x % 8) & 1))
+      })
+      .collect();
+
+    let mask = Bitmap::assemble(width, color.header.pix_height, 1, MASK_PALETTE.to_vec(), pixels);
+    Ok((color, Some(mask)))
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use alloc::vec;
+  use alloc::vec::Vec;
+
+  use crate::decoder::{row_stride, FILE_HEADER_SIZE};
+  use crate::encode::tests::sample;
+  #[cfg(feature = "bitfields")]
+  use crate::{ico::dib_to_file, incremental::tests::alpha_bitfields};
+  use crate::{Bitmap, Ico, Pixel};
+
+  // Icon DIB of `bitmap` with an AND mask marking only its top-left pixel.
+  fn icon_dib(bitmap: &Bitmap) -> Vec<u8> {
+    let mut dib = bitmap.encode()[FILE_HEADER_SIZE ..].to_vec();
+    dib[8 .. 12].copy_from_slice(&(bitmap.height() as i32 * 2).to_le_bytes());
+
+    let stride = row_stride(bitmap.width(), 1).unwrap();
+    let mut mask = vec![0; stride * bitmap.height() as usize];
+    // Bottom-up, so the top row is stored last.
+    mask[stride * (bitmap.height() as usize - 1)] = 0x80;
+    dib.extend_from_slice(&mask);
+    dib
+  }
+
+  // An icon or cursor directory holding one DIB entry per bitmap.
+  pub(crate) fn ico(resource_type: u16, bitmaps: &[Bitmap]) -> Vec<u8> {
+    let dibs = bitmaps.iter().map(icon_dib).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&resource_type.to_le_bytes());
+    out.extend_from_slice(&(bitmaps.len() as u16).to_le_bytes());
+
+    let mut offset = 6 + 16 * bitmaps.len();
+    for (bitmap, dib) in bitmaps.iter().zip(&dibs) {
+      out.extend_from_slice(&[bitmap.width() as u8, bitmap.height() as u8, 0, 0]);
+      out.extend_from_slice(&1u16.to_le_bytes());
+      out.extend_from_slice(&bitmap.header.bpp.to_le_bytes());
+      out.extend_from_slice(&(dib.len() as u32).to_le_bytes());
+      out.extend_from_slice(&(offset as u32).to_le_bytes());
+      offset += dib.len();
+    }
+    for dib in &dibs {
+      out.extend_from_slice(dib);
+    }
+    out
+  }
+
+  #[test]
+  fn reads_back_colors_and_masks() {
+    let bitmaps = [sample(24, 5), sample(32, 4), sample(8, 3)];
+    let ico = Ico::from_bytes(&ico(Ico::ICON, &bitmaps)).unwrap();
+
+    assert_eq!(ico.entries.len(), 3);
+    for (index, bitmap) in bitmaps.iter().enumerate() {
+      assert_eq!(ico.bitmap(index).unwrap(), *bitmap);
+      assert_eq!(ico.entry_bpp(index), Some(bitmap.header.bpp));
+
+      let mask = ico.mask(index).unwrap().unwrap();
+      assert_eq!(mask.pixels.iter().filter(|pixel| **pixel == Pixel::PaletteColor(1)).count(), 1);
+      assert_eq!(mask.pixels[0], Pixel::PaletteColor(1));
+    }
+    assert_eq!(ico.select_best(4, 3, 32), Some(1));
+  }
+
+  #[test]
+  fn re_serializes_with_edited_entries() {
+    let mut cursor = Ico::from_bytes(&ico(Ico::CURSOR, &[sample(24, 5)])).unwrap();
+    assert!(cursor.set_hotspot(0, 2, 1));
+
+    let reread = Ico::from_bytes(&cursor.to_bytes()).unwrap();
+    assert!(reread.is_cursor());
+    assert_eq!(reread.hotspot(0), Some((2, 1)));
+    assert_eq!(reread.entry_data(0), cursor.entry_data(0));
+  }
+
+  #[test]
+  fn rejects_truncated_directories() {
+    let file = ico(Ico::ICON, &[sample(24, 5)]);
+    for len in [0, 5, 21, file.len() - 1] {
+      assert!(Ico::from_bytes(&file[.. len]).is_err(), "{} bytes", len);
+    }
+  }
+
+  #[cfg(feature = "bitfields")]
+  #[test]
+  fn finds_pixels_after_alpha_bitfield_masks() {
+    let file = alpha_bitfields();
+    let mut dib = file[FILE_HEADER_SIZE ..].to_vec();
+    dib[8 .. 12].copy_from_slice(&2i32.to_le_bytes());
+
+    let restored = dib_to_file(&dib).unwrap();
+    assert_eq!(restored[10 .. 14], file[10 .. 14]);
+    assert_eq!(Bitmap::from_bytes(&restored).unwrap(), Bitmap::from_bytes(&file).unwrap());
+  }
+}
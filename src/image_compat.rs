@@ -0,0 +1,110 @@
+// Interop with the `image` crate: an `ImageDecoder` and `DynamicImage` conversions.
+
+use std::io::Read;
+
+use image::error::{DecodingError, ImageFormatHint};
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageResult, RgbImage, RgbaImage};
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+impl From<BitmapError> for ImageError {
+  fn from(err: BitmapError) -> ImageError {
+    match err {
+      BitmapError::BitmapIOError(err) => ImageError::IoError(err),
+      err => ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Bmp), err))
+    }
+  }
+}
+
+// Decodes eagerly on construction, then hands the pixels to `image` as RGB8 or RGBA8.
+#[derive(Debug, Clone)]
+pub struct BmpImageDecoder {
+  bitmap: Bitmap
+}
+
+impl BmpImageDecoder {
+  pub fn new<R: Read>(mut reader: R) -> BitmapResult<BmpImageDecoder> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(BmpImageDecoder { bitmap: Bitmap::from_vec(buf)? })
+  }
+
+  pub fn from_bitmap(bitmap: Bitmap) -> BmpImageDecoder {
+    BmpImageDecoder { bitmap }
+  }
+
+  pub fn into_bitmap(self) -> Bitmap {
+    self.bitmap
+  }
+}
+
+impl ImageDecoder for BmpImageDecoder {
+  fn dimensions(&self) -> (u32, u32) {
+    (self.bitmap.width(), self.bitmap.height())
+  }
+
+  // Only 32-bpp images carry a fourth channel.
+  fn color_type(&self) -> ColorType {
+    match self.bitmap.header.bpp {
+      32 => ColorType::Rgba8,
+      _ => ColorType::Rgb8
+    }
+  }
+
+  fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+    let pixels = match self.color_type() {
+      ColorType::Rgba8 => self.bitmap.to_rgba8(),
+      _ => self.bitmap.to_rgb8()
+    };
+
+    buf.get_mut(.. pixels.len())
+      .ok_or_else(|| ImageError::from(BitmapError::BufferTooSmall))?
+      .copy_from_slice(&pixels);
+
+    Ok(())
+  }
+
+  fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+    (*self).read_image(buf)
+  }
+}
+
+impl Bitmap {
+  // Truecolor images keep their channel count; indexed ones expand to RGB8.
+  pub fn to_dynamic_image(&self) -> DynamicImage {
+    let (width, height) = (self.width(), self.height());
+
+    match self.header.bpp {
+      32 => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, self.to_rgba8()).unwrap()),
+      _ => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, self.to_rgb8()).unwrap())
+    }
+  }
+
+  // Grayscale becomes 8-bpp with a gray ramp, anything with alpha 32-bpp, the rest 24-bpp.
+  // Deeper channel formats are reduced to 8 bits per channel.
+  pub fn from_dynamic_image(image: &DynamicImage) -> BitmapResult<Bitmap> {
+    let (width, height) = (image.width(), image.height());
+
+    match image {
+      DynamicImage::ImageLuma8(_) | DynamicImage::ImageLuma16(_) => {
+        let palette = (0 ..= 255).map(|level| Rgba::new(level, level, level, 255)).collect();
+        let pixels = image.to_luma8().into_raw().into_iter().map(Pixel::PaletteColor).collect();
+        Bitmap::from_parts(width, height, 8, palette, pixels)
+      }
+
+      _ if image.color().has_alpha() => {
+        let pixels = image.to_rgba8().pixels()
+          .map(|pixel| Pixel::ABGR(pixel[2], pixel[1], pixel[0], pixel[3]))
+          .collect();
+        Bitmap::from_parts(width, height, 32, Vec::new(), pixels)
+      }
+
+      _ => {
+        let pixels = image.to_rgb8().pixels()
+          .map(|pixel| Pixel::BGR(pixel[2], pixel[1], pixel[0]))
+          .collect();
+        Bitmap::from_parts(width, height, 24, Vec::new(), pixels)
+      }
+    }
+  }
+}
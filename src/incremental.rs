@@ -0,0 +1,174 @@
+// Push-based decoding for data that arrives in pieces, e.g. from a socket.
+
+use alloc::vec::Vec;
+
+use crate::decoder::{read_u32, FILE_HEADER_SIZE};
+use crate::masks::masks_len;
+use crate::{Bitmap, BitmapError, BitmapResult, BitmapV5Header, ChannelMasks, ChannelScaling, Decoder, FileHeader, HeaderVersion, Pixel};
+
+// One decoded scanline; `y` counts from the top of the image.
+#[derive(Debug, Clone)]
+pub struct Row {
+  pub y: u32,
+  pub pixels: Vec<Pixel>
+}
+
+// Buffers pushed bytes and reports rows as soon as they are complete.
+// Rows come out in storage order, so bottom-up files produce the last row first.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalDecoder {
+  buf: Vec<u8>,
+  rows_read: u32,
+  // What `Decoder::headers` found, kept once they've parsed.
+  parsed: Option<(FileHeader, BitmapV5Header, Option<ChannelMasks>)>
+}
+
+impl IncrementalDecoder {
+  pub fn new() -> IncrementalDecoder {
+    IncrementalDecoder::default()
+  }
+
+  pub fn push(&mut self, bytes: &[u8]) {
+    self.buf.extend_from_slice(bytes);
+
+    if self.parsed.is_none() && self.headers_end().is_some_and(|end| self.buf.len() >= end) {
+      if let Ok(decoder) = Decoder::headers(&self.buf) {
+        self.parsed = Some((decoder.file_header, decoder.header, decoder.masks));
+      }
+    }
+  }
+
+  pub fn buffered(&self) -> &[u8] {
+    &self.buf
+  }
+
+  // Parsed headers, once enough bytes have arrived; errors as soon as they're invalid.
+  pub fn decoder(&self) -> Option<BitmapResult<Decoder<'_>>> {
+    if let Some((file_header, header, masks)) = self.parsed {
+      return Some(Ok(Decoder { data: &self.buf, file_header, header, masks, scaling: ChannelScaling::default() }))
+    }
+
+    if self.buf.len() >= 2 && &self.buf[0..2] != b"BM" {
+      return Some(Err(BitmapError::BadMagic))
+    }
+
+    if self.buf.len() < self.headers_end()? {
+      return None
+    }
+
+    // Enough for the headers, which `push` would have kept had they parsed.
+    Some(Decoder::headers(&self.buf))
+  }
+
+  // Where the headers end, counting the masks that follow a 40-byte header
+  // for BI_BITFIELDS and BI_ALPHABITFIELDS; `None` until the header size
+  // has arrived.
+  fn headers_end(&self) -> Option<usize> {
+    let header_size = read_u32(&self.buf, FILE_HEADER_SIZE).ok()?;
+    let header_len = match HeaderVersion::from_size(header_size) {
+      HeaderVersion::Core | HeaderVersion::Os2 => header_size as usize,
+      _ => (header_size as usize).max(40)
+    };
+    let masks = read_u32(&self.buf, 30).map_or(0, |method| masks_len(header_size, method));
+    Some(FILE_HEADER_SIZE + header_len + masks)
+  }
+
+  // Bytes still missing before the headers, then the whole pixel array, are
+  // buffered; zero once decoding can finish. Never asks for trailing data.
+  pub fn bytes_needed(&self) -> BitmapResult<usize> {
+    let target = match self.decoder() {
+      Some(decoder) => decoder?.image_end(),
+      None => self.headers_end().unwrap_or(FILE_HEADER_SIZE + 4)
+    };
+
+    Ok(target.saturating_sub(self.buf.len()))
+  }
+
+  pub fn is_complete(&self) -> bool {
+    matches!(self.decoder(), Some(Ok(ref decoder)) if decoder.image_end() <= self.buf.len())
+  }
+
+  // The next stored row if it has fully arrived.
+  pub fn next_row(&mut self) -> Option<BitmapResult<Row>> {
+    let row = {
+      let decoder = match self.decoder()? {
+        Ok(decoder) => decoder,
+        Err(err) => return Some(Err(err))
+      };
+
+      if self.rows_read >= decoder.height() {
+        return None
+      }
+
+      // RLE rows have no fixed place in the stream; they only come out of
+      // `finish`.
+      if decoder.is_compressed() {
+        return Some(Err(BitmapError::UnsupportedCompression))
+      }
+
+      let at = decoder.file_header.pixel_offset as usize + self.rows_read as usize * decoder.stride();
+      let bytes = self.buf.get(at .. at + decoder.stride())?;
+      let y = if decoder.is_top_down() { self.rows_read } else { decoder.height() - 1 - self.rows_read };
+
+      Row {
+        y,
+        pixels: (0 .. decoder.width() as usize).map(|x| decoder.decode(bytes, x)).collect()
+      }
+    };
+
+    self.rows_read += 1;
+    Some(Ok(row))
+  }
+
+  // Decodes everything buffered so far into a bitmap.
+  pub fn finish(self) -> BitmapResult<Bitmap> {
+    Bitmap::from_vec(self.buf)
+  }
+}
+
+#[cfg(all(test, feature = "bitfields"))]
+pub(crate) mod tests {
+  use alloc::vec::Vec;
+
+  use crate::{Bitmap, IncrementalDecoder, Pixel};
+
+  // A 2x1 BI_ALPHABITFIELDS file with a 40-byte header and RGBA-order masks.
+  pub(crate) fn alpha_bitfields() -> Vec<u8> {
+    let mut file = Vec::new();
+    file.extend_from_slice(b"BM");
+    file.extend_from_slice(&78u32.to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&70u32.to_le_bytes());
+    file.extend_from_slice(&40u32.to_le_bytes());
+    file.extend_from_slice(&2i32.to_le_bytes());
+    file.extend_from_slice(&1i32.to_le_bytes());
+    file.extend_from_slice(&[1, 0, 32, 0]);
+    file.extend_from_slice(&6u32.to_le_bytes());
+    file.extend_from_slice(&[0; 20]);
+    for mask in [0xffu32, 0xff00, 0xff_0000, 0xff00_0000] {
+      file.extend_from_slice(&mask.to_le_bytes());
+    }
+    file.extend_from_slice(&[10, 20, 30, 255, 40, 50, 60, 128]);
+    file
+  }
+
+  #[test]
+  fn waits_for_masks_after_a_40_byte_header() {
+    let file = alpha_bitfields();
+    let expected = Bitmap::from_bytes(&file).unwrap();
+    assert_eq!(expected.pixels, [Pixel::ABGR(30, 20, 10, 255), Pixel::ABGR(60, 50, 40, 128)]);
+
+    let mut decoder = IncrementalDecoder::new();
+    let mut rows = Vec::new();
+    for byte in &file {
+      assert!(decoder.bytes_needed().unwrap() > 0);
+      decoder.push(core::slice::from_ref(byte));
+      rows.extend(decoder.next_row().map(Result::unwrap));
+    }
+
+    assert_eq!(decoder.bytes_needed().unwrap(), 0);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].pixels, expected.pixels);
+    assert_eq!(decoder.finish().unwrap().pixels, expected.pixels);
+  }
+}
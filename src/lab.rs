@@ -0,0 +1,111 @@
+// CIELAB and the CIE76 and CIEDE2000 color differences, for matching
+// colors the way they look rather than by their RGB values.
+
+use core::f64::consts::PI;
+
+use crate::color_space::srgb_to_linear;
+use crate::Rgba;
+
+// D65 reference white of sRGB, in XYZ.
+const WHITE: [f64; 3] = [0.950_47, 1.0, 1.088_83];
+
+// Color-difference formulas. Differences are delta E units, where about
+// 1.0 is the smallest a viewer notices side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeltaE {
+  // Straight-line distance in Lab; cheap, but overstates differences
+  // between saturated colors.
+  Cie76,
+  // Corrects CIE76 for hue, chroma and lightness sensitivity, and for the
+  // blues where Lab isn't uniform.
+  #[default]
+  Ciede2000
+}
+
+impl DeltaE {
+  pub fn difference(self, a: Lab, b: Lab) -> f32 {
+    match self {
+      DeltaE::Cie76 => a.delta_e76(b),
+      DeltaE::Ciede2000 => a.delta_e2000(b)
+    }
+  }
+}
+
+// Lightness from 0 to 100 and the green-red and blue-yellow axes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lab {
+  pub l: f32,
+  pub a: f32,
+  pub b: f32
+}
+
+impl Lab {
+  // Converts an sRGB color under the D65 white point; alpha is ignored.
+  pub fn from_rgba(color: Rgba) -> Lab {
+    let [r, g, b] = [color.r, color.g, color.b].map(|value| srgb_to_linear(value as f32 / 255.0) as f64);
+    let xyz = [
+      0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+      0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+      0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b
+    ];
+
+    let f = |index: usize| {
+      let t = xyz[index] / WHITE[index];
+      if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
+    };
+    let (x, y, z) = (f(0), f(1), f(2));
+    Lab { l: (116.0 * y - 16.0) as f32, a: (500.0 * (x - y)) as f32, b: (200.0 * (y - z)) as f32 }
+  }
+
+  pub fn delta_e76(self, other: Lab) -> f32 {
+    ((self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2)).sqrt()
+  }
+
+  // CIEDE2000 with unit weighting factors, following Sharma, Wu and Dalal's
+  // notes on the formula.
+  pub fn delta_e2000(self, other: Lab) -> f32 {
+    let (l1, a1, b1) = (self.l as f64, self.a as f64, self.b as f64);
+    let (l2, a2, b2) = (other.l as f64, other.a as f64, other.b as f64);
+
+    let mean_c = ((a1.hypot(b1) + a2.hypot(b2)) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (mean_c / (mean_c + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).rem_euclid(2.0 * PI) };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = match h2 - h1 {
+      _ if c1 * c2 == 0.0 => 0.0,
+      diff if diff > PI => diff - 2.0 * PI,
+      diff if diff < -PI => diff + 2.0 * PI,
+      diff => diff
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).sin();
+
+    let mean_l = (l1 + l2) / 2.0;
+    let mean_c = (c1 + c2) / 2.0;
+    let mean_h = match (h1 + h2, (h1 - h2).abs()) {
+      _ if c1 * c2 == 0.0 => h1 + h2,
+      (sum, diff) if diff <= PI => sum / 2.0,
+      (sum, _) if sum < 2.0 * PI => (sum + 2.0 * PI) / 2.0,
+      (sum, _) => (sum - 2.0 * PI) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (mean_h - PI / 6.0).cos() + 0.24 * (2.0 * mean_h).cos() + 0.32 * (3.0 * mean_h + PI / 30.0).cos()
+      - 0.20 * (4.0 * mean_h - 63.0 * PI / 180.0).cos();
+    let rotation = 30.0 * (-((mean_h.to_degrees() - 275.0) / 25.0).powi(2)).exp();
+    let mean_c7 = mean_c.powi(7);
+    let r_t = -2.0 * (mean_c7 / (mean_c7 + 25f64.powi(7))).sqrt() * (2.0 * rotation.to_radians()).sin();
+
+    let s_l = 1.0 + 0.015 * (mean_l - 50.0).powi(2) / (20.0 + (mean_l - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * mean_c;
+    let s_h = 1.0 + 0.015 * mean_c * t;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+  }
+}
@@ -1,20 +1,198 @@
 // µbmp - Tiny library for reading bitmap pixel data.
 
-use std::io::prelude::*;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+#[macro_use]
+mod trace;
+
+mod adjust;
+mod alpha;
+mod ani;
+mod best;
+mod borrowed;
+mod color_space;
+mod compare;
+mod convert;
+mod decoder;
+#[cfg(feature = "fs")]
+mod dir;
+mod draw;
+mod encode;
+mod filter;
+mod forensics;
+mod hash;
+mod histogram;
+mod ico;
+mod incremental;
+mod map;
+mod masks;
+#[cfg(feature = "std")]
+mod mipmap;
+mod mutate;
+mod netpbm;
+mod options;
+mod orientation;
+mod palette;
+#[cfg(feature = "std")]
+mod patch;
+pub mod patterns;
+mod quantize;
+mod repair;
+#[cfg(feature = "rle")]
+mod rle;
+mod sink;
+mod sprite;
+mod stego;
+mod summary;
+mod text;
+mod texture;
+mod transform;
+mod validate;
+mod variant;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_compat;
+#[cfg(feature = "egui")]
+mod egui_compat;
+#[cfg(feature = "embedded-graphics")]
+mod embedded;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "icc")]
+mod icc;
+#[cfg(feature = "font")]
+mod font;
+#[cfg(feature = "image")]
+mod image_compat;
+#[cfg(feature = "perceptual")]
+mod lab;
+#[cfg(feature = "ndarray")]
+mod ndarray_compat;
+#[cfg(feature = "png")]
+mod png_export;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "qoi")]
+mod qoi;
+#[cfg(feature = "resize")]
+mod resize;
+#[cfg(feature = "rgb")]
+mod rgb_compat;
+#[cfg(feature = "sdl2")]
+mod sdl2_compat;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tga")]
+mod tga;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use adjust::{GrayscaleOutput, LumaWeights};
+pub use alpha::{AlphaMode, BlendMode, WatermarkPosition};
+pub use ani::{Ani, AniStep};
+pub use best::EncodeChoice;
+pub use borrowed::{BitmapRef, CowBitmap};
+pub use color_space::RenderingIntent;
+pub use compare::CompareResult;
+pub use convert::{rgb565, AlignedBuffer, ChannelOrder, Rgb565Options, RowLayout};
+pub use decoder::{Decoder, ImageInfo, Pixels, Scanlines, FILE_HEADER_SIZE};
+#[cfg(feature = "fs")]
+pub use dir::decode_dir;
+pub use draw::Rect;
+pub use filter::{EdgeMode, Kernel};
+pub use forensics::ForensicReport;
+pub use hash::hamming_distance;
+pub use histogram::{ChannelStats, DominantColor, Histogram, ImageStats};
+pub use ico::{Ico, IcoEntry};
+pub use incremental::{IncrementalDecoder, Row};
+pub use masks::{ChannelMask, ChannelMasks, ChannelScaling};
+#[cfg(feature = "std")]
+pub use mipmap::MipFilter;
+pub use mutate::{mutation_list, mutations, HeaderField, Mutation};
+pub use options::{AlphaHandling, DecodeOptions, EncodeOptions, PaletteHandling};
+pub use orientation::ScanlineOrder;
+pub use palette::{ColorMetric, Palette};
+pub use quantize::{Dither, Quantized};
+pub use repair::{Repair, RepairLog};
+pub use sink::{PackedSink, PixelSink, Rgb565Sink};
+pub use summary::BitmapSummary;
+pub use text::{TextOptions, TextStyle};
+pub use texture::{TextureData, TextureFormat};
+pub use transform::UpscaleMode;
+pub use validate::{validate, Issue, Severity, ValidationReport};
+pub use variant::{identify, HeaderVersion, PixelFormat, Variant};
+#[cfg(feature = "arbitrary")]
+pub use arbitrary_compat::{ArbitraryBmp, Defect};
+#[cfg(feature = "stream")]
+pub use tokio_io::row_stream;
+#[cfg(feature = "font")]
+pub use font::{text_bounds, GLYPH_SIZE};
+#[cfg(feature = "image")]
+pub use image_compat::BmpImageDecoder;
+#[cfg(feature = "perceptual")]
+pub use lab::{DeltaE, Lab};
+#[cfg(feature = "resize")]
+pub use resize::{ResizeFilter, ResizeSpace};
+
+#[cfg(feature = "fs")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io;
-use std::intrinsics::transmute;
+#[cfg(feature = "std")]
+use std::io::prelude::*;
 
 // Pixel enumerated type containing each BPP.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Pixel {
   ABGR(u8, u8, u8, u8),
   BGR(u8, u8, u8),
   PaletteColor(u8)
 }
 
+// Resolved straight-alpha color of a pixel, laid out as four bytes in RGBA order.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Rgba {
+  pub r: u8,
+  pub g: u8,
+  pub b: u8,
+  pub a: u8
+}
+
+impl Rgba {
+  pub const BLACK: Rgba = Rgba { r: 0, g: 0, b: 0, a: 255 };
+  pub const WHITE: Rgba = Rgba { r: 255, g: 255, b: 255, a: 255 };
+
+  pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+    Rgba { r, g, b, a }
+  }
+
+  // Palette entries are RGBQUADs whose fourth byte is reserved.
+  pub const fn from_rgbquad(quad: [u8; 4]) -> Rgba {
+    Rgba { r: quad[2], g: quad[1], b: quad[0], a: 255 }
+  }
+}
+
 // Enum for each compression method.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum CompressionMethod {
   None,
   Rle8Bit,
@@ -25,36 +203,211 @@ pub enum CompressionMethod {
   Other(u32)
 }
 
+// The 14-byte BITMAPFILEHEADER that opens every file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct FileHeader {
+  pub magic: [u8; 2],
+  pub file_size: u32,
+  pub reserved1: u16,
+  pub reserved2: u16,
+  pub pixel_offset: u32
+}
+
+impl FileHeader {
+  pub fn parse(data: &[u8]) -> BitmapResult<FileHeader> {
+    let magic = data.get(0 .. 2).ok_or(BitmapError::TruncatedHeader)?;
+    Ok(FileHeader {
+      magic: [magic[0], magic[1]],
+      file_size: decoder::read_u32(data, 2)?,
+      reserved1: decoder::read_u16(data, 6)?,
+      reserved2: decoder::read_u16(data, 8)?,
+      pixel_offset: decoder::read_u32(data, 10)?
+    })
+  }
+
+  pub fn to_bytes(&self) -> [u8; FILE_HEADER_SIZE] {
+    let mut out = [0; FILE_HEADER_SIZE];
+    out[0 .. 2].copy_from_slice(&self.magic);
+    out[2 .. 6].copy_from_slice(&self.file_size.to_le_bytes());
+    out[6 .. 8].copy_from_slice(&self.reserved1.to_le_bytes());
+    out[8 .. 10].copy_from_slice(&self.reserved2.to_le_bytes());
+    out[10 .. 14].copy_from_slice(&self.pixel_offset.to_le_bytes());
+    out
+  }
+}
+
 // A basic (and incomplete) BITMAPV5HEADER.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct BitmapV5Header {
   pub size: u32,
   pub pix_width: i32,
   pub pix_height: i32,
   pub bpp: u16,
   pub method: CompressionMethod,
-  pub colors: u32
+  // Resolution in pixels per meter, 0 when unset.
+  pub x_pels_per_meter: i32,
+  pub y_pels_per_meter: i32,
+  pub colors: u32,
+  // `biClrImportant`: how many leading palette entries matter, 0 for all.
+  pub important_colors: u32,
+  // `bV5Reserved`; zero for headers shorter than 124 bytes.
+  pub reserved: u32
 }
 
 // Containing Bitmap structure.
 #[derive(Debug, Clone)]
 pub struct Bitmap {
   pub data: Vec<u8>,
-  pub size: u32,
-  pub offset: u32,
+  pub file_header: FileHeader,
   pub header: BitmapV5Header,
-  pub pixels: Vec<Pixel>
+  pub palette: Vec<Rgba>,
+  pub pixels: Vec<Pixel>,
+  // Whether color channels of `pixels` are scaled by alpha; kept up to date
+  // by `premultiply_alpha` and `unpremultiply_alpha`.
+  pub alpha_mode: AlphaMode,
+  // Row order of the file `pixels` came from, and the order `encode`
+  // writes. `pixels` itself is always top-down.
+  pub orientation: ScanlineOrder
 }
 
+// Decoded bitmaps hold nothing tied to a thread, so they can be sent or
+// shared, e.g. behind an `Arc` in a `CowBitmap::Shared`.
+const _: () = {
+  const fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<Bitmap>();
+  assert_send_sync::<BitmapRef>();
+  assert_send_sync::<CowBitmap>();
+};
+
 pub type BitmapResult<T> = Result<T, BitmapError>;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum BitmapError {
+  // Malformed data not covered by a more specific variant.
   InvalidBitmapData,
+  // The file doesn't start with the `BM` signature.
+  BadMagic,
+  // The data ends inside the file or info header.
+  TruncatedHeader,
+  // A palette that is empty, too long, or lacks an entry that is needed.
+  BadPalette,
+  // An RLE4/RLE8 stream that overruns its rows or ends without a terminator.
+  BadRleStream,
+  // Width and height whose pixel array doesn't fit the address space.
+  DimensionOverflow,
   UnsupportedBitsPerPixel,
-  BitmapIOError(io::Error)
+  UnsupportedCompression,
+  BufferTooSmall,
+  // The hook passed to `Bitmap::from_vec_cancellable` asked to stop.
+  Cancelled,
+  #[cfg(feature = "std")]
+  BitmapIOError(io::Error),
+  // `source` wrapped with where it happened, see `BitmapError::context`.
+  Context { context: ErrorContext, source: Box<BitmapError> }
+}
+
+// What was being read when an error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ErrorContext {
+  FileHeader,
+  InfoHeader,
+  Row(u32),
+  IconEntry(usize),
+  AniFrame(usize)
+}
+
+impl core::fmt::Display for ErrorContext {
+  fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    match self {
+      ErrorContext::FileHeader => write!(f, "reading the file header"),
+      ErrorContext::InfoHeader => write!(f, "reading the info header"),
+      ErrorContext::Row(y) => write!(f, "decoding row {}", y),
+      ErrorContext::IconEntry(index) => write!(f, "decoding icon entry {}", index),
+      ErrorContext::AniFrame(frame) => write!(f, "decoding animation frame {}", frame)
+    }
+  }
+}
+
+impl BitmapError {
+  // Wraps the error with where it happened, for messages such as
+  // "failed decoding row 512: invalid bitmap data".
+  pub fn context(self, context: ErrorContext) -> BitmapError {
+    BitmapError::Context { context, source: Box::new(self) }
+  }
+
+  // The innermost error, under any layers of context.
+  pub fn root_cause(&self) -> &BitmapError {
+    let mut err = self;
+    while let BitmapError::Context { source, .. } = err {
+      err = source;
+    }
+    err
+  }
+}
+
+impl core::fmt::Display for BitmapError {
+  fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    match self {
+      BitmapError::InvalidBitmapData => write!(f, "invalid bitmap data"),
+      BitmapError::BadMagic => write!(f, "missing BM signature"),
+      BitmapError::TruncatedHeader => write!(f, "data ends inside the headers"),
+      BitmapError::BadPalette => write!(f, "invalid palette"),
+      BitmapError::BadRleStream => write!(f, "malformed RLE stream"),
+      BitmapError::DimensionOverflow => write!(f, "image dimensions overflow"),
+      BitmapError::UnsupportedBitsPerPixel => write!(f, "unsupported bits per pixel"),
+      BitmapError::UnsupportedCompression => write!(f, "unsupported compression method"),
+      BitmapError::BufferTooSmall => write!(f, "output buffer too small"),
+      BitmapError::Cancelled => write!(f, "decoding cancelled"),
+      #[cfg(feature = "std")]
+      BitmapError::BitmapIOError(err) => write!(f, "I/O error: {}", err),
+      BitmapError::Context { context, source } => write!(f, "failed {}: {}", context, source)
+    }
+  }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for BitmapError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      BitmapError::BitmapIOError(err) => Some(err),
+      BitmapError::Context { source, .. } => Some(source.as_ref()),
+      _ => None
+    }
+  }
+}
+
+// Written by hand since `io::Error` has no defmt representation.
+#[cfg(feature = "defmt")]
+impl defmt::Format for BitmapError {
+  fn format(&self, fmt: defmt::Formatter) {
+    match self {
+      BitmapError::InvalidBitmapData => defmt::write!(fmt, "InvalidBitmapData"),
+      BitmapError::BadMagic => defmt::write!(fmt, "BadMagic"),
+      BitmapError::TruncatedHeader => defmt::write!(fmt, "TruncatedHeader"),
+      BitmapError::BadPalette => defmt::write!(fmt, "BadPalette"),
+      BitmapError::BadRleStream => defmt::write!(fmt, "BadRleStream"),
+      BitmapError::DimensionOverflow => defmt::write!(fmt, "DimensionOverflow"),
+      BitmapError::UnsupportedBitsPerPixel => defmt::write!(fmt, "UnsupportedBitsPerPixel"),
+      BitmapError::UnsupportedCompression => defmt::write!(fmt, "UnsupportedCompression"),
+      BitmapError::BufferTooSmall => defmt::write!(fmt, "BufferTooSmall"),
+      BitmapError::Cancelled => defmt::write!(fmt, "Cancelled"),
+      #[cfg(feature = "std")]
+      BitmapError::BitmapIOError(_) => defmt::write!(fmt, "BitmapIOError"),
+      BitmapError::Context { context, source } => defmt::write!(fmt, "{} in {}", source.as_ref(), context)
+    }
+  }
+}
+
+#[cfg(feature = "std")]
 impl std::convert::From<io::Error> for BitmapError {
   fn from(err: io::Error) -> BitmapError {
     BitmapError::BitmapIOError(err)
@@ -62,115 +415,172 @@ impl std::convert::From<io::Error> for BitmapError {
 }
 
 impl Bitmap {
-  pub fn new(file: &mut File) -> BitmapResult<Bitmap> {
-    let mut buf: Vec<u8> = Vec::new();    
-    try!(file.read_to_end(&mut buf));
+  // Reads a whole bitmap file from `reader`, e.g. a `File`, a socket or a
+  // `Cursor`, and parses it.
+  #[cfg(feature = "std")]
+  pub fn new<R: Read>(reader: &mut R) -> BitmapResult<Bitmap> {
+    let mut buf: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Bitmap::from_vec(buf)
+  }
 
-    // magic number check
-    if &buf[0..2] != b"BM" {
-      return Err(BitmapError::InvalidBitmapData)
-    }
+  // Opens and parses the file at `path`.
+  #[cfg(feature = "fs")]
+  pub fn open<P: AsRef<std::path::Path>>(path: P) -> BitmapResult<Bitmap> {
+    Bitmap::new(&mut File::open(path)?)
+  }
 
-    let size: u32 = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[2..6]);
-      transmute(bytes)
-    };
+  // Parses a bitmap from an in-memory copy of the file.
+  pub fn from_bytes(bytes: &[u8]) -> BitmapResult<Bitmap> {
+    Bitmap::from_vec(bytes.to_vec())
+  }
 
-    let offset: u32 = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[10..14]);
-      transmute(bytes)
-    };
+  // Parses a bitmap, taking ownership of the file bytes.
+  pub fn from_vec(buf: Vec<u8>) -> BitmapResult<Bitmap> {
+    Bitmap::from_vec_with(buf, DecodeOptions::default())
+  }
 
-    let header_size: u32 = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[14..18]);
-      transmute(bytes)
-    };
+  pub fn from_bytes_with(bytes: &[u8], options: DecodeOptions) -> BitmapResult<Bitmap> {
+    Bitmap::from_vec_with(bytes.to_vec(), options)
+  }
 
-    let pix_width: i32 = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[18..22]);
-      transmute(bytes)
-    };
+  pub fn from_vec_with(buf: Vec<u8>, options: DecodeOptions) -> BitmapResult<Bitmap> {
+    Bitmap::from_vec_cancellable(buf, options, || ControlFlow::Continue(()))
+  }
 
-    let pix_height: i32 = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[22..26]);
-      transmute(bytes)
+  // Like `from_vec_with`, calling `check` before each scanline and giving
+  // up with `BitmapError::Cancelled` as soon as it breaks. Lets a UI or a
+  // server abandon a huge file promptly, e.g. with a shared `AtomicBool`:
+  // `|| if cancel.load(Relaxed) { Break(()) } else { Continue(()) }`.
+  pub fn from_vec_cancellable<F: FnMut() -> ControlFlow<()>>(buf: Vec<u8>, options: DecodeOptions, mut check: F) -> BitmapResult<Bitmap> {
+    trace_span!("decode", len = buf.len());
+    let parsed = Decoder::headers(&buf).inspect_err(|_err| { trace_event!(error = %_err, "rejected file"); })?;
+    // RLE files are expanded into an uncompressed copy to decode from.
+    let expanded: Option<Vec<u8>> = match parsed.is_compressed() {
+      #[cfg(feature = "rle")]
+      true => Some(parsed.decompress_until(&mut check)?),
+      _ => None
     };
-    
-    let bpp: u16 = unsafe {
-      let mut bytes: [u8; 2] = [0; 2];
-      bytes.clone_from_slice(&buf[28..30]);
-      transmute(bytes)
+    let decoder = Decoder::new(expanded.as_deref().unwrap_or(&buf)).inspect_err(|_err| { trace_event!(error = %_err, "rejected file"); })?;
+    let ControlFlow::Continue((file_header, header, palette, pixels, palette_fixed)) = decode_parts(&decoder, options, &mut check) else {
+      trace_event!("cancelled");
+      return Err(BitmapError::Cancelled)
     };
+    if palette_fixed && options.palette == PaletteHandling::Strict {
+      return Err(BitmapError::BadPalette)
+    }
 
-    let method: CompressionMethod = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[30..34]);
-      match transmute(bytes) {
-        0 => CompressionMethod::None,
-        1 => CompressionMethod::Rle8Bit,
-        2 => CompressionMethod::Rle4Bit,
-        3 => CompressionMethod::Huffman1D,
-        4 => CompressionMethod::Jpeg,
-        5 => CompressionMethod::Png,
-        n => CompressionMethod::Other(n)
-      }
-    }; 
-    
-    let end: u32 = offset + unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[34..38]);
-      transmute::<[u8; 4], u32>(bytes)
+    let mut bitmap = Bitmap {
+      data: buf,
+      file_header,
+      header,
+      palette,
+      pixels,
+      alpha_mode: AlphaMode::Straight,
+      orientation: ScanlineOrder::from_height(header.pix_height)
     };
+    if options.alpha_mode == AlphaMode::Premultiplied {
+      bitmap.premultiply_alpha();
+    }
+    Ok(bitmap)
+  }
 
-    let colors: u32 = unsafe {
-      let mut bytes: [u8; 4] = [0; 4];
-      bytes.clone_from_slice(&buf[46..50]);
-      transmute(bytes)
-    };
+  pub fn width(&self) -> u32 {
+    self.header.pix_width.unsigned_abs()
+  }
 
-    let pixel_data = match bpp {
-      24 | 32 => {
-        buf[offset as usize .. end as usize]
-          .chunks(4)
-          .map(|slice| {
-            Pixel::ABGR(slice[0], slice[1], slice[2], slice[3])
-          })
-          .collect::<Vec<_>>()
-      }
+  pub fn height(&self) -> u32 {
+    self.header.pix_height.unsigned_abs()
+  }
 
-      4 => {
-        fn nibbles(n: u8) -> Vec<u8> {
-          vec![(n & 0xF0) >> 4, n & 0xF]
-        }
+  // Pixel at column `x` of row `y`, counted from the top-left corner.
+  pub fn pixel(&self, x: u32, y: u32) -> Option<&Pixel> {
+    if x >= self.width() {
+      return None
+    }
 
-        buf[offset as usize .. end as usize]
-          .iter()
-          .flat_map(|pixel| nibbles(*pixel))
-          .map(|pixel| Pixel::PaletteColor(pixel))
-          .collect::<Vec<_>>()
-      }
+    self.pixels.get(y as usize * self.width() as usize + x as usize)
+  }
 
-      _ => { return Err(BitmapError::UnsupportedBitsPerPixel) }
-    };
-    
-    Ok(Bitmap {
-      data: buf,
-      size: size,
-      offset: offset,
-      header: BitmapV5Header {
-        size: header_size,
-        pix_width: pix_width,
-        pix_height: pix_height,
-        bpp: bpp,
-        method: method,
-        colors: colors
-      },
-      pixels: pixel_data
+  // Pixels of `rect`, clipped to the image, with their coordinates, in
+  // top-to-bottom, left-to-right order.
+  pub fn pixels_in(&self, rect: Rect) -> impl Iterator<Item = (u32, u32, &Pixel)> + '_ {
+    let (width, height) = (self.width(), self.height());
+    let x_end = rect.x.saturating_add(rect.width).min(width);
+    let y_end = rect.y.saturating_add(rect.height).min(height);
+    let x_start = rect.x.min(x_end);
+
+    (rect.y.min(y_end) .. y_end).flat_map(move |y| {
+      let row = y as usize * width as usize;
+      let pixels = self.pixels.get(row + x_start as usize .. row + x_end as usize).unwrap_or_default();
+      (x_start ..).zip(pixels).map(move |(x, pixel)| (x, y, pixel))
     })
   }
+
+  // Every pixel with its coordinates, in top-to-bottom, left-to-right order.
+  pub fn enumerate_pixels(&self) -> impl Iterator<Item = (u32, u32, &Pixel)> + '_ {
+    let width = self.width().max(1) as usize;
+    self.pixels.iter().enumerate().map(move |(at, pixel)| ((at % width) as u32, (at / width) as u32, pixel))
+  }
+
+  // Mutable counterpart of `enumerate_pixels`. As with `set_pixel`, written
+  // pixels should match how this bitmap stores pixels.
+  pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (u32, u32, &mut Pixel)> + '_ {
+    let width = self.width().max(1) as usize;
+    self.pixels.iter_mut().enumerate().map(move |(at, pixel)| ((at % width) as u32, (at / width) as u32, pixel))
+  }
+
+  // Resolves a pixel through the palette; missing entries come out opaque black.
+  pub fn color(&self, pixel: Pixel) -> Rgba {
+    match pixel {
+      Pixel::ABGR(b, g, r, a) => Rgba { r, g, b, a },
+      Pixel::BGR(b, g, r) => Rgba { r, g, b, a: 255 },
+      Pixel::PaletteColor(index) => self.palette.get(index as usize).copied().unwrap_or(Rgba::BLACK)
+    }
+  }
+}
+
+// Headers, palette and pixels decoded from a file already checked by
+// `Decoder::new`, plus the fix-up flag: whether the palette had to be
+// trimmed or padded.
+pub(crate) type Parts = (FileHeader, BitmapV5Header, Vec<Rgba>, Vec<Pixel>, bool);
+
+// Decodes everything but the file bytes themselves, calling `check` before
+// each row and stopping with its value if it breaks.
+pub(crate) fn decode_parts<B>(decoder: &Decoder, options: DecodeOptions, mut check: impl FnMut() -> ControlFlow<B>) -> ControlFlow<B, Parts> {
+  let palette_opaque = match options.palette_alpha {
+    AlphaHandling::Trust => false,
+    AlphaHandling::Opaque => true,
+    AlphaHandling::Auto => !decoder.palette_has_alpha()
+  };
+  let mut palette = (0 .. decoder.palette_len().min(256))
+    .map_while(|index| decoder.palette_entry(index as u8))
+    .map(|quad| Rgba { a: if palette_opaque { 255 } else { quad[3] }, ..Rgba::from_rgbquad(quad) })
+    .collect::<Vec<_>>();
+  trace_event!(entries = palette.len(), declared = decoder.header.colors, "parsed palette");
+
+  let decoder = Decoder { scaling: options.scaling, ..decoder.clone() };
+  let mut source = decoder.pixels();
+  let mut pixels = Vec::new();
+  for _ in 0 .. decoder.height() {
+    check()?;
+    pixels.extend(source.by_ref().take(decoder.width() as usize));
+  }
+  trace_event!(pixels = pixels.len(), bitfields = decoder.header.method == CompressionMethod::Huffman1D, "decoded pixels");
+
+  let opaque = match options.alpha {
+    AlphaHandling::Trust => false,
+    AlphaHandling::Opaque => true,
+    AlphaHandling::Auto => pixels.iter().all(|pixel| matches!(pixel, Pixel::ABGR(_, _, _, 0)))
+  };
+  if opaque {
+    for pixel in &mut pixels {
+      if let Pixel::ABGR(b, g, r, _) = *pixel {
+        *pixel = Pixel::ABGR(b, g, r, 255);
+      }
+    }
+  }
+
+  let palette_fixed = palette::fit_palette(&mut palette, &pixels, decoder.header.bpp);
+  ControlFlow::Continue((decoder.file_header, decoder.header, palette, pixels, palette_fixed))
 }
@@ -0,0 +1,34 @@
+// Per-pixel transforms through a closure, optionally across rayon's thread
+// pool.
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{Bitmap, Pixel};
+
+impl Bitmap {
+  // Replaces every pixel with `f(x, y, pixel)`, in top-to-bottom,
+  // left-to-right order. As with `set_pixel`, returned pixels should match
+  // how this bitmap stores pixels.
+  pub fn map_pixels<F: FnMut(u32, u32, Pixel) -> Pixel>(&mut self, mut f: F) {
+    for (x, y, pixel) in self.enumerate_pixels_mut() {
+      *pixel = f(x, y, *pixel);
+    }
+  }
+
+  // Like `map_pixels`, handing rows out across rayon's thread pool, so `f`
+  // can be called in any order.
+  #[cfg(feature = "rayon")]
+  pub fn par_map_pixels<F: Fn(u32, u32, Pixel) -> Pixel + Sync>(&mut self, f: F) {
+    let width = self.width() as usize;
+    if width == 0 {
+      return
+    }
+
+    self.pixels.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+      for (x, pixel) in row.iter_mut().enumerate() {
+        *pixel = f(x as u32, y as u32, *pixel);
+      }
+    });
+  }
+}
@@ -0,0 +1,173 @@
+// Bitfield color masks and their shift/width decomposition.
+
+use crate::decoder::read_u32;
+use crate::{Bitmap, BitmapResult, CompressionMethod, Decoder, Rgba, FILE_HEADER_SIZE};
+
+// How channels narrower than 8 bits are widened. Both map zero and full
+// scale exactly but round some values in between differently, so matching
+// a reference decoder pixel for pixel needs the method it uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelScaling {
+  // `value * 255 / max`, rounded to nearest.
+  #[default]
+  Scale,
+  // The bits repeated to fill the byte, e.g. `abcde` becomes `abcdeabc`.
+  // Wider channels keep their top 8 bits.
+  Replicate
+}
+
+// One channel's mask with the position and width of its bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelMask {
+  pub mask: u32,
+  // Bits below the lowest set bit.
+  pub shift: u32,
+  // Number of set bits.
+  pub bits: u32
+}
+
+impl ChannelMask {
+  pub const fn new(mask: u32) -> ChannelMask {
+    let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+    ChannelMask { mask, shift, bits: mask.count_ones() }
+  }
+
+  // Whether the set bits form one run, as every real-world layout does.
+  pub const fn is_contiguous(&self) -> bool {
+    self.mask == 0 || (self.mask >> self.shift).wrapping_add(1) & (self.mask >> self.shift) == 0
+  }
+
+  // This channel of a pixel value, widened or narrowed to 8 bits; 0 if
+  // the channel is absent.
+  pub const fn extract(&self, value: u32) -> u8 {
+    self.extract_with(value, ChannelScaling::Scale)
+  }
+
+  pub const fn extract_with(&self, value: u32, scaling: ChannelScaling) -> u8 {
+    if self.bits == 0 {
+      return 0
+    }
+
+    let field = ((value & self.mask) >> self.shift) as u64;
+    match scaling {
+      ChannelScaling::Scale => {
+        let max = (1u64 << self.bits) - 1;
+        ((field * 255 + max / 2) / max) as u8
+      }
+      ChannelScaling::Replicate if self.bits >= 8 => (field >> (self.bits - 8)) as u8,
+      ChannelScaling::Replicate => {
+        let (mut out, mut position) = (0, 8i32);
+        while position > 0 {
+          position -= self.bits as i32;
+          out |= if position >= 0 { field << position } else { field >> -position };
+        }
+        out as u8
+      }
+    }
+  }
+}
+
+// Masks for all four channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelMasks {
+  pub red: ChannelMask,
+  pub green: ChannelMask,
+  pub blue: ChannelMask,
+  pub alpha: ChannelMask
+}
+
+impl ChannelMasks {
+  pub const fn new(red: u32, green: u32, blue: u32, alpha: u32) -> ChannelMasks {
+    ChannelMasks {
+      red: ChannelMask::new(red),
+      green: ChannelMask::new(green),
+      blue: ChannelMask::new(blue),
+      alpha: ChannelMask::new(alpha)
+    }
+  }
+
+  // Color of a pixel value. Without an alpha mask the pixel is opaque.
+  pub const fn color(&self, value: u32, scaling: ChannelScaling) -> Rgba {
+    Rgba {
+      r: self.red.extract_with(value, scaling),
+      g: self.green.extract_with(value, scaling),
+      b: self.blue.extract_with(value, scaling),
+      a: if self.alpha.bits == 0 { 255 } else { self.alpha.extract_with(value, scaling) }
+    }
+  }
+}
+
+// Bytes of masks between a 40-byte header and the palette: three for
+// BI_BITFIELDS, four for BI_ALPHABITFIELDS. Later headers hold them inside.
+pub(crate) const fn masks_len(header_size: u32, method: u32) -> usize {
+  match (header_size, method) {
+    (40, 3) => 12,
+    (40, 6) => 16,
+    _ => 0
+  }
+}
+
+// Red, green, blue and alpha masks as stored: inside the header from V2 on,
+// or straight after a 40-byte one. Alpha is only present from V3 on, or
+// with BI_ALPHABITFIELDS (method 6).
+pub(crate) fn read_masks(data: &[u8], header_size: u32, method: u32) -> BitmapResult<[u32; 4]> {
+  let at = FILE_HEADER_SIZE + 40;
+  let alpha = if header_size >= 56 || method == 6 { read_u32(data, at + 12)? } else { 0 };
+  Ok([read_u32(data, at)?, read_u32(data, at + 4)?, read_u32(data, at + 8)?, alpha])
+}
+
+impl Decoder<'_> {
+  // Masks of a bitfields image; `None` for other compression methods.
+  pub fn channel_masks(&self) -> Option<ChannelMasks> {
+    let method = match self.header.method {
+      CompressionMethod::Huffman1D => 3,
+      CompressionMethod::Other(6) => 6,
+      _ => return None
+    };
+
+    let [red, green, blue, alpha] = read_masks(self.data, self.header.size, method).ok()?;
+    Some(ChannelMasks::new(red, green, blue, alpha))
+  }
+}
+
+impl Bitmap {
+  // Masks from the file in `data`, if it is a bitfields image.
+  pub fn channel_masks(&self) -> Option<ChannelMasks> {
+    Decoder::headers(&self.data).ok()?.channel_masks()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{ChannelMask, ChannelMasks, ChannelScaling, Rgba};
+
+  #[test]
+  fn widens_channels_per_scaling() {
+    let red = ChannelMask::new(0x7c00);
+    assert_eq!((red.shift, red.bits, red.is_contiguous()), (10, 5, true));
+
+    for scaling in [ChannelScaling::Scale, ChannelScaling::Replicate] {
+      assert_eq!(red.extract_with(0x7c00, scaling), 0xff, "{:?}", scaling);
+      assert_eq!(red.extract_with(0x03ff, scaling), 0, "{:?}", scaling);
+    }
+    assert_eq!(red.extract_with(0x4000, ChannelScaling::Scale), 132);
+    assert_eq!(red.extract_with(0x4000, ChannelScaling::Replicate), 0b1000_0100);
+    assert_eq!(ChannelMask::new(0x07e0).extract_with(0x0400, ChannelScaling::Replicate), 0b1000_0010);
+    assert_eq!(ChannelMask::new(1).extract_with(1, ChannelScaling::Replicate), 0xff);
+    assert_eq!(ChannelMask::new(0x3ff).extract_with(0x201, ChannelScaling::Replicate), 0x80);
+    assert!(!ChannelMask::new(0xf0f0).is_contiguous());
+  }
+
+  #[test]
+  fn treats_pixels_without_an_alpha_mask_as_opaque() {
+    let masks = ChannelMasks::new(0x7c00, 0x03e0, 0x001f, 0);
+    assert_eq!(masks.color(0x7c1f, ChannelScaling::Replicate), Rgba::new(0xff, 0, 0xff, 255));
+
+    let masks = ChannelMasks::new(0x0f00, 0x00f0, 0x000f, 0xf000);
+    assert_eq!(masks.color(0x8f00, ChannelScaling::Scale), Rgba::new(0xff, 0, 0, 0x88));
+  }
+}
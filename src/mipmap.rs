@@ -0,0 +1,93 @@
+// Mip chains for textures, filtered in linear light.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipFilter {
+  // Average of each 2x2 block.
+  #[default]
+  Box,
+  // 4x4 tent around each 2x2 block, weighted 1, 3, 3, 1 along each axis;
+  // smoother, with less aliasing on fine detail.
+  Triangle
+}
+
+// Linear premultiplied RGBA, four floats per pixel.
+struct Level {
+  width: u32,
+  height: u32,
+  samples: Vec<f32>
+}
+
+impl Level {
+  fn sample(&self, x: i64, y: i64) -> &[f32] {
+    let x = x.clamp(0, self.width as i64 - 1) as usize;
+    let y = y.clamp(0, self.height as i64 - 1) as usize;
+    let at = (y * self.width as usize + x) * 4;
+    &self.samples[at .. at + 4]
+  }
+
+  fn halved(&self, filter: MipFilter) -> Level {
+    let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+    // Source offsets and weights along one axis, relative to 2 * x.
+    let taps: &[(i64, f32)] = match filter {
+      MipFilter::Box => &[(0, 0.5), (1, 0.5)],
+      MipFilter::Triangle => &[(-1, 0.125), (0, 0.375), (1, 0.375), (2, 0.125)]
+    };
+    // A dimension already at 1 isn't halved, so it only samples in place.
+    let axis = |len: u32| if len == 1 { &[(0, 1.0)][..] } else { taps };
+    let (x_taps, y_taps) = (axis(self.width), axis(self.height));
+
+    let mut samples = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0 .. height as i64 {
+      for x in 0 .. width as i64 {
+        let mut sum = [0.0; 4];
+        for (dy, y_weight) in y_taps {
+          for (dx, x_weight) in x_taps {
+            let source_x = if self.width == 1 { x } else { 2 * x + dx };
+            let source_y = if self.height == 1 { y } else { 2 * y + dy };
+            for (total, value) in sum.iter_mut().zip(self.sample(source_x, source_y)) {
+              *total += value * x_weight * y_weight;
+            }
+          }
+        }
+        samples.extend_from_slice(&sum);
+      }
+    }
+
+    Level { width, height, samples }
+  }
+
+  fn to_bitmap(&self) -> BitmapResult<Bitmap> {
+    let straight = self.samples.chunks_exact(4)
+      .flat_map(|rgba| {
+        let alpha = rgba[3];
+        let scale = if alpha > 0.0 { 1.0 / alpha } else { 0.0 };
+        [rgba[0] * scale, rgba[1] * scale, rgba[2] * scale, alpha]
+      })
+      .collect::<Vec<_>>();
+    Bitmap::from_linear_f32(self.width, self.height, &straight)
+  }
+}
+
+impl Bitmap {
+  // Successively halved 32-bpp sRGB levels, starting at half size and
+  // ending at 1x1. Odd sizes round down. Levels are filtered in
+  // premultiplied linear light, each from the unrounded level before it, so
+  // transparent pixels don't darken edges and error doesn't build up.
+  pub fn mipmaps(&self, filter: MipFilter) -> BitmapResult<Vec<Bitmap>> {
+    let samples = self.to_linear_f32().chunks_exact(4)
+      .flat_map(|rgba| [rgba[0] * rgba[3], rgba[1] * rgba[3], rgba[2] * rgba[3], rgba[3]])
+      .collect();
+    let mut level = Level { width: self.width(), height: self.height(), samples };
+
+    let mut levels = Vec::new();
+    while level.width > 1 || level.height > 1 {
+      level = level.halved(filter);
+      levels.push(level.to_bitmap()?);
+    }
+    Ok(levels)
+  }
+}
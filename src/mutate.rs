@@ -0,0 +1,162 @@
+// Systematically corrupted variants of a valid file, for exercising
+// decoder error paths.
+
+use alloc::vec::Vec;
+
+use crate::decoder::FILE_HEADER_SIZE;
+
+// Fields of the file header and the 40-byte info header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderField {
+  Magic,
+  FileSize,
+  Reserved,
+  PixelOffset,
+  HeaderSize,
+  Width,
+  Height,
+  Planes,
+  BitsPerPixel,
+  Compression,
+  ImageSize,
+  XPelsPerMeter,
+  YPelsPerMeter,
+  Colors,
+  ImportantColors
+}
+
+impl HeaderField {
+  pub const ALL: [HeaderField; 15] = [
+    HeaderField::Magic,
+    HeaderField::FileSize,
+    HeaderField::Reserved,
+    HeaderField::PixelOffset,
+    HeaderField::HeaderSize,
+    HeaderField::Width,
+    HeaderField::Height,
+    HeaderField::Planes,
+    HeaderField::BitsPerPixel,
+    HeaderField::Compression,
+    HeaderField::ImageSize,
+    HeaderField::XPelsPerMeter,
+    HeaderField::YPelsPerMeter,
+    HeaderField::Colors,
+    HeaderField::ImportantColors
+  ];
+
+  // Byte offset in the file and width in bytes.
+  pub const fn span(&self) -> (usize, usize) {
+    match self {
+      HeaderField::Magic => (0, 2),
+      HeaderField::FileSize => (2, 4),
+      HeaderField::Reserved => (6, 4),
+      HeaderField::PixelOffset => (10, 4),
+      HeaderField::HeaderSize => (FILE_HEADER_SIZE, 4),
+      HeaderField::Width => (FILE_HEADER_SIZE + 4, 4),
+      HeaderField::Height => (FILE_HEADER_SIZE + 8, 4),
+      HeaderField::Planes => (FILE_HEADER_SIZE + 12, 2),
+      HeaderField::BitsPerPixel => (FILE_HEADER_SIZE + 14, 2),
+      HeaderField::Compression => (FILE_HEADER_SIZE + 16, 4),
+      HeaderField::ImageSize => (FILE_HEADER_SIZE + 20, 4),
+      HeaderField::XPelsPerMeter => (FILE_HEADER_SIZE + 24, 4),
+      HeaderField::YPelsPerMeter => (FILE_HEADER_SIZE + 28, 4),
+      HeaderField::Colors => (FILE_HEADER_SIZE + 32, 4),
+      HeaderField::ImportantColors => (FILE_HEADER_SIZE + 36, 4)
+    }
+  }
+
+  fn read(&self, data: &[u8]) -> Option<u32> {
+    let (at, width) = self.span();
+    let mut bytes = [0; 4];
+    bytes[.. width].copy_from_slice(data.get(at .. at + width)?);
+    Some(u32::from_le_bytes(bytes))
+  }
+}
+
+// One change made by `mutations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+  // Cut the file to this many bytes.
+  Truncate(usize),
+  // Overwrite a header field, keeping only as many low bytes as it has.
+  Field { field: HeaderField, value: u32 },
+  // Invert every bit of the byte at this offset.
+  FlipByte(usize)
+}
+
+impl Mutation {
+  pub fn apply(&self, data: &[u8]) -> Vec<u8> {
+    let mut out = data.to_vec();
+    match *self {
+      Mutation::Truncate(len) => out.truncate(len),
+      Mutation::Field { field, value } => {
+        let (at, width) = field.span();
+        if let Some(bytes) = out.get_mut(at .. at + width) {
+          bytes.copy_from_slice(&value.to_le_bytes()[.. width]);
+        }
+      }
+      Mutation::FlipByte(at) => {
+        if let Some(byte) = out.get_mut(at) {
+          *byte = !*byte;
+        }
+      }
+    }
+    out
+  }
+}
+
+// Every mutation `mutations` tries on `data`, in order: truncations at each
+// structural boundary, boundary and overflow values for each header
+// field, then a flip of each header byte. Mutations that wouldn't change
+// the file are left out.
+pub fn mutation_list(data: &[u8]) -> Vec<Mutation> {
+  let mut list = Vec::new();
+  let header_size = HeaderField::HeaderSize.read(data).unwrap_or(0) as usize;
+  let offset = HeaderField::PixelOffset.read(data).unwrap_or(0) as usize;
+
+  let mut cuts = [0, 1, 2, FILE_HEADER_SIZE - 1, FILE_HEADER_SIZE, FILE_HEADER_SIZE + 4, FILE_HEADER_SIZE + header_size - 1, offset, offset + 1, data.len() / 2, data.len().saturating_sub(1)];
+  cuts.sort_unstable();
+  let mut previous = None;
+  for len in cuts {
+    if len < data.len() && previous != Some(len) {
+      list.push(Mutation::Truncate(len));
+    }
+    previous = Some(len);
+  }
+
+  for field in HeaderField::ALL {
+    let Some(original) = field.read(data) else { continue };
+    let (_, width) = field.span();
+    let mask = if width == 4 { u32::MAX } else { (1 << (width * 8)) - 1 };
+
+    let candidates = [
+      0,
+      1,
+      original.wrapping_sub(1),
+      original.wrapping_add(1),
+      original.wrapping_mul(2),
+      original.wrapping_neg(),
+      0x7fff,
+      0xffff,
+      0x7fff_ffff,
+      0x8000_0000,
+      u32::MAX
+    ];
+    let mut seen = Vec::new();
+    for value in candidates.map(|value| value & mask) {
+      if value != original && !seen.contains(&value) {
+        seen.push(value);
+        list.push(Mutation::Field { field, value });
+      }
+    }
+  }
+
+  let headers_end = (FILE_HEADER_SIZE + header_size).min(data.len());
+  list.extend((0 .. headers_end).map(Mutation::FlipByte));
+  list
+}
+
+// Mutated copies of `data` with the mutation that made each one.
+pub fn mutations(data: &[u8]) -> impl Iterator<Item = (Mutation, Vec<u8>)> + '_ {
+  mutation_list(data).into_iter().map(move |mutation| (mutation, mutation.apply(data)))
+}
@@ -0,0 +1,46 @@
+// Conversions to and from `ndarray` arrays shaped height × width × channels.
+
+use alloc::vec::Vec;
+
+use ndarray::{Array3, ArrayView3};
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+impl Bitmap {
+  // RGBA channels for 32-bpp images, RGB for everything else.
+  pub fn to_ndarray(&self) -> Array3<u8> {
+    let (width, height) = (self.width() as usize, self.height() as usize);
+
+    match self.header.bpp {
+      32 => Array3::from_shape_vec((height, width, 4), self.to_rgba8()).unwrap(),
+      _ => Array3::from_shape_vec((height, width, 3), self.to_rgb8()).unwrap()
+    }
+  }
+
+  // One channel becomes 8-bpp gray, three 24-bpp and four 32-bpp.
+  pub fn from_ndarray(array: ArrayView3<u8>) -> BitmapResult<Bitmap> {
+    let (height, width, channels) = array.dim();
+    let pixel_at = |y: usize, x: usize| {
+      let channel = |c: usize| array[[y, x, c]];
+      match channels {
+        1 => Pixel::PaletteColor(channel(0)),
+        3 => Pixel::BGR(channel(2), channel(1), channel(0)),
+        _ => Pixel::ABGR(channel(2), channel(1), channel(0), channel(3))
+      }
+    };
+
+    let (bpp, palette) = match channels {
+      1 => (8, (0 ..= 255).map(|level| Rgba::new(level, level, level, 255)).collect()),
+      3 => (24, Vec::new()),
+      4 => (32, Vec::new()),
+      _ => return Err(BitmapError::UnsupportedBitsPerPixel)
+    };
+
+    let pixels = (0 .. height)
+      .flat_map(|y| (0 .. width).map(move |x| (y, x)))
+      .map(|(y, x)| pixel_at(y, x))
+      .collect();
+
+    Bitmap::from_parts(width as u32, height as u32, bpp, palette, pixels)
+  }
+}
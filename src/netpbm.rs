@@ -0,0 +1,65 @@
+// Netpbm output: binary PPM (P6) and PAM (P7).
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::Bitmap;
+
+impl Bitmap {
+  // Binary PPM; alpha is dropped.
+  pub fn to_ppm(&self) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", self.width(), self.height()).into_bytes();
+    out.extend_from_slice(&self.to_rgb8());
+    out
+  }
+
+  // PAM keeps alpha as RGB_ALPHA for 32-bpp images, plain RGB otherwise.
+  pub fn to_pam(&self) -> Vec<u8> {
+    let (depth, tuple_type, pixels) = match self.header.bpp {
+      32 => (4, "RGB_ALPHA", self.to_rgba8()),
+      _ => (3, "RGB", self.to_rgb8())
+    };
+
+    let mut out = format!(
+      "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL 255\nTUPLTYPE {}\nENDHDR\n",
+      self.width(), self.height(), depth, tuple_type
+    ).into_bytes();
+    out.extend_from_slice(&pixels);
+    out
+  }
+
+  #[cfg(feature = "std")]
+  pub fn write_ppm<W: std::io::Write>(&self, writer: &mut W) -> crate::BitmapResult<()> {
+    writer.write_all(&self.to_ppm())?;
+    Ok(())
+  }
+
+  #[cfg(feature = "std")]
+  pub fn write_pam<W: std::io::Write>(&self, writer: &mut W) -> crate::BitmapResult<()> {
+    writer.write_all(&self.to_pam())?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::encode::tests::sample;
+
+  #[test]
+  fn writes_headers_and_resolved_pixels() {
+    let ppm = sample(8, 5).to_ppm();
+    let header = b"P6\n5 3\n255\n";
+    assert_eq!((&ppm[.. header.len()], ppm.len()), (&header[..], header.len() + 15 * 3));
+    // The first two pixels use palette entries 0 and 5.
+    assert_eq!(ppm[header.len() .. header.len() + 6], [0, 0, 255, 5, 35, 250]);
+
+    let pam = sample(32, 5).to_pam();
+    let header = b"P7\nWIDTH 5\nHEIGHT 3\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+    assert_eq!((&pam[.. header.len()], pam.len()), (&header[..], header.len() + 15 * 4));
+    assert_eq!(pam[header.len() .. header.len() + 8], [0, 0, 0, 1, 7, 3, 1, 12]);
+
+    let pam = sample(24, 5).to_pam();
+    let header = b"P7\nWIDTH 5\nHEIGHT 3\nDEPTH 3\nMAXVAL 255\nTUPLTYPE RGB\nENDHDR\n";
+    assert_eq!((&pam[.. header.len()], pam.len()), (&header[..], header.len() + 15 * 3));
+  }
+}
@@ -0,0 +1,73 @@
+// Choices that change how a file is turned into pixels.
+
+use crate::{AlphaMode, ChannelScaling};
+
+// What to make of the fourth byte of 32-bpp pixels, which plain
+// BITMAPINFOHEADER files leave unused and often fill with zeros or junk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlphaHandling {
+  // Keep the stored alpha.
+  Trust,
+  // Make every pixel opaque.
+  Opaque,
+  // Keep the stored alpha unless every pixel is fully transparent, which
+  // means the byte was left unused.
+  #[default]
+  Auto
+}
+
+// What to do with an indexed image's palette when it has more entries
+// than the depth can index, or too few for the indices its pixels use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PaletteHandling {
+  // Fail with `BitmapError::BadPalette`.
+  Strict,
+  // Drop the excess entries and pad with opaque black, logging a warning
+  // with the `tracing` feature.
+  #[default]
+  Lenient
+}
+
+// Settings for `Bitmap::from_vec_with`; `from_vec` uses the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecodeOptions {
+  pub alpha: AlphaHandling,
+  // Widening of bitfield channels narrower than 8 bits.
+  pub scaling: ChannelScaling,
+  pub palette: PaletteHandling,
+  // What to make of the reserved fourth byte of palette entries, which
+  // some tools use as alpha. Defaults to `Opaque`, as the format intends.
+  pub palette_alpha: AlphaHandling,
+  // Whether to premultiply 32-bpp pixels after decoding, for compositors
+  // that work that way. `Bitmap::alpha_mode` records the result.
+  pub alpha_mode: AlphaMode
+}
+
+impl Default for DecodeOptions {
+  fn default() -> DecodeOptions {
+    DecodeOptions {
+      alpha: AlphaHandling::default(),
+      scaling: ChannelScaling::default(),
+      palette: PaletteHandling::default(),
+      palette_alpha: AlphaHandling::Opaque,
+      alpha_mode: AlphaMode::Straight
+    }
+  }
+}
+
+// Settings for `Bitmap::encode_with`; `encode` uses the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodeOptions {
+  // Store palette alpha in the reserved fourth byte of each entry instead
+  // of zero. Readers that don't expect it will ignore it.
+  pub palette_alpha: bool
+}
+
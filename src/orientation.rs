@@ -0,0 +1,57 @@
+// Row order of the stored pixel array, kept alongside the decoded pixels.
+
+use crate::{Bitmap, Decoder, Pixel};
+
+// How rows are laid out in the file. `Bitmap::pixels` is always top-down
+// whatever this says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScanlineOrder {
+  // First row stored is the top one; negative heights in the header.
+  TopDown,
+  // First row stored is the bottom one, as for Win32 DIB sections.
+  #[default]
+  BottomUp
+}
+
+impl ScanlineOrder {
+  // Order declared by a header height.
+  pub const fn from_height(pix_height: i32) -> ScanlineOrder {
+    if pix_height < 0 { ScanlineOrder::TopDown } else { ScanlineOrder::BottomUp }
+  }
+
+  // `height` as stored in a header for this order.
+  pub const fn signed_height(self, height: u32) -> i32 {
+    match self {
+      ScanlineOrder::TopDown => -(height as i32),
+      ScanlineOrder::BottomUp => height as i32
+    }
+  }
+}
+
+impl Bitmap {
+  // Rows of `pixels` in the given order, e.g. `BottomUp` to copy straight
+  // into a bottom-up target without flipping first.
+  pub fn rows(&self, order: ScanlineOrder) -> impl ExactSizeIterator<Item = &[Pixel]> + '_ {
+    let (width, height) = (self.width() as usize, self.height());
+    (0 .. height).map(move |index| {
+      let y = match order {
+        ScanlineOrder::TopDown => index,
+        ScanlineOrder::BottomUp => height - 1 - index
+      } as usize;
+      self.pixels.get(y * width .. (y + 1) * width).unwrap_or(&[])
+    })
+  }
+
+  // Rows in the order `orientation` stores them.
+  pub fn stored_rows(&self) -> impl ExactSizeIterator<Item = &[Pixel]> + '_ {
+    self.rows(self.orientation)
+  }
+}
+
+impl Decoder<'_> {
+  pub fn orientation(&self) -> ScanlineOrder {
+    ScanlineOrder::from_height(self.header.pix_height)
+  }
+}
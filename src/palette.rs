@@ -0,0 +1,353 @@
+// Palette maintenance for indexed images.
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "perceptual")]
+use crate::{DeltaE, Lab};
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+// Cells per channel of the lookup grid over RGB space.
+const GRID: usize = 8;
+const CELL_SIZE: i32 = 256 / GRID as i32;
+
+// How `Palette::nearest` measures the difference between two colors.
+// Alpha never counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorMetric {
+  // Squared distance in RGB.
+  #[default]
+  Rgb,
+  // Squared distance with each channel's difference weighted, e.g. 2, 4, 3
+  // to follow the eye's greater sensitivity to green.
+  WeightedRgb { r: u32, g: u32, b: u32 },
+  // Delta E between the colors in CIELAB.
+  #[cfg(feature = "perceptual")]
+  Perceptual(DeltaE)
+}
+
+impl ColorMetric {
+  // Channel weights of the RGB metrics, which the lookup grid relies on.
+  fn weights(self) -> Option<[u32; 3]> {
+    match self {
+      ColorMetric::Rgb => Some([1, 1, 1]),
+      ColorMetric::WeightedRgb { r, g, b } => Some([r, g, b]),
+      #[cfg(feature = "perceptual")]
+      ColorMetric::Perceptual(_) => None
+    }
+  }
+}
+
+pub(crate) fn weighted_distance(weights: [u32; 3], from: [i32; 3], to: [i32; 3]) -> u64 {
+  (0 .. 3).map(|channel| weights[channel] as u64 * (from[channel] - to[channel]).pow(2) as u64).sum()
+}
+
+// Up to 256 colors with a lookup structure for fast nearest-entry queries.
+// For the RGB metrics each cell of a grid over RGB space keeps only the
+// entries that can be nearest to some color in it, so a query scans a
+// handful of entries instead of the whole palette. Perceptual metrics
+// convert the entries to Lab once and scan them all.
+#[derive(Debug, Clone)]
+pub struct Palette {
+  colors: Vec<Rgba>,
+  metric: ColorMetric,
+  cells: Vec<Vec<u8>>,
+  #[cfg(feature = "perceptual")]
+  labs: Vec<Lab>
+}
+
+impl Palette {
+  // Builds the lookup structure up front; entries past the 256th are dropped.
+  pub fn new(colors: &[Rgba], metric: ColorMetric) -> Palette {
+    let colors = colors[.. colors.len().min(256)].to_vec();
+    let cells = match metric.weights() {
+      Some(weights) => grid(&colors, weights),
+      None => Vec::new()
+    };
+
+    #[cfg(feature = "perceptual")]
+    let labs = match metric {
+      ColorMetric::Perceptual(_) => colors.iter().map(|color| Lab::from_rgba(*color)).collect(),
+      _ => Vec::new()
+    };
+
+    Palette {
+      colors,
+      metric,
+      cells,
+      #[cfg(feature = "perceptual")]
+      labs
+    }
+  }
+
+  pub fn colors(&self) -> &[Rgba] {
+    &self.colors
+  }
+
+  pub fn metric(&self) -> ColorMetric {
+    self.metric
+  }
+
+  // Index of the entry closest to `color` under the metric; the lowest
+  // index wins ties, and an empty palette gives 0.
+  pub fn nearest(&self, color: Rgba) -> u8 {
+    #[cfg(feature = "perceptual")]
+    if let ColorMetric::Perceptual(formula) = self.metric {
+      let lab = Lab::from_rgba(color);
+      return self.labs.iter()
+        .map(|entry| formula.difference(lab, *entry))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(index, _)| index as u8)
+    }
+
+    let weights = self.metric.weights().unwrap_or([1; 3]);
+    let channels = [color.r as i32, color.g as i32, color.b as i32];
+    let cell = [color.r, color.g, color.b].map(|value| value as usize / CELL_SIZE as usize);
+    self.cells[(cell[0] * GRID + cell[1]) * GRID + cell[2]].iter()
+      .min_by_key(|index| {
+        let entry = self.colors[**index as usize];
+        weighted_distance(weights, channels, [entry.r as i32, entry.g as i32, entry.b as i32])
+      })
+      .map_or(0, |index| *index)
+  }
+}
+
+// Candidate entries for each grid cell under weighted RGB distance.
+fn grid(colors: &[Rgba], weights: [u32; 3]) -> Vec<Vec<u8>> {
+  let channels = colors.iter().map(|color| [color.r as i32, color.g as i32, color.b as i32]).collect::<Vec<_>>();
+  let distance = |from: [i32; 3], to: [i32; 3]| weighted_distance(weights, from, to);
+
+  (0 .. GRID * GRID * GRID)
+    .map(|cell| {
+      let low = [cell / (GRID * GRID), cell / GRID % GRID, cell % GRID].map(|at| at as i32 * CELL_SIZE);
+      // Closest and farthest points of the cell from each entry.
+      let nearest = |entry: &[i32; 3]| core::array::from_fn(|channel| entry[channel].clamp(low[channel], low[channel] + CELL_SIZE - 1));
+      let farthest = |entry: &[i32; 3]| core::array::from_fn(|channel| {
+        let mid = low[channel] + CELL_SIZE / 2;
+        if entry[channel] < mid { low[channel] + CELL_SIZE - 1 } else { low[channel] }
+      });
+
+      // No color in the cell is farther than this from its nearest entry.
+      let bound = channels.iter().map(|entry| distance(*entry, farthest(entry))).min().unwrap_or(0);
+      (0 .. channels.len())
+        .filter(|index| distance(channels[*index], nearest(&channels[*index])) <= bound)
+        .map(|index| index as u8)
+        .collect()
+    })
+    .collect()
+}
+
+// Trims `palette` to the entries a `bpp` image can index and pads it with
+// opaque black to cover every index in `pixels`. Returns whether it
+// changed; truecolor color tables are left alone.
+pub(crate) fn fit_palette(palette: &mut Vec<Rgba>, pixels: &[Pixel], bpp: u16) -> bool {
+  if bpp > 8 {
+    return false
+  }
+
+  let before = palette.len();
+  let needed = pixels.iter()
+    .filter_map(|pixel| match pixel {
+      Pixel::PaletteColor(index) => Some(*index as usize + 1),
+      _ => None
+    })
+    .max()
+    .unwrap_or(0);
+
+  palette.truncate(1 << bpp);
+  let clamped = palette.len() < before;
+  if clamped {
+    trace_warn!(declared = before, kept = palette.len(), bpp, "palette longer than the depth allows");
+  }
+
+  let padded = palette.len() < needed;
+  if padded {
+    trace_warn!(entries = palette.len(), needed, "palette too short for the pixel indices");
+    palette.resize(needed, Rgba::BLACK);
+  }
+
+  clamped || padded
+}
+
+impl Bitmap {
+  // Rebuilds the palette of an indexed image from the colors its pixels
+  // actually use, merging exact RGBA duplicates, and drops to the smallest of 1, 4 or
+  // 8 bpp that holds them. Returns the new depth; truecolor images are left
+  // alone.
+  pub fn optimize_palette(&mut self) -> u16 {
+    if self.header.bpp > 8 {
+      return self.header.bpp
+    }
+
+    let mut palette: Vec<Rgba> = Vec::new();
+    let mut pixels = Vec::with_capacity(self.pixels.len());
+    // New index of each old one, so most pixels skip the search.
+    let mut remapped = [None; 256];
+
+    for pixel in &self.pixels {
+      if let Pixel::PaletteColor(old) = pixel {
+        if let Some(index) = remapped[*old as usize] {
+          pixels.push(Pixel::PaletteColor(index));
+          continue
+        }
+      }
+
+      let color = self.color(*pixel);
+      let index = match palette.iter().position(|entry| *entry == color) {
+        Some(index) => index,
+        None if palette.len() < 256 => {
+          palette.push(color);
+          palette.len() - 1
+        }
+        None => return self.header.bpp
+      };
+
+      if let Pixel::PaletteColor(old) = pixel {
+        remapped[*old as usize] = Some(index as u8);
+      }
+      pixels.push(Pixel::PaletteColor(index as u8));
+    }
+
+    let bpp = indexed_depth(palette.len());
+
+    self.header.bpp = bpp;
+    self.header.colors = palette.len() as u32;
+    self.header.important_colors = 0;
+    self.palette = palette;
+    self.pixels = pixels;
+    bpp
+  }
+
+  // Re-indexes every pixel onto `palette`, for forcing assets onto a fixed
+  // platform palette. With `nearest` each color takes the closest entry;
+  // otherwise every color must appear exactly (alpha aside) or the image is
+  // left unchanged. Indexed images keep their depth when the palette fits.
+  pub fn remap_palette(&mut self, palette: &[Rgba], nearest: bool) -> BitmapResult<()> {
+    if palette.is_empty() || palette.len() > 256 {
+      return Err(BitmapError::BadPalette)
+    }
+
+    let lookup = nearest.then(|| Palette::new(palette, ColorMetric::Rgb));
+    let mut pixels = Vec::with_capacity(self.pixels.len());
+    let mut remapped = [None; 256];
+
+    for pixel in &self.pixels {
+      if let Pixel::PaletteColor(old) = pixel {
+        if let Some(index) = remapped[*old as usize] {
+          pixels.push(Pixel::PaletteColor(index));
+          continue
+        }
+      }
+
+      let color = self.color(*pixel);
+      let index = if let Some(lookup) = &lookup {
+        lookup.nearest(color)
+      } else {
+        match palette.iter().position(|entry| entry.r == color.r && entry.g == color.g && entry.b == color.b) {
+          Some(index) => index as u8,
+          None => return Err(BitmapError::BadPalette)
+        }
+      };
+
+      if let Pixel::PaletteColor(old) = pixel {
+        remapped[*old as usize] = Some(index);
+      }
+      pixels.push(Pixel::PaletteColor(index));
+    }
+
+    let bpp = if self.header.bpp <= 8 && palette.len() <= 1 << self.header.bpp {
+      self.header.bpp
+    } else {
+      indexed_depth(palette.len())
+    };
+
+    self.header.bpp = bpp;
+    self.header.colors = palette.len() as u32;
+    self.header.important_colors = 0;
+    self.palette = palette.to_vec();
+    self.pixels = pixels;
+    Ok(())
+  }
+
+  // The palette entries `biClrImportant` marks as significant, which is
+  // all of them when it is zero or larger than the palette.
+  pub fn important_palette(&self) -> &[Rgba] {
+    match self.header.important_colors as usize {
+      0 => &self.palette,
+      important => &self.palette[.. important.min(self.palette.len())]
+    }
+  }
+
+  // Remaps every pixel onto the nearest important entry, so the rest of
+  // the palette can be dropped. Does nothing if every entry is important.
+  pub fn restrict_to_important(&mut self) -> BitmapResult<()> {
+    if self.header.bpp > 8 || self.important_palette().len() == self.palette.len() {
+      return Ok(())
+    }
+
+    let important = self.important_palette().to_vec();
+    self.remap_palette(&important, true)
+  }
+}
+
+// Smallest indexed depth with room for `colors` entries.
+pub(crate) fn indexed_depth(colors: usize) -> u16 {
+  match colors {
+    0 ..= 2 => 1,
+    3 ..= 16 => 4,
+    _ => 8
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+  use alloc::vec::Vec;
+
+  use crate::palette::weighted_distance;
+  use crate::{Bitmap, ColorMetric, Palette, Pixel, Rgba};
+
+  #[test]
+  fn optimized_palettes_keep_alpha() {
+    let palette = vec![Rgba::new(9, 9, 9, 255), Rgba::new(1, 2, 3, 0), Rgba::new(1, 2, 3, 255), Rgba::new(1, 2, 3, 0)];
+    let pixels = [1, 2, 3, 1].iter().map(|index| Pixel::PaletteColor(*index)).collect();
+    let mut bitmap = Bitmap::from_parts(4, 1, 8, palette, pixels).unwrap();
+
+    assert_eq!(bitmap.optimize_palette(), 1);
+    assert_eq!(bitmap.palette, [Rgba::new(1, 2, 3, 0), Rgba::new(1, 2, 3, 255)]);
+    assert_eq!(bitmap.pixels, [Pixel::PaletteColor(0), Pixel::PaletteColor(1), Pixel::PaletteColor(0), Pixel::PaletteColor(0)]);
+  }
+
+  #[test]
+  fn grid_lookups_match_a_full_scan() {
+    // Scattered entries from a small LCG, with a repeat to exercise ties.
+    let mut state = 12345u32;
+    let mut colors = (0 .. 40)
+      .map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let [r, g, b, _] = state.to_be_bytes();
+        Rgba::new(r, g, b, 255)
+      })
+      .collect::<Vec<_>>();
+    colors.push(colors[7]);
+
+    for (metric, weights) in [(ColorMetric::Rgb, [1, 1, 1]), (ColorMetric::WeightedRgb { r: 2, g: 4, b: 3 }, [2, 4, 3])] {
+      let palette = Palette::new(&colors, metric);
+      for r in (0 ..= 255).step_by(9) {
+        for g in (0 ..= 255).step_by(9) {
+          for b in (0 ..= 255).step_by(9) {
+            let color = [r, g, b];
+            let scan = colors.iter()
+              .enumerate()
+              .min_by_key(|(_, entry)| weighted_distance(weights, color, [entry.r as i32, entry.g as i32, entry.b as i32]))
+              .map(|(index, _)| index as u8);
+            assert_eq!(Some(palette.nearest(Rgba::new(r as u8, g as u8, b as u8, 0))), scan, "{:?} under {:?}", color, metric);
+          }
+        }
+      }
+    }
+
+    assert_eq!(Palette::new(&[], ColorMetric::Rgb).nearest(Rgba::WHITE), 0);
+  }
+}
@@ -0,0 +1,65 @@
+// Rewriting part of an existing file in place.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::decoder::read_u32;
+use crate::{Bitmap, BitmapError, BitmapResult, CompressionMethod, Decoder, Rect, Rgba, ScanlineOrder};
+
+impl Bitmap {
+  // Writes the pixels of `dirty` into `file`, an uncompressed bitmap of the
+  // same size, depth and palette, seeking to each affected row and
+  // rewriting only the bytes that hold those columns. The rest of the file,
+  // headers included, is left untouched. `dirty` is clipped to the image.
+  pub fn patch_file<F: Read + Write + Seek>(&self, file: &mut F, dirty: Rect) -> BitmapResult<()> {
+    let mut head = vec![0; 14];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut head)?;
+    let offset = read_u32(&head, 10)? as usize;
+    head.resize(offset.max(14), 0);
+    file.read_exact(&mut head[14 ..])?;
+
+    let decoder = Decoder::headers(&head)?;
+    if decoder.header.method != CompressionMethod::None {
+      return Err(BitmapError::UnsupportedCompression)
+    }
+    if (decoder.width(), decoder.height(), decoder.header.bpp) != (self.width(), self.height(), self.header.bpp) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    // Indices only mean the same colors if the palettes agree.
+    let bpp = self.header.bpp;
+    if bpp <= 8 {
+      let stored = (0 .. decoder.palette_len().min(256)).map_while(|index| decoder.palette_entry(index as u8)).map(Rgba::from_rgbquad);
+      if !stored.eq(self.palette.iter().map(|color| Rgba { a: 255, ..*color })) {
+        return Err(BitmapError::BadPalette)
+      }
+    }
+
+    let x_end = dirty.x.saturating_add(dirty.width).min(self.width());
+    let y_end = dirty.y.saturating_add(dirty.height).min(self.height());
+    if dirty.x >= x_end || dirty.y >= y_end {
+      return Ok(())
+    }
+
+    // Whole bytes covering the columns, since low depths share bytes.
+    let start = dirty.x as usize * bpp as usize / 8;
+    let end = (x_end as usize * bpp as usize).div_ceil(8);
+    let stride = decoder.stride();
+    let mut row = vec![0; stride];
+
+    for y in dirty.y .. y_end {
+      row.fill(0);
+      self.encode_row(y, &mut row);
+
+      let stored = match decoder.orientation() {
+        ScanlineOrder::TopDown => y,
+        ScanlineOrder::BottomUp => self.height() - 1 - y
+      };
+      file.seek(SeekFrom::Start((offset + stored as usize * stride + start) as u64))?;
+      file.write_all(&row[start .. end])?;
+    }
+
+    file.flush()?;
+    Ok(())
+  }
+}
@@ -0,0 +1,89 @@
+// Deterministic test images, for tests that shouldn't need fixture files.
+// Everything is 32 bpp and stored bottom-up.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapResult, Pixel, Rgba};
+
+// Full-intensity color bars, left to right.
+const BARS: [Rgba; 8] = [
+  Rgba::WHITE,
+  Rgba::new(255, 255, 0, 255),
+  Rgba::new(0, 255, 255, 255),
+  Rgba::new(0, 255, 0, 255),
+  Rgba::new(255, 0, 255, 255),
+  Rgba::new(255, 0, 0, 255),
+  Rgba::new(0, 0, 255, 255),
+  Rgba::BLACK
+];
+
+// Axis a `gradient` runs along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+  #[default]
+  Horizontal,
+  Vertical,
+  // From the top-left corner to the bottom-right.
+  Diagonal
+}
+
+fn from_fn(width: u32, height: u32, mut color: impl FnMut(u32, u32) -> Rgba) -> BitmapResult<Bitmap> {
+  let mut pixels = Vec::with_capacity(width as usize * height as usize);
+  for y in 0 .. height {
+    for x in 0 .. width {
+      let color = color(x, y);
+      pixels.push(Pixel::ABGR(color.b, color.g, color.r, color.a));
+    }
+  }
+
+  Bitmap::from_parts(width, height, 32, Vec::new(), pixels)
+}
+
+// Squares of `cell` pixels alternating between `a` and `b`, with `a` at
+// the top-left.
+pub fn checkerboard(width: u32, height: u32, cell: u32, a: Rgba, b: Rgba) -> BitmapResult<Bitmap> {
+  let cell = cell.max(1);
+  from_fn(width, height, |x, y| if (x / cell + y / cell).is_multiple_of(2) { a } else { b })
+}
+
+// Linear blend from `start` to `end`, alpha included, reaching both at the
+// first and last pixels along `direction`.
+pub fn gradient(width: u32, height: u32, start: Rgba, end: Rgba, direction: Direction) -> BitmapResult<Bitmap> {
+  let (max_x, max_y) = (width.saturating_sub(1) as u64, height.saturating_sub(1) as u64);
+  let lerp = |a: u8, b: u8, step: u64, steps: u64| {
+    let (a, b) = (a as u64, b as u64);
+    match steps {
+      0 => a as u8,
+      _ => ((a * (steps - step) + b * step + steps / 2) / steps) as u8
+    }
+  };
+
+  from_fn(width, height, |x, y| {
+    let (step, steps) = match direction {
+      Direction::Horizontal => (x as u64, max_x),
+      Direction::Vertical => (y as u64, max_y),
+      Direction::Diagonal => (x as u64 + y as u64, max_x + max_y)
+    };
+    Rgba::new(lerp(start.r, end.r, step, steps), lerp(start.g, end.g, step, steps), lerp(start.b, end.b, step, steps), lerp(start.a, end.a, step, steps))
+  })
+}
+
+// Eight equal vertical bars: white, yellow, cyan, green, magenta, red,
+// blue and black.
+pub fn color_bars(width: u32, height: u32) -> BitmapResult<Bitmap> {
+  from_fn(width, height, |x, _| BARS[(x as u64 * BARS.len() as u64 / width as u64) as usize])
+}
+
+// Opaque uniform noise from a xorshift32 generator; the same seed always
+// gives the same image.
+pub fn noise(width: u32, height: u32, seed: u32) -> BitmapResult<Bitmap> {
+  // Zero is xorshift's one fixed point.
+  let mut state = seed.max(1);
+  from_fn(width, height, |_, _| {
+    state ^= state << 13;
+    state ^= state >> 17;
+    state ^= state << 5;
+    let [r, g, b, _] = state.to_le_bytes();
+    Rgba::new(r, g, b, 255)
+  })
+}
@@ -0,0 +1,116 @@
+// PNG export: indexed images stay indexed, truecolor maps to RGB or RGBA.
+
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+
+use crate::{Bitmap, BitmapError, BitmapResult, Rgba};
+
+impl From<EncodingError> for BitmapError {
+  fn from(err: EncodingError) -> BitmapError {
+    match err {
+      EncodingError::IoError(err) => BitmapError::BitmapIOError(err),
+      _ => BitmapError::InvalidBitmapData
+    }
+  }
+}
+
+impl Bitmap {
+  pub fn encode_png(&self) -> BitmapResult<Vec<u8>> {
+    let (width, height, bpp) = (self.width(), self.height(), self.header.bpp);
+    let mut out = Vec::new();
+    let mut encoder = Encoder::new(&mut out, width, height);
+
+    let data = match bpp {
+      1 | 4 | 8 => {
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(match bpp {
+          1 => BitDepth::One,
+          4 => BitDepth::Four,
+          _ => BitDepth::Eight
+        });
+
+        // PNG needs a palette entry for every index used, so a short one is
+        // padded with opaque black, and alpha below 255 goes in a tRNS chunk
+        // that stops after the last translucent entry.
+        let used = self.pixels.iter().map(|pixel| self.palette_index(*pixel) as usize + 1).max().unwrap_or(1);
+        let mut palette = self.palette[.. self.palette.len().min(1 << bpp)].to_vec();
+        if palette.len() < used.min(1 << bpp) {
+          palette.resize(used.min(1 << bpp), Rgba::new(0, 0, 0, 255));
+        }
+        encoder.set_palette(palette.iter().flat_map(|color| [color.r, color.g, color.b]).collect::<Vec<_>>());
+        if let Some(last) = palette.iter().rposition(|color| color.a < 255) {
+          encoder.set_trns(palette[..= last].iter().map(|color| color.a).collect::<Vec<_>>());
+        }
+
+        // BMP packs sub-byte indices most significant bit first, exactly like PNG.
+        let row_len = (width as usize * bpp as usize).div_ceil(8);
+        let mut data = vec![0; row_len * height as usize];
+        for (y, row) in data.chunks_exact_mut(row_len).enumerate() {
+          self.encode_row(y as u32, row);
+        }
+        data
+      }
+
+      32 => {
+        encoder.set_color(ColorType::Rgba);
+        self.to_rgba8()
+      }
+
+      _ => {
+        encoder.set_color(ColorType::Rgb);
+        self.to_rgb8()
+      }
+    };
+
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(out)
+  }
+
+  #[cfg(feature = "fs")]
+  pub fn save_png<P: AsRef<Path>>(&self, path: P) -> BitmapResult<()> {
+    std::fs::write(path, self.encode_png()?)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use png::{Decoder, Transformations};
+
+  use crate::{Bitmap, Pixel, Rgba};
+
+  fn decode(png: &[u8]) -> (png::ColorType, Vec<u8>) {
+    let mut decoder = Decoder::new(std::io::Cursor::new(png));
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    pixels.truncate(info.buffer_size());
+    (info.color_type, pixels)
+  }
+
+  #[test]
+  fn indexed_images_keep_palette_alpha() {
+    let palette = vec![Rgba::new(10, 20, 30, 255), Rgba::new(40, 50, 60, 0)];
+    let bitmap = Bitmap::from_parts(2, 1, 8, palette, vec![Pixel::PaletteColor(0), Pixel::PaletteColor(1)]).unwrap();
+    assert_eq!(decode(&bitmap.encode_png().unwrap()), (png::ColorType::Rgba, vec![10, 20, 30, 255, 40, 50, 60, 0]));
+  }
+
+  #[test]
+  fn short_palettes_are_padded() {
+    let mut bitmap = Bitmap::from_parts(2, 1, 4, vec![Rgba::new(10, 20, 30, 255)], vec![Pixel::PaletteColor(0); 2]).unwrap();
+    bitmap.pixels[1] = Pixel::PaletteColor(3);
+    assert_eq!(decode(&bitmap.encode_png().unwrap()), (png::ColorType::Rgb, vec![10, 20, 30, 0, 0, 0]));
+
+    bitmap.palette.clear();
+    assert_eq!(decode(&bitmap.encode_png().unwrap()), (png::ColorType::Rgb, vec![0; 6]));
+  }
+
+  #[test]
+  fn truecolor_round_trips() {
+    let bitmap = Bitmap::from_parts(2, 1, 32, vec![], vec![Pixel::ABGR(1, 2, 3, 4), Pixel::ABGR(5, 6, 7, 255)]).unwrap();
+    assert_eq!(decode(&bitmap.encode_png().unwrap()), (png::ColorType::Rgba, vec![3, 2, 1, 4, 7, 6, 5, 255]));
+  }
+}
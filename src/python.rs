@@ -0,0 +1,75 @@
+// pyo3 bindings exposing a `microbmp.Bitmap` class to Python.
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::{Bitmap, BitmapError};
+
+impl From<BitmapError> for PyErr {
+  fn from(err: BitmapError) -> PyErr {
+    match err.root_cause() {
+      BitmapError::BitmapIOError(_) => PyIOError::new_err(err.to_string()),
+      _ => PyValueError::new_err(err.to_string())
+    }
+  }
+}
+
+#[pyclass(name = "Bitmap", module = "microbmp")]
+pub struct PyBitmap {
+  bitmap: Bitmap
+}
+
+#[pymethods]
+impl PyBitmap {
+  #[staticmethod]
+  pub fn open(path: std::path::PathBuf) -> PyResult<PyBitmap> {
+    Ok(PyBitmap { bitmap: Bitmap::open(path)? })
+  }
+
+  #[staticmethod]
+  pub fn from_bytes(data: &[u8]) -> PyResult<PyBitmap> {
+    Ok(PyBitmap { bitmap: Bitmap::from_bytes(data)? })
+  }
+
+  #[getter]
+  pub fn width(&self) -> u32 {
+    self.bitmap.width()
+  }
+
+  #[getter]
+  pub fn height(&self) -> u32 {
+    self.bitmap.height()
+  }
+
+  #[getter]
+  pub fn bpp(&self) -> u16 {
+    self.bitmap.header.bpp
+  }
+
+  // Top-down pixel bytes in "RGBA" or "RGB" mode, like PIL's `tobytes`.
+  #[pyo3(signature = (mode = "RGBA"))]
+  pub fn to_bytes<'py>(&self, py: Python<'py>, mode: &str) -> PyResult<Bound<'py, PyBytes>> {
+    let data = match mode {
+      "RGBA" => self.bitmap.to_rgba8(),
+      "RGB" => self.bitmap.to_rgb8(),
+      _ => return Err(PyValueError::new_err(format!("unsupported mode {:?}", mode)))
+    };
+
+    Ok(PyBytes::new(py, &data))
+  }
+
+  pub fn save(&self, path: std::path::PathBuf) -> PyResult<()> {
+    Ok(self.bitmap.save(path)?)
+  }
+
+  fn __repr__(&self) -> String {
+    format!("<microbmp.Bitmap {}x{} {}bpp>", self.bitmap.width(), self.bitmap.height(), self.bitmap.header.bpp)
+  }
+}
+
+#[pymodule]
+#[pyo3(name = "microbmp")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+  module.add_class::<PyBitmap>()
+}
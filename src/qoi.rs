@@ -0,0 +1,183 @@
+// QOI ("Quite OK Image") conversion, implemented in-crate from the spec.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+const MAGIC: &[u8; 4] = b"qoif";
+const HEADER_SIZE: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const MASK_2: u8 = 0xc0;
+
+// Same limit as the reference implementation, to bound allocations.
+const MAX_PIXELS: u64 = 400_000_000;
+
+fn hash(color: Rgba) -> usize {
+  (color.r as usize * 3 + color.g as usize * 5 + color.b as usize * 7 + color.a as usize * 11) % 64
+}
+
+impl Bitmap {
+  // 32-bpp images are written with four channels, everything else with three.
+  pub fn to_qoi(&self) -> Vec<u8> {
+    let channels: u8 = if self.header.bpp == 32 { 4 } else { 3 };
+    let mut out = Vec::with_capacity(HEADER_SIZE + self.pixels.len() + END_MARKER.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&self.width().to_be_bytes());
+    out.extend_from_slice(&self.height().to_be_bytes());
+    out.extend_from_slice(&[channels, 0]);
+
+    let mut index = [Rgba::default(); 64];
+    let mut prev = Rgba::new(0, 0, 0, 255);
+    let mut run = 0u8;
+
+    for (position, pixel) in self.pixels.iter().enumerate() {
+      let color = self.straight_color(*pixel);
+      let color = if channels == 3 { Rgba { a: 255, ..color } } else { color };
+
+      if color == prev {
+        run += 1;
+        if run == 62 || position + 1 == self.pixels.len() {
+          out.push(OP_RUN | (run - 1));
+          run = 0;
+        }
+        continue
+      }
+
+      if run > 0 {
+        out.push(OP_RUN | (run - 1));
+        run = 0;
+      }
+
+      let slot = hash(color);
+      if index[slot] == color {
+        out.push(OP_INDEX | slot as u8);
+      } else if color.a == prev.a {
+        index[slot] = color;
+        let dr = color.r.wrapping_sub(prev.r) as i8;
+        let dg = color.g.wrapping_sub(prev.g) as i8;
+        let db = color.b.wrapping_sub(prev.b) as i8;
+        let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+
+        if (-2 ..= 1).contains(&dr) && (-2 ..= 1).contains(&dg) && (-2 ..= 1).contains(&db) {
+          out.push(OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+        } else if (-32 ..= 31).contains(&dg) && (-8 ..= 7).contains(&dr_dg) && (-8 ..= 7).contains(&db_dg) {
+          out.extend_from_slice(&[OP_LUMA | (dg + 32) as u8, ((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8]);
+        } else {
+          out.extend_from_slice(&[OP_RGB, color.r, color.g, color.b]);
+        }
+      } else {
+        index[slot] = color;
+        out.extend_from_slice(&[OP_RGBA, color.r, color.g, color.b, color.a]);
+      }
+
+      prev = color;
+    }
+
+    out.extend_from_slice(&END_MARKER);
+    out
+  }
+
+  // Four-channel files decode to 32-bpp, three-channel ones to 24-bpp.
+  pub fn from_qoi(data: &[u8]) -> BitmapResult<Bitmap> {
+    if data.len() < HEADER_SIZE + END_MARKER.len() || &data[0..4] != MAGIC {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    let channels = data[12];
+    let count = width as u64 * height as u64;
+
+    if width == 0 || height == 0 || count > MAX_PIXELS || !(3 ..= 4).contains(&channels) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let chunks = &data[HEADER_SIZE .. data.len() - END_MARKER.len()];
+    let mut pixels = Vec::with_capacity(count as usize);
+    let mut index = [Rgba::default(); 64];
+    let mut color = Rgba::new(0, 0, 0, 255);
+    let mut at = 0;
+    let mut run = 0u8;
+
+    let byte = |at: usize| chunks.get(at).copied().ok_or(BitmapError::InvalidBitmapData);
+
+    for _ in 0 .. count {
+      if run > 0 {
+        run -= 1;
+      } else {
+        let op = byte(at)?;
+        at += 1;
+
+        if op == OP_RGB {
+          color = Rgba { r: byte(at)?, g: byte(at + 1)?, b: byte(at + 2)?, ..color };
+          at += 3;
+        } else if op == OP_RGBA {
+          color = Rgba::new(byte(at)?, byte(at + 1)?, byte(at + 2)?, byte(at + 3)?);
+          at += 4;
+        } else {
+          match op & MASK_2 {
+            OP_INDEX => color = index[op as usize],
+            OP_DIFF => {
+              color.r = color.r.wrapping_add((op >> 4 & 0x3).wrapping_sub(2));
+              color.g = color.g.wrapping_add((op >> 2 & 0x3).wrapping_sub(2));
+              color.b = color.b.wrapping_add((op & 0x3).wrapping_sub(2));
+            }
+            OP_LUMA => {
+              let next = byte(at)?;
+              at += 1;
+              let dg = (op & 0x3f).wrapping_sub(32);
+              color.r = color.r.wrapping_add(dg.wrapping_sub(8).wrapping_add(next >> 4 & 0xf));
+              color.g = color.g.wrapping_add(dg);
+              color.b = color.b.wrapping_add(dg.wrapping_sub(8).wrapping_add(next & 0xf));
+            }
+            _ => run = op & 0x3f
+          }
+        }
+
+        index[hash(color)] = color;
+      }
+
+      pixels.push(match channels {
+        4 => Pixel::ABGR(color.b, color.g, color.r, color.a),
+        _ => Pixel::BGR(color.b, color.g, color.r)
+      });
+    }
+
+    Bitmap::from_parts(width, height, if channels == 4 { 32 } else { 24 }, Vec::new(), pixels)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::encode::tests::{sample, DEPTHS};
+  use crate::Bitmap;
+
+  #[test]
+  fn round_trips_every_depth() {
+    for bpp in DEPTHS {
+      let bitmap = sample(*bpp, 5);
+      let decoded = Bitmap::from_qoi(&bitmap.to_qoi()).unwrap();
+      assert_eq!(decoded.header.bpp, if *bpp == 32 { 32 } else { 24 });
+      assert!(decoded.compare(&bitmap, 0).matches, "{} bpp", bpp);
+    }
+  }
+
+  #[test]
+  fn rejects_truncated_and_oversized_files() {
+    let file = sample(24, 5).to_qoi();
+    for len in [0, 13, 21, file.len() - 9] {
+      assert!(Bitmap::from_qoi(&file[.. len]).is_err(), "{} bytes", len);
+    }
+
+    let mut huge = file.clone();
+    huge[4 .. 12].copy_from_slice(&[0xff; 8]);
+    assert!(Bitmap::from_qoi(&huge).is_err());
+  }
+}
@@ -0,0 +1,360 @@
+// Reducing images to fewer colors: thresholding, palette conversion and
+// median-cut palette generation.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::convert::BAYER_4X4;
+use crate::palette::indexed_depth;
+use crate::{Bitmap, BitmapError, BitmapResult, ColorMetric, LumaWeights, Palette, Pixel, Rgba};
+
+const MONOCHROME: [Rgba; 2] = [Rgba::BLACK, Rgba::WHITE];
+
+// (dx, dy, weight) taps of the error-diffusion kernels, in sixteenths and
+// eighths respectively.
+const FLOYD_STEINBERG: [(isize, usize, i32); 4] = [(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)];
+// Atkinson passes on only 6/8 of the error, trading shadow detail for contrast.
+const ATKINSON: [(isize, usize, i32); 6] = [(1, 0, 1), (2, 0, 1), (-1, 1, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1)];
+
+// How `to_indexed` hides the banding of a small palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+  #[default]
+  None,
+  FloydSteinberg,
+  Atkinson,
+  // 4x4 ordered dithering; no error carried between pixels, so it tiles
+  // and compresses well.
+  Bayer
+}
+
+// Palette computed for an image, with each pixel's index into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quantized {
+  pub palette: Vec<Rgba>,
+  pub indices: Vec<u8>
+}
+
+impl Quantized {
+  // Smallest indexed depth that holds the palette.
+  pub fn bpp(&self) -> u16 {
+    indexed_depth(self.palette.len())
+  }
+}
+
+// A box of distinct colors (packed 0xRRGGBB) with their pixel counts.
+struct ColorBox {
+  colors: Vec<(u32, u32)>
+}
+
+impl ColorBox {
+  fn channel(color: u32, channel: usize) -> u8 {
+    (color >> (16 - channel * 8)) as u8
+  }
+
+  // Widest channel and its extent.
+  fn widest(&self) -> (usize, u8) {
+    (0 .. 3)
+      .map(|channel| {
+        let values = self.colors.iter().map(|(color, _)| ColorBox::channel(*color, channel));
+        let (low, high) = values.fold((255, 0), |(low, high), value| (value.min(low), value.max(high)));
+        (channel, high.saturating_sub(low))
+      })
+      .max_by_key(|(_, extent)| *extent)
+      .unwrap_or((0, 0))
+  }
+
+  // Splits at the pixel-weighted median of the widest channel.
+  fn split(mut self) -> (ColorBox, ColorBox) {
+    let (channel, _) = self.widest();
+    self.colors.sort_unstable_by_key(|(color, _)| ColorBox::channel(*color, channel));
+
+    let total = self.colors.iter().map(|(_, count)| *count as u64).sum::<u64>();
+    let mut seen = 0;
+    let at = self.colors.iter()
+      .position(|(_, count)| {
+        seen += *count as u64;
+        seen * 2 >= total
+      })
+      .unwrap_or(0);
+
+    let rest = self.colors.split_off((at + 1).min(self.colors.len() - 1));
+    (self, ColorBox { colors: rest })
+  }
+
+  fn average(&self) -> Rgba {
+    let total = self.colors.iter().map(|(_, count)| *count as u64).sum::<u64>().max(1);
+    let mean = |channel| {
+      let sum = self.colors.iter().map(|(color, count)| ColorBox::channel(*color, channel) as u64 * *count as u64).sum::<u64>();
+      ((sum + total / 2) / total) as u8
+    };
+    Rgba::new(mean(0), mean(1), mean(2), 255)
+  }
+}
+
+impl Bitmap {
+  fn lumas(&self) -> Vec<u8> {
+    self.pixels.iter().map(|pixel| LumaWeights::REC709.luma(self.straight_color(*pixel))).collect()
+  }
+
+  fn monochrome(&self, pixels: Vec<Pixel>) -> Bitmap {
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    Bitmap::assemble(self.width(), pix_height, 1, MONOCHROME.to_vec(), pixels)
+  }
+
+  // 1-bpp black and white image, white where the Rec. 709 luma is at least `level`.
+  pub fn threshold(&self, level: u8) -> Bitmap {
+    let pixels = self.lumas().into_iter().map(|luma| Pixel::PaletteColor((luma >= level) as u8)).collect();
+    self.monochrome(pixels)
+  }
+
+  // Like `threshold(128)`, but diffusing each pixel's error to its
+  // neighbours (Floyd-Steinberg) so mid-tones come out as patterns.
+  pub fn threshold_dithered(&self) -> Bitmap {
+    let width = self.width() as usize;
+    let mut levels = self.lumas().into_iter().map(|luma| luma as i16 * 16).collect::<Vec<_>>();
+    let mut pixels = vec![Pixel::PaletteColor(0); levels.len()];
+
+    for index in 0 .. levels.len() {
+      let (x, level) = (index % width, levels[index]);
+      let white = level >= 128 * 16;
+      let error = level - if white { 255 * 16 } else { 0 };
+      pixels[index] = Pixel::PaletteColor(white as u8);
+
+      let mut spread = |at: usize, weight: i16| {
+        if let Some(value) = levels.get_mut(at) {
+          *value += error * weight / 16;
+        }
+      };
+      if x + 1 < width {
+        spread(index + 1, 7);
+        spread(index + width + 1, 1);
+      }
+      if x > 0 {
+        spread(index + width - 1, 3);
+      }
+      spread(index + width, 5);
+    }
+
+    self.monochrome(pixels)
+  }
+
+  // Converts to a `bpp`-deep indexed image over `palette`, picking the
+  // nearest entry for each pixel after applying `dither`. Alpha is dropped.
+  pub fn to_indexed(&self, palette: &[Rgba], bpp: u16, dither: Dither) -> BitmapResult<Bitmap> {
+    if !matches!(bpp, 1 | 4 | 8) {
+      return Err(BitmapError::UnsupportedBitsPerPixel)
+    }
+
+    if palette.is_empty() || palette.len() > 1 << bpp {
+      return Err(BitmapError::BadPalette)
+    }
+
+    let (kernel, divisor): (&[(isize, usize, i32)], i32) = match dither {
+      Dither::FloydSteinberg => (&FLOYD_STEINBERG, 16),
+      Dither::Atkinson => (&ATKINSON, 8),
+      _ => (&[], 1)
+    };
+
+    // Ordered dithering nudges by up to half the typical gap between entries.
+    let spread = palette_gap(palette);
+    let lookup = Palette::new(palette, ColorMetric::Rgb);
+
+    let (width, height) = (self.width() as usize, self.height() as usize);
+    let mut errors = vec![[0i32; 3]; self.pixels.len()];
+    let mut pixels = Vec::with_capacity(self.pixels.len());
+
+    for (index, pixel) in self.pixels.iter().enumerate() {
+      let (x, y) = (index % width, index / width);
+      let color = self.straight_color(*pixel);
+      let offset = match dither {
+        Dither::Bayer => (BAYER_4X4[y % 4][x % 4] as i32 * 2 - 15) * spread / 32,
+        _ => 0
+      };
+
+      let mut value = [color.r as i32, color.g as i32, color.b as i32];
+      for (channel, value) in value.iter_mut().enumerate() {
+        *value = (*value + errors[index][channel] + offset).clamp(0, 255);
+      }
+
+      let chosen = lookup.nearest(Rgba::new(value[0] as u8, value[1] as u8, value[2] as u8, 255));
+      let entry = palette[chosen as usize];
+      let error = [value[0] - entry.r as i32, value[1] - entry.g as i32, value[2] - entry.b as i32];
+      pixels.push(Pixel::PaletteColor(chosen));
+
+      for (dx, dy, weight) in kernel {
+        let (tx, ty) = (x as isize + dx, y + dy);
+        if tx < 0 || tx as usize >= width || ty >= height {
+          continue
+        }
+
+        let target = &mut errors[ty * width + tx as usize];
+        for channel in 0 .. 3 {
+          target[channel] += error[channel] * weight / divisor;
+        }
+      }
+    }
+
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    Ok(Bitmap::assemble(self.width(), pix_height, bpp, palette.to_vec(), pixels))
+  }
+
+  // Median-cut palette of at most `max_colors` entries (clamped to
+  // 1 ..= 256), with every pixel mapped to the box its color fell in.
+  // Alpha is ignored.
+  pub fn quantize(&self, max_colors: usize) -> Quantized {
+    let pack = |color: Rgba| (color.r as u32) << 16 | (color.g as u32) << 8 | color.b as u32;
+    let mut packed = self.pixels.iter().map(|pixel| pack(self.straight_color(*pixel))).collect::<Vec<_>>();
+    packed.sort_unstable();
+
+    let mut colors: Vec<(u32, u32)> = Vec::new();
+    for color in packed {
+      match colors.last_mut() {
+        Some((last, count)) if *last == color => *count += 1,
+        _ => colors.push((color, 1))
+      }
+    }
+
+    let mut boxes = vec![ColorBox { colors }];
+    while boxes.len() < max_colors.clamp(1, 256) {
+      let candidate = boxes.iter()
+        .enumerate()
+        .filter(|(_, color_box)| color_box.colors.len() > 1)
+        .max_by_key(|(_, color_box)| color_box.widest().1)
+        .map(|(index, _)| index);
+
+      let Some(index) = candidate else {
+        break
+      };
+      let (low, high) = boxes.swap_remove(index).split();
+      boxes.push(low);
+      boxes.push(high);
+    }
+
+    // Colors and the box each ended up in, sorted for lookup.
+    let mut owners = boxes.iter()
+      .enumerate()
+      .flat_map(|(index, color_box)| color_box.colors.iter().map(move |(color, _)| (*color, index as u8)))
+      .collect::<Vec<_>>();
+    owners.sort_unstable();
+
+    let indices = self.pixels.iter()
+      .map(|pixel| {
+        let color = pack(self.straight_color(*pixel));
+        owners.binary_search_by_key(&color, |(color, _)| *color).map_or(0, |at| owners[at].1)
+      })
+      .collect();
+
+    Quantized { palette: boxes.iter().map(ColorBox::average).collect(), indices }
+  }
+
+  // Indexed copy using a `quantize(max_colors)` palette at the smallest
+  // depth that fits, remapped through `to_indexed` so `dither` can apply.
+  pub fn quantized(&self, max_colors: usize, dither: Dither) -> BitmapResult<Bitmap> {
+    let quantized = self.quantize(max_colors);
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    let bpp = quantized.bpp();
+
+    if dither == Dither::None {
+      let pixels = quantized.indices.into_iter().map(Pixel::PaletteColor).collect();
+      return Ok(Bitmap::assemble(self.width(), pix_height, bpp, quantized.palette, pixels))
+    }
+
+    self.to_indexed(&quantized.palette, bpp, dither)
+  }
+
+  // Copy stored at `bpp`: indexed depths over a quantized palette of up to
+  // `1 << bpp` colors, 24 bpp dropping alpha, or 32 bpp keeping it.
+  pub fn to_depth(&self, bpp: u16, dither: Dither) -> BitmapResult<Bitmap> {
+    let pix_height = self.header.pix_height.signum() * self.height() as i32;
+    let truecolor = |pixel: &Pixel| {
+      let color = self.straight_color(*pixel);
+      match bpp {
+        24 => Pixel::BGR(color.b, color.g, color.r),
+        _ => Pixel::ABGR(color.b, color.g, color.r, color.a)
+      }
+    };
+
+    match bpp {
+      1 | 4 | 8 => self.to_indexed(&self.quantize(1 << bpp).palette, bpp, dither),
+      24 | 32 => Ok(Bitmap::assemble(self.width(), pix_height, bpp, Vec::new(), self.pixels.iter().map(truecolor).collect())),
+      _ => Err(BitmapError::UnsupportedBitsPerPixel)
+    }
+  }
+}
+
+// Mean distance from each palette entry to its closest neighbour, in the
+// largest per-channel difference.
+fn palette_gap(palette: &[Rgba]) -> i32 {
+  if palette.len() < 2 {
+    return 255
+  }
+
+  let gap = |a: &Rgba, b: &Rgba| (a.r.abs_diff(b.r)).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b)) as i32;
+  let total = palette.iter()
+    .enumerate()
+    .map(|(index, entry)| {
+      palette.iter().enumerate()
+        .filter(|(other, _)| *other != index)
+        .map(|(_, other)| gap(entry, other))
+        .min()
+        .unwrap_or(255)
+    })
+    .sum::<i32>();
+  total / palette.len() as i32
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+  use alloc::vec::Vec;
+
+  use crate::{Bitmap, Dither, Pixel, Rgba};
+
+  fn stripes() -> Bitmap {
+    let colors = [Pixel::BGR(0, 0, 250), Pixel::BGR(0, 0, 240), Pixel::BGR(250, 0, 0), Pixel::BGR(240, 0, 0)];
+    Bitmap::from_parts(4, 2, 24, Vec::new(), colors.iter().chain(&colors).copied().collect()).unwrap()
+  }
+
+  #[test]
+  fn quantizes_to_box_averages() {
+    let quantized = stripes().quantize(2);
+    let mut palette = quantized.palette.clone();
+    palette.sort_unstable_by_key(|color| color.r);
+
+    assert_eq!(palette, [Rgba::new(0, 0, 245, 255), Rgba::new(245, 0, 0, 255)]);
+    assert_eq!(quantized.bpp(), 1);
+    for (index, pair) in quantized.indices.chunks(2).enumerate() {
+      assert_eq!(pair[0], pair[1], "pair {}", index);
+    }
+    assert_ne!(quantized.indices[0], quantized.indices[2]);
+  }
+
+  #[test]
+  fn quantized_images_use_the_smallest_depth() {
+    for dither in [Dither::None, Dither::FloydSteinberg, Dither::Atkinson, Dither::Bayer] {
+      let bitmap = stripes().quantized(3, dither).unwrap();
+      assert_eq!((bitmap.header.bpp, bitmap.palette.len()), (4, 3), "{:?}", dither);
+      assert_eq!(bitmap.pixels.len(), 8);
+    }
+
+    let bitmap = stripes().quantized(256, Dither::FloydSteinberg).unwrap();
+    assert_eq!((bitmap.header.bpp, bitmap.palette.len()), (4, 4));
+    assert_eq!(bitmap.straight_color(bitmap.pixels[1]), Rgba::new(240, 0, 0, 255));
+  }
+
+  #[test]
+  fn dithering_spreads_mid_tones() {
+    let gray = Bitmap::from_parts(8, 8, 24, Vec::new(), vec![Pixel::BGR(128, 128, 128); 64]).unwrap();
+    let whites = |dither| {
+      let indexed = gray.to_indexed(&[Rgba::BLACK, Rgba::WHITE], 1, dither).unwrap();
+      indexed.pixels.iter().filter(|pixel| **pixel == Pixel::PaletteColor(1)).count()
+    };
+
+    assert_eq!(whites(Dither::None), 64);
+    for dither in [Dither::FloydSteinberg, Dither::Atkinson, Dither::Bayer] {
+      assert!((24 ..= 40).contains(&whites(dither)), "{:?} gave {} white pixels", dither, whites(dither));
+    }
+    assert!(gray.to_indexed(&[Rgba::BLACK; 3], 1, Dither::None).is_err());
+  }
+}
@@ -0,0 +1,196 @@
+// Salvaging files with damaged but recomputable header fields.
+
+use alloc::vec::Vec;
+
+use crate::decoder::{read_i32, read_u16, read_u32, row_stride};
+use crate::masks::masks_len;
+use crate::{Bitmap, BitmapError, BitmapResult, FILE_HEADER_SIZE};
+
+// One change made by `repair`, with the value found and the one used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+  // `bfSize` didn't match the length of the file.
+  FileSize { declared: u32, actual: u32 },
+  // `biSizeImage` was zero or disagreed with the pixel array size.
+  ImageSize { declared: u32, computed: u32 },
+  // `bfOffBits` didn't leave room for the pixel array after the palette.
+  Offset { declared: u32, corrected: u32 },
+  // The row order sign was on the width instead of the height.
+  NegativeWidth,
+  // The pixel array was cut short and padded with zero bytes.
+  Truncated { missing: usize }
+}
+
+// Everything `repair` changed, in the order it was applied.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RepairLog {
+  pub repairs: Vec<Repair>
+}
+
+impl RepairLog {
+  pub fn is_empty(&self) -> bool {
+    self.repairs.is_empty()
+  }
+}
+
+impl Bitmap {
+  // Parses `bytes` after fixing header fields that can be derived from the
+  // rest of the file. The returned bitmap's `data` holds the repaired file.
+  pub fn repair(bytes: &[u8]) -> BitmapResult<(Bitmap, RepairLog)> {
+    let mut data = bytes.to_vec();
+    let mut log = RepairLog::default();
+
+    if data.get(0 .. 2) != Some(&b"BM"[..]) {
+      return Err(BitmapError::BadMagic)
+    }
+
+    if data.len() < FILE_HEADER_SIZE + 40 {
+      return Err(BitmapError::TruncatedHeader)
+    }
+
+    let header_size = read_u32(&data, 14)?;
+    if header_size < 40 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let width = read_i32(&data, 18)?;
+    let height = read_i32(&data, 22)?;
+    if width < 0 {
+      // Keep the top-down intent the stray sign most likely meant.
+      // `i32::MIN` has no positive counterpart to store for either field.
+      let width = i32::try_from(width.unsigned_abs()).map_err(|_| BitmapError::DimensionOverflow)?;
+      let height = i32::try_from(height.unsigned_abs()).map_err(|_| BitmapError::DimensionOverflow)?;
+      data[18 .. 22].copy_from_slice(&width.to_le_bytes());
+      data[22 .. 26].copy_from_slice(&(-height).to_le_bytes());
+      log.repairs.push(Repair::NegativeWidth);
+    }
+
+    let (width, height) = (width.unsigned_abs(), height.unsigned_abs());
+    let bpp = read_u16(&data, 28)?;
+    let method = read_u32(&data, 30)?;
+    let colors = read_u32(&data, 46)?;
+
+    // RLE, JPEG and PNG streams have no size to derive from the dimensions,
+    // so their image size, offset and length are left alone.
+    if !matches!(method, 1 | 2 | 4 | 5) {
+      let image_size = row_stride(width, bpp)
+        .and_then(|stride| stride.checked_mul(height as usize))
+        .filter(|size| *size <= u32::MAX as usize)
+        .ok_or(BitmapError::DimensionOverflow)?;
+
+      let declared = read_u32(&data, 34)?;
+      if declared as usize != image_size {
+        data[34 .. 38].copy_from_slice(&(image_size as u32).to_le_bytes());
+        log.repairs.push(Repair::ImageSize { declared, computed: image_size as u32 });
+      }
+
+      let masks = masks_len(header_size, method);
+      let palette_len = match (colors, bpp) {
+        (0, bpp) if bpp <= 8 => 1 << bpp,
+        (n, _) => n.min(256) as usize
+      };
+      let palette_end = FILE_HEADER_SIZE + header_size as usize + masks + palette_len * 4;
+
+      // An offset into the palette, or one leaving too little room, is replaced
+      // so the pixel array ends with the file, as writers almost always lay it
+      // out; if even that overlaps the palette, it starts right after it.
+      let declared = read_u32(&data, 10)?;
+      let fits = |offset: usize| offset >= palette_end && offset.saturating_add(image_size) <= data.len();
+      if !fits(declared as usize) {
+        let corrected = data.len().saturating_sub(image_size).max(palette_end) as u32;
+
+        // A truncated file can land back on the declared offset; that's left
+        // to the padding below rather than logged as a change.
+        if corrected != declared {
+          data[10 .. 14].copy_from_slice(&corrected.to_le_bytes());
+          log.repairs.push(Repair::Offset { declared, corrected });
+        }
+      }
+
+      let image_end = read_u32(&data, 10)? as usize + image_size;
+      if image_end > data.len() {
+        log.repairs.push(Repair::Truncated { missing: image_end - data.len() });
+        data.resize(image_end, 0);
+      }
+    }
+
+    let (declared, actual) = (read_u32(&data, 2)?, data.len() as u32);
+    if declared != actual {
+      data[2 .. 6].copy_from_slice(&actual.to_le_bytes());
+      log.repairs.push(Repair::FileSize { declared, actual });
+    }
+
+    Ok((Bitmap::from_vec(data)?, log))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  #[cfg(feature = "rle")]
+  use crate::Rgba;
+  use crate::{Bitmap, BitmapError, Pixel, Repair};
+
+  fn file() -> alloc::vec::Vec<u8> {
+    Bitmap::from_parts(2, 2, 24, vec![], vec![Pixel::BGR(1, 2, 3); 4]).unwrap().encode()
+  }
+
+  #[test]
+  fn moves_a_stray_width_sign_to_the_height() {
+    let mut data = file();
+    data[18 .. 22].copy_from_slice(&(-2i32).to_le_bytes());
+    data[2 .. 6].copy_from_slice(&0u32.to_le_bytes());
+
+    let (bitmap, log) = Bitmap::repair(&data).unwrap();
+    assert_eq!(log.repairs, [Repair::NegativeWidth, Repair::FileSize { declared: 0, actual: data.len() as u32 }]);
+    assert_eq!((bitmap.width(), bitmap.height()), (2, 2));
+    assert_eq!(bitmap.pixels, vec![Pixel::BGR(1, 2, 3); 4]);
+  }
+
+  #[test]
+  fn rejects_dimensions_without_a_positive_counterpart() {
+    for (width, height) in [(-2, i32::MIN), (i32::MIN, 2)] {
+      let mut data = file();
+      data[18 .. 22].copy_from_slice(&width.to_le_bytes());
+      data[22 .. 26].copy_from_slice(&height.to_le_bytes());
+      assert!(matches!(Bitmap::repair(&data), Err(BitmapError::DimensionOverflow)));
+    }
+  }
+
+  #[test]
+  fn recomputes_sizes_and_offsets() {
+    let original = file();
+    let mut data = original.clone();
+    data[34 .. 38].copy_from_slice(&0u32.to_le_bytes());
+    data[10 .. 14].copy_from_slice(&20u32.to_le_bytes());
+
+    let (bitmap, log) = Bitmap::repair(&data).unwrap();
+    assert_eq!(log.repairs, [Repair::ImageSize { declared: 0, computed: 16 }, Repair::Offset { declared: 20, corrected: 54 }]);
+    assert_eq!(bitmap.data, original);
+    assert!(Bitmap::repair(&original).unwrap().1.is_empty());
+  }
+
+  #[test]
+  fn pads_a_truncated_pixel_array() {
+    let data = file();
+    let (bitmap, log) = Bitmap::repair(&data[.. data.len() - 3]).unwrap();
+    assert_eq!(log.repairs, [Repair::Truncated { missing: 3 }]);
+    // The last row in the file is the top one, ending in two padding bytes.
+    assert_eq!(bitmap.pixels[.. 2], [Pixel::BGR(1, 2, 3), Pixel::BGR(1, 2, 0)]);
+  }
+
+  #[cfg(feature = "rle")]
+  #[test]
+  fn leaves_rle_streams_alone() {
+    let palette = vec![Rgba::BLACK, Rgba::WHITE];
+    let pixels = (0 .. 12).map(|at| Pixel::PaletteColor(at % 3 / 2)).collect();
+    let original = Bitmap::from_parts(4, 3, 8, palette, pixels).unwrap();
+    let mut data = original.encode_rle().unwrap();
+    data[2 .. 6].copy_from_slice(&0u32.to_le_bytes());
+
+    let (bitmap, log) = Bitmap::repair(&data).unwrap();
+    assert_eq!(log.repairs, [Repair::FileSize { declared: 0, actual: data.len() as u32 }]);
+    assert_eq!(bitmap.pixels, original.pixels);
+  }
+}
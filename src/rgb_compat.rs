@@ -0,0 +1,64 @@
+// Conversions between this crate's colors and the `rgb` crate's pixel types.
+
+use alloc::vec::Vec;
+
+use rgb::{RGB8, RGBA8};
+
+use crate::{Bitmap, BitmapResult, Pixel, Rgba};
+
+impl From<Rgba> for RGBA8 {
+  fn from(color: Rgba) -> RGBA8 {
+    RGBA8::new(color.r, color.g, color.b, color.a)
+  }
+}
+
+impl From<RGBA8> for Rgba {
+  fn from(color: RGBA8) -> Rgba {
+    Rgba::new(color.r, color.g, color.b, color.a)
+  }
+}
+
+// Alpha is dropped.
+impl From<Rgba> for RGB8 {
+  fn from(color: Rgba) -> RGB8 {
+    RGB8::new(color.r, color.g, color.b)
+  }
+}
+
+impl From<RGB8> for Rgba {
+  fn from(color: RGB8) -> Rgba {
+    Rgba::new(color.r, color.g, color.b, 255)
+  }
+}
+
+impl From<RGBA8> for Pixel {
+  fn from(color: RGBA8) -> Pixel {
+    Pixel::ABGR(color.b, color.g, color.r, color.a)
+  }
+}
+
+impl From<RGB8> for Pixel {
+  fn from(color: RGB8) -> Pixel {
+    Pixel::BGR(color.b, color.g, color.r)
+  }
+}
+
+impl Bitmap {
+  pub fn to_rgba8_pixels(&self) -> Vec<RGBA8> {
+    self.pixels.iter().map(|pixel| self.straight_color(*pixel).into()).collect()
+  }
+
+  pub fn to_rgb8_pixels(&self) -> Vec<RGB8> {
+    self.pixels.iter().map(|pixel| self.straight_color(*pixel).into()).collect()
+  }
+
+  // Builds a 32-bpp bitmap from row-major pixels.
+  pub fn from_rgba8_pixels(width: u32, height: u32, pixels: &[RGBA8]) -> BitmapResult<Bitmap> {
+    Bitmap::from_parts(width, height, 32, Vec::new(), pixels.iter().map(|pixel| (*pixel).into()).collect())
+  }
+
+  // Builds a 24-bpp bitmap from row-major pixels.
+  pub fn from_rgb8_pixels(width: u32, height: u32, pixels: &[RGB8]) -> BitmapResult<Bitmap> {
+    Bitmap::from_parts(width, height, 24, Vec::new(), pixels.iter().map(|pixel| (*pixel).into()).collect())
+  }
+}
@@ -0,0 +1,302 @@
+// Run-length encoding and decoding of 4- and 8-bpp images (BI_RLE4 and
+// BI_RLE8).
+
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::decoder::FILE_HEADER_SIZE;
+use crate::{Bitmap, BitmapError, BitmapResult, CompressionMethod, Decoder};
+
+// Escape codes following a zero count byte.
+const END_OF_LINE: u8 = 0;
+const END_OF_BITMAP: u8 = 1;
+const DELTA: u8 = 2;
+
+// Longest run or literal one count byte can describe.
+const MAX_COUNT: usize = 255;
+
+// Appends one row of palette indices. Runs repeat one index for RLE8 and
+// alternate two for RLE4; stretches without runs go out as literals, which
+// need at least three pixels and are padded to a 16-bit boundary.
+fn encode_row(row: &[u8], nibbles: bool, out: &mut Vec<u8>) {
+  let period = if nibbles { 2 } else { 1 };
+  let threshold = if nibbles { 3 } else { 2 };
+  let run_at = |start: usize| {
+    let pattern = [row[start], *row.get(start + 1).unwrap_or(&row[start])];
+    row[start ..].iter()
+      .take(MAX_COUNT)
+      .enumerate()
+      .take_while(|(offset, index)| **index == pattern[offset % period])
+      .count()
+  };
+  let run = |start: usize, len: usize, out: &mut Vec<u8>| {
+    let value = if nibbles { row[start] << 4 | row.get(start + 1).map_or(row[start], |index| *index) } else { row[start] };
+    out.extend_from_slice(&[len as u8, value]);
+  };
+
+  let mut x = 0;
+  while x < row.len() {
+    let len = run_at(x);
+    if len >= threshold {
+      run(x, len, out);
+      x += len;
+      continue
+    }
+
+    let start = x;
+    while x < row.len() && x - start < MAX_COUNT && run_at(x) < threshold {
+      x += 1;
+    }
+
+    let literal = &row[start .. x];
+    if literal.len() < 3 {
+      // Too short for absolute mode; for RLE4 two pixels are always a run.
+      if nibbles {
+        run(start, literal.len(), out);
+      } else {
+        for offset in 0 .. literal.len() {
+          run(start + offset, 1, out);
+        }
+      }
+      continue
+    }
+
+    out.extend_from_slice(&[0, literal.len() as u8]);
+    let begin = out.len();
+    if nibbles {
+      out.extend(literal.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).map_or(0, |index| *index)));
+    } else {
+      out.extend_from_slice(literal);
+    }
+    if (out.len() - begin) % 2 == 1 {
+      out.push(0);
+    }
+  }
+}
+
+impl Bitmap {
+  // Encodes a 4- or 8-bpp image as BI_RLE4 or BI_RLE8. Compressed files are
+  // always stored bottom-up, so top-down images have their rows reordered.
+  pub fn encode_rle(&self) -> BitmapResult<Vec<u8>> {
+    let bpp = self.header.bpp;
+    let (method, nibbles) = match bpp {
+      8 => (1u32, false),
+      4 => (2, true),
+      _ => return Err(BitmapError::UnsupportedBitsPerPixel)
+    };
+
+    trace_span!("encode_rle", width = self.width(), height = self.height(), bpp);
+    let mut out = self.encode_headers();
+    let offset = out.len();
+    let (width, height) = (self.width(), self.height());
+    let mut row = Vec::with_capacity(width as usize);
+
+    for stored in 0 .. height {
+      let y = height - 1 - stored;
+      row.clear();
+      row.extend((0 .. width).map(|x| self.pixel(x, y).map_or(0, |pixel| self.palette_index(*pixel) & ((1u16 << bpp) - 1) as u8)));
+
+      encode_row(&row, nibbles, &mut out);
+      let end = if stored + 1 == height { END_OF_BITMAP } else { END_OF_LINE };
+      out.extend_from_slice(&[0, end]);
+    }
+
+    let (file_size, image_size) = (out.len() as u32, (out.len() - offset) as u32);
+    out[2 .. 6].copy_from_slice(&file_size.to_le_bytes());
+    out[FILE_HEADER_SIZE + 8 .. FILE_HEADER_SIZE + 12].copy_from_slice(&(height as i32).to_le_bytes());
+    out[FILE_HEADER_SIZE + 16 .. FILE_HEADER_SIZE + 20].copy_from_slice(&method.to_le_bytes());
+    out[FILE_HEADER_SIZE + 20 .. FILE_HEADER_SIZE + 24].copy_from_slice(&image_size.to_le_bytes());
+    trace_event!(len = out.len(), image_size, "encoded");
+    Ok(out)
+  }
+}
+
+impl Decoder<'_> {
+  // Expands an RLE4 or RLE8 file into an uncompressed one with the same
+  // headers and palette, which `Decoder::new` accepts. Pixels the stream
+  // skips over with end-of-line or delta codes are left at index 0 and
+  // runs past the right edge are cut off, but writing past the last row,
+  // ending without an end-of-bitmap code, or a stream too short to cover
+  // the image with runs, is a `BadRleStream`.
+  // Uncompressed files come back unchanged.
+  pub fn decompress(&self) -> BitmapResult<Vec<u8>> {
+    self.decompress_until(|| ControlFlow::Continue(()))
+  }
+
+  // `decompress`, calling `check` before each row and failing with
+  // `BitmapError::Cancelled` as soon as it breaks.
+  pub(crate) fn decompress_until(&self, mut check: impl FnMut() -> ControlFlow<()>) -> BitmapResult<Vec<u8>> {
+    if !self.is_compressed() {
+      return Ok(self.data.to_vec())
+    }
+
+    trace_span!("decompress", width = self.width(), height = self.height(), method = ?self.header.method);
+    let nibbles = self.header.method == CompressionMethod::Rle4Bit;
+    let (width, height, stride) = (self.width() as usize, self.height() as usize, self.stride());
+    let offset = self.file_header.pixel_offset as usize;
+    let stream = self.data.get(offset .. self.image_end().min(self.data.len())).ok_or(BitmapError::BadRleStream)?;
+
+    // Each two-byte code fills at most 255 pixels, which bounds the image a
+    // stream can cover and so what is allocated for it. Streams skipping
+    // most of the image with end-of-line or delta codes could cover more,
+    // but they're rejected rather than letting a few bytes claim gigabytes.
+    if width.saturating_mul(height) > stream.len() / 2 * MAX_COUNT {
+      return Err(BitmapError::BadRleStream)
+    }
+
+    let mut out = self.data[.. offset].to_vec();
+    out.resize(offset + stride * height, 0);
+    let (mut at, mut x, mut row) = (0, 0, 0);
+    let mut next = || {
+      let byte = stream.get(at).copied().ok_or(BitmapError::BadRleStream);
+      at += 1;
+      byte
+    };
+    let put = |out: &mut [u8], x: usize, row: usize, index: u8| {
+      if row >= height {
+        return Err(BitmapError::BadRleStream)
+      }
+      if x < width {
+        let pixels = &mut out[offset + row * stride ..];
+        if nibbles {
+          let shift = 4 - x % 2 * 4;
+          pixels[x / 2] = pixels[x / 2] & !(0xf << shift) | index << shift;
+        } else {
+          pixels[x] = index;
+        }
+      }
+      Ok(())
+    };
+
+    if check().is_break() {
+      return Err(BitmapError::Cancelled)
+    }
+    loop {
+      let (count, value) = (next()?, next()?);
+      if count > 0 {
+        // A run: one index, or two alternating ones for RLE4.
+        for step in 0 .. count as usize {
+          let index = if !nibbles { value } else if step % 2 == 0 { value >> 4 } else { value & 0xf };
+          put(&mut out, x + step, row, index)?;
+        }
+        x += count as usize;
+        continue
+      }
+
+      match value {
+        END_OF_LINE => {
+          (x, row) = (0, row + 1);
+          if check().is_break() {
+            return Err(BitmapError::Cancelled)
+          }
+        }
+        END_OF_BITMAP => break,
+        DELTA => {
+          x += next()? as usize;
+          row += next()? as usize;
+        }
+        len => {
+          // A literal, padded to a 16-bit boundary.
+          let bytes = if nibbles { (len as usize).div_ceil(2) } else { len as usize };
+          for step in 0 .. bytes {
+            let byte = next()?;
+            if nibbles {
+              put(&mut out, x + step * 2, row, byte >> 4)?;
+              if step * 2 + 1 < len as usize {
+                put(&mut out, x + step * 2 + 1, row, byte & 0xf)?;
+              }
+            } else {
+              put(&mut out, x + step, row, byte)?;
+            }
+          }
+          if bytes % 2 == 1 {
+            next()?;
+          }
+          x += len as usize;
+        }
+      }
+    }
+
+    // Now an uncompressed file: BI_RGB, with sizes to match.
+    let (file_size, image_size) = (out.len() as u32, (stride * height) as u32);
+    out[2 .. 6].copy_from_slice(&file_size.to_le_bytes());
+    out[FILE_HEADER_SIZE + 16 .. FILE_HEADER_SIZE + 20].copy_from_slice(&0u32.to_le_bytes());
+    if self.header.size >= 24 {
+      out[FILE_HEADER_SIZE + 20 .. FILE_HEADER_SIZE + 24].copy_from_slice(&image_size.to_le_bytes());
+    }
+    trace_event!(stream = at, len = out.len(), "decompressed");
+    Ok(out)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec::Vec;
+
+  use crate::{validate, Bitmap, BitmapError, Issue, Pixel, Rgba};
+
+  fn indexed(width: u32, height: u32, bpp: u16, index: impl Fn(u32) -> u8) -> Bitmap {
+    let palette = (0 .. 16).map(|n| Rgba::new(n * 16, 255 - n * 16, n, 255)).collect();
+    let pixels = (0 .. width * height).map(|at| Pixel::PaletteColor(index(at))).collect();
+    Bitmap::from_parts(width, height, bpp, palette, pixels).unwrap()
+  }
+
+  fn indices(bitmap: &Bitmap) -> Vec<u8> {
+    bitmap.pixels.iter().map(|pixel| bitmap.palette_index(*pixel)).collect()
+  }
+
+  // The encoded stream with `biSizeImage` cleared, so it can be edited.
+  fn stream_of(bitmap: &Bitmap) -> (Vec<u8>, usize) {
+    let mut file = bitmap.encode_rle().unwrap();
+    file[34 .. 38].fill(0);
+    let offset = u32::from_le_bytes([file[10], file[11], file[12], file[13]]) as usize;
+    (file, offset)
+  }
+
+  #[test]
+  fn round_trips_runs_and_literals() {
+    for (width, height, bpp) in [(1, 1, 8), (7, 3, 4), (33, 5, 8), (301, 4, 4), (600, 2, 8)] {
+      if bpp == 4 && !cfg!(feature = "low-depth") {
+        continue
+      }
+      let patterns: [&dyn Fn(u32) -> u8; 3] = [&|_| 3, &|at| (at * 7 % 11) as u8, &|at| if at % 9 < 5 { 2 } else { (at % 16) as u8 }];
+      for pattern in patterns {
+        let bitmap = indexed(width, height, bpp, pattern);
+        let decoded = Bitmap::from_bytes(&bitmap.encode_rle().unwrap()).unwrap();
+        assert_eq!(indices(&decoded), indices(&bitmap), "{}x{} at {} bpp", width, height, bpp);
+        assert_eq!(decoded.palette, bitmap.palette);
+      }
+    }
+  }
+
+  #[test]
+  fn skipped_pixels_are_index_zero() {
+    let (mut file, offset) = stream_of(&indexed(4, 2, 8, |_| 1));
+    // Two pixels, a delta of (1, 1), one more pixel, end of bitmap.
+    file.truncate(offset);
+    file.extend_from_slice(&[2, 1, 0, 2, 1, 1, 1, 1, 0, 1]);
+
+    let decoded = Bitmap::from_bytes(&file).unwrap();
+    assert_eq!(indices(&decoded), [0, 0, 0, 1, 1, 1, 0, 0]);
+  }
+
+  #[test]
+  fn rejects_truncated_and_overrunning_streams() {
+    let (file, offset) = stream_of(&indexed(4, 2, 8, |_| 1));
+    let truncated = &file[.. file.len() - 2];
+    assert!(matches!(Bitmap::from_bytes(truncated), Err(BitmapError::BadRleStream)));
+    assert!(validate(truncated).issues.contains(&Issue::BadRleStream));
+
+    let mut overrun = file[.. offset].to_vec();
+    overrun.extend_from_slice(&[0, 2, 0, 5, 1, 1, 0, 1]);
+    assert!(matches!(Bitmap::from_bytes(&overrun), Err(BitmapError::BadRleStream)));
+  }
+
+  #[test]
+  fn rejects_streams_too_short_for_their_dimensions() {
+    let (mut file, _) = stream_of(&indexed(4, 2, 8, |_| 1));
+    file[18 .. 22].copy_from_slice(&i32::MAX.to_le_bytes());
+    file[22 .. 26].copy_from_slice(&1i32.to_le_bytes());
+    assert!(matches!(Bitmap::from_bytes(&file), Err(BitmapError::BadRleStream)));
+  }
+}
@@ -0,0 +1,72 @@
+// Conversions to and from SDL2 surfaces. Errors are SDL-style strings so they
+// compose with the rest of an `sdl2` program.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use sdl2::pixels::{Color, Palette, PixelFormatEnum};
+use sdl2::surface::{Surface, SurfaceRef};
+
+use crate::{Bitmap, Pixel};
+
+impl Bitmap {
+  // Indexed images become INDEX8 surfaces with the palette attached, 24-bpp
+  // ones RGB24 and 32-bpp ones RGBA32, with rows copied at the surface pitch.
+  pub fn to_sdl_surface(&self) -> Result<Surface<'static>, String> {
+    let bpp = self.header.bpp;
+    let format = match bpp {
+      1 | 4 | 8 => PixelFormatEnum::Index8,
+      24 => PixelFormatEnum::RGB24,
+      _ => PixelFormatEnum::RGBA32
+    };
+    let bytes_per_pixel = format.byte_size_per_pixel();
+
+    let mut surface = Surface::new(self.width(), self.height(), format)?;
+    if bpp <= 8 && !self.palette.is_empty() {
+      let colors = self.palette.iter().map(|color| Color::RGB(color.r, color.g, color.b)).collect::<Vec<_>>();
+      surface.set_palette(&Palette::with_colors(&colors)?)?;
+    }
+
+    let (width, pitch) = (self.width() as usize, surface.pitch() as usize);
+    surface.with_lock_mut(|bytes| {
+      for (row, out) in self.pixels.chunks(width).zip(bytes.chunks_mut(pitch)) {
+        for (pixel, out) in row.iter().zip(out.chunks_exact_mut(bytes_per_pixel)) {
+          if bpp <= 8 {
+            out[0] = self.palette_index(*pixel);
+          } else {
+            let color = self.straight_color(*pixel);
+            out.copy_from_slice(&[color.r, color.g, color.b, color.a][.. bytes_per_pixel]);
+          }
+        }
+      }
+    });
+
+    Ok(surface)
+  }
+
+  // Converts any surface through RGBA32; formats with alpha give a 32-bpp
+  // bitmap, the rest 24-bpp.
+  pub fn from_sdl_surface(surface: &SurfaceRef) -> Result<Bitmap, String> {
+    let has_alpha = surface.pixel_format_enum().supports_alpha();
+    let converted = surface.convert_format(PixelFormatEnum::RGBA32)?;
+    let (width, pitch) = (converted.width() as usize, converted.pitch() as usize);
+
+    let pixels = converted.with_lock(|bytes| {
+      bytes.chunks(pitch)
+        .take(converted.height() as usize)
+        .flat_map(|row| row[.. width * 4].chunks_exact(4))
+        .map(|color| {
+          if has_alpha {
+            Pixel::ABGR(color[2], color[1], color[0], color[3])
+          } else {
+            Pixel::BGR(color[2], color[1], color[0])
+          }
+        })
+        .collect()
+    });
+
+    let bpp = if has_alpha { 32 } else { 24 };
+    Bitmap::from_parts(converted.width(), converted.height(), bpp, Vec::new(), pixels)
+      .map_err(|err| err.to_string())
+  }
+}
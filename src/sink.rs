@@ -0,0 +1,102 @@
+// Destinations the decoder can write pixels into directly, so callers with
+// their own layouts (planar, 10-bit packed, indices plus a mask) don't need
+// an intermediate RGBA copy.
+
+use crate::convert::rgb565;
+use crate::{BitmapError, BitmapResult, ChannelOrder, Decoder, Pixel, Rgb565Options, Rgba};
+
+pub trait PixelSink {
+  // Called once with the image size before any pixel arrives; an error
+  // here, such as a buffer that's too small, stops decoding.
+  fn begin(&mut self, _width: u32, _height: u32) -> BitmapResult<()> {
+    Ok(())
+  }
+
+  // Pixel (x, y), counted from the top-left corner, as stored and resolved
+  // through the palette. Pixels arrive row by row, left to right.
+  fn put(&mut self, x: u32, y: u32, pixel: Pixel, color: Rgba);
+}
+
+// Four bytes per pixel in `order`, row `y` starting at `y * stride`.
+#[derive(Debug)]
+pub struct PackedSink<'a> {
+  pub buffer: &'a mut [u8],
+  pub stride: usize,
+  pub order: ChannelOrder
+}
+
+impl PixelSink for PackedSink<'_> {
+  fn begin(&mut self, width: u32, height: u32) -> BitmapResult<()> {
+    check_len(self.buffer.len(), self.stride, width, 4, height)
+  }
+
+  fn put(&mut self, x: u32, y: u32, _pixel: Pixel, color: Rgba) {
+    let at = y as usize * self.stride + x as usize * 4;
+    self.buffer[at .. at + 4].copy_from_slice(&self.order.arrange(color));
+  }
+}
+
+// RGB565 words, row `y` starting at word `y * stride`; alpha is discarded.
+#[derive(Debug)]
+pub struct Rgb565Sink<'a> {
+  pub buffer: &'a mut [u16],
+  pub stride: usize,
+  pub options: Rgb565Options
+}
+
+impl PixelSink for Rgb565Sink<'_> {
+  fn begin(&mut self, width: u32, height: u32) -> BitmapResult<()> {
+    check_len(self.buffer.len(), self.stride, width, 1, height)
+  }
+
+  fn put(&mut self, x: u32, y: u32, _pixel: Pixel, color: Rgba) {
+    let word = rgb565(color, x, y, self.options.dither);
+    self.buffer[y as usize * self.stride + x as usize] = if self.options.swap_bytes { word.swap_bytes() } else { word };
+  }
+}
+
+// Whether `height` rows of `width` pixels of `units` each, `stride` apart,
+// fit in `len`. Sizes past `usize` fit in no buffer either.
+fn check_len(len: usize, stride: usize, width: u32, units: usize, height: u32) -> BitmapResult<()> {
+  let row_len = (width as usize).checked_mul(units).ok_or(BitmapError::BufferTooSmall)?;
+  let needed = (height as usize).saturating_sub(1)
+    .checked_mul(stride)
+    .and_then(|rows| rows.checked_add(row_len))
+    .ok_or(BitmapError::BufferTooSmall)?;
+  if stride < row_len || len < needed {
+    return Err(BitmapError::BufferTooSmall)
+  }
+  Ok(())
+}
+
+impl Decoder<'_> {
+  // Decodes every pixel straight into `sink`, without building a `Bitmap`.
+  // As with `Decoder::pixels`, alpha comes out as stored.
+  pub fn decode_to_sink<S: PixelSink + ?Sized>(&self, sink: &mut S) -> BitmapResult<()> {
+    let (width, height) = (self.width(), self.height());
+    sink.begin(width, height)?;
+
+    for y in 0 .. height {
+      let row = self.row_bytes(y).ok_or(BitmapError::InvalidBitmapData)?;
+      for x in 0 .. width {
+        let pixel = self.decode(row, x as usize);
+        sink.put(x, y, pixel, self.color(pixel));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{BitmapError, ChannelOrder, PackedSink, PixelSink};
+
+  #[test]
+  fn rejects_strides_past_usize() {
+    let mut buffer = [0; 16];
+    let mut sink = PackedSink { buffer: &mut buffer, stride: usize::MAX / 2, order: ChannelOrder::Rgba };
+    assert!(matches!(sink.begin(1, 3), Err(BitmapError::BufferTooSmall)));
+    assert!(sink.begin(1, 1).is_ok());
+  }
+}
@@ -0,0 +1,128 @@
+// Sprite sheets: slicing them into tiles and packing images into them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rect, Rgba};
+
+impl Bitmap {
+  // Tiles of a sheet in row-major order. The first tile starts `margin`
+  // pixels in from the top-left corner and tiles are `spacing` pixels
+  // apart; only tiles that fit entirely within the image are produced.
+  pub fn tiles(&self, tile_width: u32, tile_height: u32, margin: u32, spacing: u32) -> impl Iterator<Item = Bitmap> + '_ {
+    let count = |len: u32, tile: u32| {
+      match len.checked_sub(margin) {
+        Some(room) if tile > 0 && room >= tile => (room - tile) as u64 / (tile as u64 + spacing as u64) + 1,
+        _ => 0
+      }
+    };
+    let (columns, rows) = (count(self.width(), tile_width), count(self.height(), tile_height));
+
+    (0 .. rows)
+      .flat_map(move |row| (0 .. columns).map(move |column| (column, row)))
+      .filter_map(move |(column, row)| {
+        let x = margin as u64 + column * (tile_width as u64 + spacing as u64);
+        let y = margin as u64 + row * (tile_height as u64 + spacing as u64);
+        self.crop(x as u32, y as u32, tile_width, tile_height).ok()
+      })
+  }
+
+  // Packs `images` into a 32-bpp sheet, `columns` to a row in equal cells
+  // sized to the largest image, with `padding` pixels around and between
+  // cells filled with `background`. Each image sits in the top-left of its
+  // cell; the returned rectangles give where, in input order.
+  pub fn montage(images: &[Bitmap], columns: u32, padding: u32, background: Rgba) -> BitmapResult<(Bitmap, Vec<Rect>)> {
+    if images.is_empty() || columns == 0 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let cell_width = images.iter().map(Bitmap::width).max().unwrap_or(0) as u64;
+    let cell_height = images.iter().map(Bitmap::height).max().unwrap_or(0) as u64;
+    let columns = (columns as usize).min(images.len()) as u64;
+    let rows = images.len().div_ceil(columns as usize) as u64;
+
+    let width = columns * (cell_width + padding as u64) + padding as u64;
+    let height = rows * (cell_height + padding as u64) + padding as u64;
+    if width > i32::MAX as u64 || height > i32::MAX as u64 || width * height > usize::MAX as u64 / 2 {
+      return Err(BitmapError::DimensionOverflow)
+    }
+
+    let fill = Pixel::ABGR(background.b, background.g, background.r, background.a);
+    let pixels = vec![fill; (width * height) as usize];
+    let mut sheet = Bitmap::assemble(width as u32, height as i32, 32, Vec::new(), pixels);
+
+    let placements = images.iter()
+      .enumerate()
+      .map(|(index, image)| {
+        let (column, row) = (index as u64 % columns, index as u64 / columns);
+        let x = padding as u64 + column * (cell_width + padding as u64);
+        let y = padding as u64 + row * (cell_height + padding as u64);
+        let rect = Rect::new(x as u32, y as u32, image.width(), image.height());
+
+        sheet.blit_from(image, Rect::new(0, 0, image.width(), image.height()), (rect.x as i32, rect.y as i32));
+        rect
+      })
+      .collect();
+
+    Ok((sheet, placements))
+  }
+  // Joins `images` left to right, top-aligned, with shorter ones padded
+  // below with `background`. The result is 32-bpp if any image is or the
+  // background is translucent, and 24-bpp otherwise.
+  pub fn hconcat(images: &[&Bitmap], background: Rgba) -> BitmapResult<Bitmap> {
+    Bitmap::concat(images, true, background)
+  }
+
+  // Joins `images` top to bottom, left-aligned, as `hconcat` does.
+  pub fn vconcat(images: &[&Bitmap], background: Rgba) -> BitmapResult<Bitmap> {
+    Bitmap::concat(images, false, background)
+  }
+
+  fn concat(images: &[&Bitmap], horizontal: bool, background: Rgba) -> BitmapResult<Bitmap> {
+    if images.is_empty() {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    // Extent along the join, and across it.
+    let along = |image: &Bitmap| if horizontal { image.width() } else { image.height() };
+    let across = |image: &Bitmap| if horizontal { image.height() } else { image.width() };
+    let length = images.iter().map(|image| along(image) as u64).sum::<u64>();
+    let breadth = images.iter().map(|image| across(image) as u64).max().unwrap_or(0);
+    let (width, height) = if horizontal { (length, breadth) } else { (breadth, length) };
+    if width > i32::MAX as u64 || height > i32::MAX as u64 || width * height > usize::MAX as u64 / 2 {
+      return Err(BitmapError::DimensionOverflow)
+    }
+
+    let bpp = if background.a != 255 || images.iter().any(|image| image.header.bpp == 32) { 32 } else { 24 };
+    let mut joined = Bitmap::assemble(width as u32, height as i32, bpp, Vec::new(), Vec::new());
+    let fill = joined.pixel_for(background);
+    joined.pixels = vec![fill; (width * height) as usize];
+
+    let mut offset = 0;
+    for image in images {
+      let destination = if horizontal { (offset, 0) } else { (0, offset) };
+      joined.blit_from(image, Rect::new(0, 0, image.width(), image.height()), destination);
+      offset += along(image) as i32;
+    }
+    Ok(joined)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  use crate::{Bitmap, BitmapError, Pixel, Rect, Rgba};
+
+  #[test]
+  fn montage_places_cells_and_rejects_oversized_sheets() {
+    let image = Bitmap::from_parts(1, 2, 24, vec![], vec![Pixel::BGR(0, 0, 0); 2]).unwrap();
+    let images = [image.clone(), image.clone(), image];
+
+    let (sheet, placements) = Bitmap::montage(&images, 2, 1, Rgba::WHITE).unwrap();
+    assert_eq!((sheet.width(), sheet.height()), (5, 7));
+    assert_eq!(placements, [Rect::new(1, 1, 1, 2), Rect::new(3, 1, 1, 2), Rect::new(1, 4, 1, 2)]);
+
+    assert!(matches!(Bitmap::montage(&images, 2, u32::MAX, Rgba::WHITE), Err(BitmapError::DimensionOverflow)));
+  }
+}
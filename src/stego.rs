@@ -0,0 +1,137 @@
+// Least-significant-bit steganography over truecolor pixels.
+//
+// Payload bits go most significant first into the low `bits` of each color
+// channel, in pixel order and blue, green, red within a pixel (the file's
+// byte order). Alpha is never touched.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel};
+
+impl Bitmap {
+  fn check_lsb(&self, bits: u8) -> BitmapResult<()> {
+    if self.header.bpp < 24 {
+      return Err(BitmapError::UnsupportedBitsPerPixel)
+    }
+
+    if !(1 ..= 8).contains(&bits) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    Ok(())
+  }
+
+  // Bytes that fit at `bits` per channel.
+  pub fn lsb_capacity(&self, bits: u8) -> usize {
+    self.pixels.len() * 3 * bits as usize / 8
+  }
+
+  // Hides `payload` in the low `bits` of every channel. Fails without
+  // changing anything if the image is indexed or the payload doesn't fit.
+  pub fn embed_lsb(&mut self, payload: &[u8], bits: u8) -> BitmapResult<()> {
+    self.check_lsb(bits)?;
+    if payload.len() > self.lsb_capacity(bits) {
+      return Err(BitmapError::BufferTooSmall)
+    }
+
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut stream = payload.iter().flat_map(|byte| (0 .. 8).rev().map(move |bit| byte >> bit & 1));
+
+    'pixels: for pixel in &mut self.pixels {
+      let channels = match pixel {
+        Pixel::BGR(b, g, r) | Pixel::ABGR(b, g, r, _) => [b, g, r],
+        Pixel::PaletteColor(_) => continue
+      };
+
+      for channel in channels {
+        let mut value = 0;
+        let mut taken = 0;
+        for bit in stream.by_ref().take(bits as usize) {
+          value = value << 1 | bit;
+          taken += 1;
+        }
+
+        if taken == 0 {
+          break 'pixels
+        }
+
+        // A short final group keeps its bits at the top of the field.
+        let value = value << (bits - taken);
+        *channel = *channel & !mask | value;
+      }
+    }
+
+    Ok(())
+  }
+
+  // Reads back `len` bytes written by `embed_lsb` with the same `bits`.
+  pub fn extract_lsb(&self, len: usize, bits: u8) -> BitmapResult<Vec<u8>> {
+    self.check_lsb(bits)?;
+    if len > self.lsb_capacity(bits) {
+      return Err(BitmapError::BufferTooSmall)
+    }
+
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut stream = self.pixels.iter()
+      .flat_map(|pixel| match *pixel {
+        Pixel::BGR(b, g, r) | Pixel::ABGR(b, g, r, _) => [b, g, r],
+        Pixel::PaletteColor(_) => [0; 3]
+      })
+      .flat_map(|channel| (0 .. bits).rev().map(move |bit| (channel & mask) >> bit & 1));
+
+    let bytes = (0 .. len)
+      .map(|_| stream.by_ref().take(8).fold(0, |byte, bit| byte << 1 | bit))
+      .collect();
+
+    Ok(bytes)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  use crate::encode::tests::sample;
+  use crate::{Bitmap, BitmapError, Pixel};
+
+  #[test]
+  fn extracts_what_was_embedded() {
+    let payload = b"hidden!";
+    for bits in [1, 3, 8] {
+      for bpp in [24, 32] {
+        let original = sample(bpp, 8);
+        let mut bitmap = original.clone();
+        bitmap.embed_lsb(payload, bits).unwrap();
+
+        assert_eq!(bitmap.extract_lsb(payload.len(), bits).unwrap(), payload, "{} bits at {} bpp", bits, bpp);
+        for (pixel, before) in bitmap.pixels.iter().zip(&original.pixels) {
+          if let (Pixel::ABGR(_, _, _, alpha), Pixel::ABGR(_, _, _, kept)) = (pixel, before) {
+            assert_eq!(alpha, kept);
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn keeps_short_final_groups_at_the_top_of_the_field() {
+    // Eight bits at three per channel: 101, 001 and then 11 padded to 110.
+    let mut bitmap = Bitmap::from_parts(2, 1, 24, vec![], vec![Pixel::BGR(0xff, 0xff, 0xff); 2]).unwrap();
+    bitmap.embed_lsb(&[0b1010_0111], 3).unwrap();
+
+    assert_eq!(bitmap.pixels, [Pixel::BGR(0xfd, 0xf9, 0xfe), Pixel::BGR(0xff, 0xff, 0xff)]);
+    assert_eq!(bitmap.extract_lsb(1, 3).unwrap(), [0b1010_0111]);
+  }
+
+  #[test]
+  fn rejects_payloads_that_do_not_fit() {
+    let mut bitmap = sample(24, 8);
+    let before = bitmap.pixels.clone();
+
+    assert_eq!(bitmap.lsb_capacity(1), 9);
+    assert!(matches!(bitmap.embed_lsb(&[0; 10], 1), Err(BitmapError::BufferTooSmall)));
+    assert!(matches!(bitmap.embed_lsb(&[0], 9), Err(BitmapError::InvalidBitmapData)));
+    assert!(matches!(sample(8, 8).embed_lsb(&[0], 1), Err(BitmapError::UnsupportedBitsPerPixel)));
+    assert_eq!(bitmap.pixels, before);
+  }
+}
@@ -0,0 +1,117 @@
+// Pixel-free description of a bitmap, for cataloguing and fixtures.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Bitmap, BitmapV5Header, CompressionMethod, FileHeader, HeaderVersion, Rgba};
+
+const METERS_PER_INCH: f32 = 0.0254;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitmapSummary {
+  pub size: u32,
+  pub offset: u32,
+  pub width: u32,
+  pub height: u32,
+  pub top_down: bool,
+  pub header: BitmapV5Header,
+  pub palette: Vec<Rgba>
+}
+
+impl Bitmap {
+  pub fn summary(&self) -> BitmapSummary {
+    BitmapSummary {
+      size: self.file_header.file_size,
+      offset: self.file_header.pixel_offset,
+      width: self.width(),
+      height: self.height(),
+      top_down: self.header.pix_height < 0,
+      header: self.header,
+      palette: self.palette.clone()
+    }
+  }
+
+  // Horizontal and vertical resolution in dots per inch, if the header
+  // gives both. Files that leave it unset store zeros.
+  pub fn dpi(&self) -> Option<(f32, f32)> {
+    let (x, y) = self.pels_per_meter()?;
+    Some((dpi(x), dpi(y)))
+  }
+
+  // Width and height in millimetres at the header's resolution, for laying
+  // the image out at true size. `None` when the resolution is unset.
+  pub fn physical_size_mm(&self) -> Option<(f32, f32)> {
+    let (x, y) = self.pels_per_meter()?;
+    Some((self.width() as f32 * 1000.0 / x, self.height() as f32 * 1000.0 / y))
+  }
+
+  fn pels_per_meter(&self) -> Option<(f32, f32)> {
+    let (x, y) = (self.header.x_pels_per_meter, self.header.y_pels_per_meter);
+    (x > 0 && y > 0).then_some((x as f32, y as f32))
+  }
+}
+
+// Pixels per meter to dots per inch.
+fn dpi(pels_per_meter: f32) -> f32 {
+  pels_per_meter * METERS_PER_INCH
+}
+
+// Headers print as aligned `name: value` lines, one field or group per line.
+impl fmt::Display for FileHeader {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "signature:    {}{}", self.magic[0] as char, self.magic[1] as char)?;
+    writeln!(f, "file size:    {} bytes", self.file_size)?;
+    writeln!(f, "reserved:     {} {}", self.reserved1, self.reserved2)?;
+    write!(f, "pixel offset: {}", self.pixel_offset)
+  }
+}
+
+impl fmt::Display for BitmapV5Header {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let version = match HeaderVersion::from_size(self.size) {
+      HeaderVersion::Core => "BITMAPCOREHEADER",
+      HeaderVersion::Os2 => "OS/2 BITMAPINFOHEADER2",
+      HeaderVersion::Info => "BITMAPINFOHEADER",
+      HeaderVersion::V2 => "BITMAPV2INFOHEADER",
+      HeaderVersion::V3 => "BITMAPV3INFOHEADER",
+      HeaderVersion::V4 => "BITMAPV4HEADER",
+      HeaderVersion::V5 => "BITMAPV5HEADER",
+      HeaderVersion::Unknown(_) => "unknown header"
+    };
+    writeln!(f, "header:       {} ({} bytes)", version, self.size)?;
+
+    let order = if self.pix_height < 0 { "top-down" } else { "bottom-up" };
+    writeln!(f, "dimensions:   {} x {}, {}", self.pix_width, self.pix_height.unsigned_abs(), order)?;
+    writeln!(f, "depth:        {} bpp", self.bpp)?;
+
+    write!(f, "compression:  ")?;
+    match self.method {
+      CompressionMethod::None => writeln!(f, "none")?,
+      CompressionMethod::Rle8Bit => writeln!(f, "RLE8")?,
+      CompressionMethod::Rle4Bit => writeln!(f, "RLE4")?,
+      // Method 3 is Huffman only for OS/2 fax images.
+      CompressionMethod::Huffman1D if self.bpp == 1 => writeln!(f, "Huffman 1D")?,
+      CompressionMethod::Huffman1D => writeln!(f, "bitfields")?,
+      CompressionMethod::Jpeg => writeln!(f, "JPEG")?,
+      CompressionMethod::Png => writeln!(f, "PNG")?,
+      CompressionMethod::Other(method) => writeln!(f, "unknown ({})", method)?
+    }
+
+    match (self.x_pels_per_meter, self.y_pels_per_meter) {
+      (0, 0) => writeln!(f, "resolution:   unset")?,
+      (x, y) => writeln!(f, "resolution:   {:.0} x {:.0} dpi", dpi(x as f32), dpi(y as f32))?
+    }
+
+    write!(f, "palette:      ")?;
+    match (self.colors, self.bpp) {
+      (0, bpp) if bpp <= 8 => write!(f, "{} entries (implied)", 1 << bpp)?,
+      (0, _) => write!(f, "none")?,
+      (colors, _) => write!(f, "{} entries", colors)?
+    }
+    if self.important_colors != 0 {
+      write!(f, ", {} important", self.important_colors)?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,62 @@
+// Snapshot-test support behind `assert_bitmaps_eq!`. Failed comparisons
+// leave the expected, actual and diff images in `failure_dir()` for
+// inspection.
+
+use std::path::PathBuf;
+
+use crate::{Bitmap, Rgba};
+
+// Where failing comparisons write their images: `MICROBMP_FAILURES` if
+// set, otherwise `microbmp-failures` in the system temp directory.
+pub fn failure_dir() -> PathBuf {
+  std::env::var_os("MICROBMP_FAILURES")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| std::env::temp_dir().join("microbmp-failures"))
+}
+
+// Compares like `Bitmap::compare`. On a mismatch, writes the images as
+// `<name>-expected.bmp`, `<name>-actual.bmp` and `<name>-diff.bmp` and
+// describes the difference.
+pub fn check_bitmaps_eq(expected: &Bitmap, actual: &Bitmap, tolerance: u8, name: &str) -> Result<(), String> {
+  let result = expected.compare(actual, tolerance);
+  if result.matches {
+    return Ok(())
+  }
+
+  let mut message = if result.same_size {
+    let (x, y) = expected.first_difference(actual, tolerance).unwrap_or((0, 0));
+    let color = |bitmap: &Bitmap| bitmap.pixel(x, y).map_or(Rgba::default(), |pixel| bitmap.color(*pixel));
+    format!(
+      "bitmaps differ at ({}, {}): expected {:?}, actual {:?}; {} of {} pixels differ, by up to {}",
+      x, y, color(expected), color(actual), result.differing_pixels, expected.width() as usize * expected.height() as usize, result.max_delta
+    )
+  } else {
+    format!("bitmap sizes differ: expected {}x{}, actual {}x{}", expected.width(), expected.height(), actual.width(), actual.height())
+  };
+
+  let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect();
+  let (dir, diff) = (failure_dir(), expected.diff_image(actual));
+  let written = std::fs::create_dir_all(&dir).is_ok()
+    && [(expected, "expected"), (actual, "actual"), (&diff, "diff")]
+      .iter()
+      .all(|(bitmap, kind)| bitmap.save(dir.join(format!("{}-{}.bmp", name, kind))).is_ok());
+
+  if written {
+    message.push_str(&format!("\nimages written to {}/{}-{{expected,actual,diff}}.bmp", dir.display(), name));
+  }
+  Err(message)
+}
+
+// Panics unless two bitmaps render the same, optionally within a
+// per-channel tolerance, writing the images for inspection first.
+#[macro_export]
+macro_rules! assert_bitmaps_eq {
+  ($expected:expr, $actual:expr $(,)?) => {
+    $crate::assert_bitmaps_eq!($expected, $actual, 0)
+  };
+  ($expected:expr, $actual:expr, $tolerance:expr $(,)?) => {
+    if let Err(message) = $crate::testing::check_bitmaps_eq(&$expected, &$actual, $tolerance, concat!(module_path!(), "-", line!())) {
+      panic!("{}", message)
+    }
+  };
+}
@@ -0,0 +1,132 @@
+// Text renderings of bitmaps for terminals and logs.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::convert::BAYER_4X4;
+use crate::{Bitmap, Rgba};
+
+// Luminance ramp from dark to light.
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+// Bit of each dot within a braille cell, indexed by [row][column].
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextStyle {
+  // One ramp character per cell.
+  Ascii,
+  // One Unicode braille pattern per cell, covering 2x4 pixels.
+  Braille
+}
+
+// Settings for `render_text`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextOptions {
+  // Output width in characters.
+  pub width: u32,
+  pub style: TextStyle,
+  // Apply ordered dithering so gradients survive the few output levels.
+  pub dither: bool,
+  // Map dark pixels to dense characters, for dark text on a light background.
+  pub invert: bool
+}
+
+impl Default for TextOptions {
+  fn default() -> TextOptions {
+    TextOptions { width: 80, style: TextStyle::Ascii, dither: false, invert: false }
+  }
+}
+
+// Rec. 709 luma of the sRGB values, 0 to 255.
+fn luma(color: Rgba) -> u32 {
+  (color.r as u32 * 2126 + color.g as u32 * 7152 + color.b as u32 * 722) / 10000
+}
+
+impl Bitmap {
+  // Nearest-neighbour sample of the image scaled to `columns` x `rows`.
+  fn sample(&self, x: u32, y: u32, columns: u32, rows: u32) -> Rgba {
+    let sx = (x as u64 * self.width() as u64 / columns as u64) as u32;
+    let sy = (y as u64 * self.height() as u64 / rows as u64) as u32;
+    self.pixel(sx, sy).map_or(Rgba::BLACK, |pixel| self.color(*pixel))
+  }
+
+  // Rows of upper-half blocks coloured with 24-bit ANSI escapes, two pixels
+  // per character cell so the preview keeps its aspect ratio. Alpha is ignored.
+  pub fn render_ansi(&self, width_cols: u32) -> String {
+    let columns = width_cols.max(1);
+    let rows = ((self.height() as u64 * columns as u64 / self.width() as u64) as u32).max(1);
+    let mut out = String::new();
+
+    for y in (0 .. rows).step_by(2) {
+      for x in 0 .. columns {
+        let top = self.sample(x, y, columns, rows);
+        let _ = write!(out, "\x1b[38;2;{};{};{}m", top.r, top.g, top.b);
+
+        if y + 1 < rows {
+          let bottom = self.sample(x, y + 1, columns, rows);
+          let _ = write!(out, "\x1b[48;2;{};{};{}m", bottom.r, bottom.g, bottom.b);
+        } else {
+          out.push_str("\x1b[49m");
+        }
+
+        out.push('\u{2580}');
+      }
+
+      out.push_str("\x1b[0m\n");
+    }
+
+    out
+  }
+
+  // Plain-text rendering, one line per row of cells. Character cells are
+  // taken to be twice as tall as they are wide.
+  pub fn render_text(&self, options: TextOptions) -> String {
+    let columns = options.width.max(1);
+    let rows = ((self.height() as u64 * columns as u64 / self.width() as u64 / 2) as u32).max(1);
+    let (dots_x, dots_y) = match options.style {
+      TextStyle::Ascii => (1, 1),
+      TextStyle::Braille => (2, 4)
+    };
+
+    // Luma of dot (x, y), flipped when inverting.
+    let level = |x: u32, y: u32| {
+      let value = luma(self.sample(x, y, columns * dots_x, rows * dots_y));
+      if options.invert { 255 - value } else { value }
+    };
+    // Dither threshold in sixteenths, or rounding to nearest without.
+    let threshold = |x: u32, y: u32| {
+      if options.dither { BAYER_4X4[y as usize % 4][x as usize % 4] as u32 } else { 8 }
+    };
+
+    let mut out = String::new();
+    for row in 0 .. rows {
+      for column in 0 .. columns {
+        match options.style {
+          TextStyle::Ascii => {
+            let steps = ASCII_RAMP.len() as u32 - 1;
+            let index = (level(column, row) * steps * 16 + threshold(column, row) * 255) / (255 * 16);
+            out.push(ASCII_RAMP[index.min(steps) as usize] as char);
+          }
+
+          TextStyle::Braille => {
+            let mut bits = 0;
+            for (dy, dots) in BRAILLE_DOTS.iter().enumerate() {
+              for (dx, dot) in dots.iter().enumerate() {
+                let (x, y) = (column * 2 + dx as u32, row * 4 + dy as u32);
+                if level(x, y) * 16 > threshold(x, y) * 256 {
+                  bits |= *dot as u32;
+                }
+              }
+            }
+            out.push(char::from_u32(0x2800 + bits).unwrap_or(' '));
+          }
+        }
+      }
+
+      out.push('\n');
+    }
+
+    out
+  }
+}
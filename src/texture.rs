@@ -0,0 +1,154 @@
+// Pixel data laid out for GPU texture uploads, with the matching format names.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use crate::{Bitmap, Decoder, Pixel};
+
+// Eight-bit sRGB formats with straight alpha, in memory byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+  Rgba8UnormSrgb,
+  Bgra8UnormSrgb
+}
+
+impl TextureFormat {
+  // Variant name of the matching `wgpu::TextureFormat`.
+  pub const fn wgpu_name(&self) -> &'static str {
+    match self {
+      TextureFormat::Rgba8UnormSrgb => "Rgba8UnormSrgb",
+      TextureFormat::Bgra8UnormSrgb => "Bgra8UnormSrgb"
+    }
+  }
+
+  // `internalformat`, `format` and `type` arguments for `glTexImage2D`.
+  pub const fn gl_names(&self) -> (&'static str, &'static str, &'static str) {
+    match self {
+      TextureFormat::Rgba8UnormSrgb => ("GL_SRGB8_ALPHA8", "GL_RGBA", "GL_UNSIGNED_BYTE"),
+      TextureFormat::Bgra8UnormSrgb => ("GL_SRGB8_ALPHA8", "GL_BGRA", "GL_UNSIGNED_BYTE")
+    }
+  }
+
+  // Numeric values of `gl_names`.
+  pub const fn gl_enums(&self) -> (u32, u32, u32) {
+    match self {
+      TextureFormat::Rgba8UnormSrgb => (0x8c43, 0x1908, 0x1401),
+      TextureFormat::Bgra8UnormSrgb => (0x8c43, 0x80e1, 0x1401)
+    }
+  }
+
+  pub const fn bytes_per_pixel(&self) -> usize {
+    4
+  }
+}
+
+// Top-down texture rows, `row_pitch` bytes apart. Borrowed when the file
+// bytes can be uploaded as they are.
+#[derive(Debug, Clone)]
+pub struct TextureData<'a> {
+  pub bytes: Cow<'a, [u8]>,
+  pub width: u32,
+  pub height: u32,
+  pub format: TextureFormat,
+  pub row_pitch: usize
+}
+
+impl TextureData<'_> {
+  pub fn into_parts(self) -> (Vec<u8>, u32, u32, TextureFormat, usize) {
+    (self.bytes.into_owned(), self.width, self.height, self.format, self.row_pitch)
+  }
+}
+
+impl<'a> Decoder<'a> {
+  // Top-down 32-bpp files in plain BGRA order are already texture rows, so
+  // their pixel array is borrowed directly. Everything else is expanded to
+  // RGBA: other depths, bitfields in any other order, and files whose
+  // fourth byte is zero throughout, which come out opaque as with
+  // `AlphaHandling::Auto`.
+  pub fn texture_data(&self) -> TextureData<'a> {
+    let (width, height) = (self.width(), self.height());
+    let unused_alpha = self.header.bpp == 32 && self.masks.is_none() && self.pixels().all(|pixel| matches!(pixel, Pixel::ABGR(_, _, _, 0)));
+
+    if self.header.bpp == 32 && self.is_top_down() && self.masks.is_none() && !unused_alpha {
+      if let Some(bytes) = self.data.get(self.file_header.pixel_offset as usize .. self.image_end()) {
+        return TextureData { bytes: Cow::Borrowed(bytes), width, height, format: TextureFormat::Bgra8UnormSrgb, row_pitch: self.stride() }
+      }
+    }
+
+    let bytes = self.pixels()
+      .flat_map(|pixel| {
+        // File pixels are always straight, so there's nothing to divide out.
+        let color = self.color(pixel);
+        [color.r, color.g, color.b, if unused_alpha { 255 } else { color.a }]
+      })
+      .collect::<Vec<_>>();
+
+    TextureData { bytes: Cow::Owned(bytes), width, height, format: TextureFormat::Rgba8UnormSrgb, row_pitch: width as usize * 4 }
+  }
+}
+
+impl Bitmap {
+  // Always copies, since `pixels` may have been edited since decoding.
+  pub fn texture_data(&self) -> TextureData<'static> {
+    TextureData {
+      bytes: Cow::Owned(self.to_rgba8()),
+      width: self.width(),
+      height: self.height(),
+      format: TextureFormat::Rgba8UnormSrgb,
+      row_pitch: self.width() as usize * 4
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::borrow::Cow;
+  use alloc::vec::Vec;
+
+  use crate::{Decoder, TextureFormat};
+
+  // A top-down 2x1 32-bpp file with a 40-byte header, followed by `masks`
+  // for BI_ALPHABITFIELDS or nothing for BI_RGB.
+  fn top_down_32(masks: Option<[u32; 4]>, pixels: [u8; 8]) -> Vec<u8> {
+    let offset = 54 + if masks.is_some() { 16 } else { 0 };
+    let mut file = Vec::new();
+    file.extend_from_slice(b"BM");
+    file.extend_from_slice(&(offset + 8u32).to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&offset.to_le_bytes());
+    file.extend_from_slice(&40u32.to_le_bytes());
+    file.extend_from_slice(&2i32.to_le_bytes());
+    file.extend_from_slice(&(-1i32).to_le_bytes());
+    file.extend_from_slice(&[1, 0, 32, 0]);
+    file.extend_from_slice(&(if masks.is_some() { 6u32 } else { 0 }).to_le_bytes());
+    file.extend_from_slice(&[0; 20]);
+    for mask in masks.unwrap_or_default().iter().take(if masks.is_some() { 4 } else { 0 }) {
+      file.extend_from_slice(&mask.to_le_bytes());
+    }
+    file.extend_from_slice(&pixels);
+    file
+  }
+
+  #[test]
+  fn borrows_only_plain_bgra_with_alpha() {
+    let file = top_down_32(None, [10, 20, 30, 255, 40, 50, 60, 128]);
+    let texture = Decoder::new(&file).unwrap().texture_data();
+    assert!(matches!(texture.bytes, Cow::Borrowed(_)));
+    assert_eq!((texture.format, &texture.bytes[..]), (TextureFormat::Bgra8UnormSrgb, &[10, 20, 30, 255, 40, 50, 60, 128][..]));
+  }
+
+  #[cfg(feature = "bitfields")]
+  #[test]
+  fn converts_other_channel_orders() {
+    let file = top_down_32(Some([0xff, 0xff00, 0xff_0000, 0xff00_0000]), [10, 20, 30, 255, 40, 50, 60, 128]);
+    let texture = Decoder::new(&file).unwrap().texture_data();
+    assert_eq!((texture.format, &texture.bytes[..]), (TextureFormat::Rgba8UnormSrgb, &[10, 20, 30, 255, 40, 50, 60, 128][..]));
+  }
+
+  #[test]
+  fn unused_alpha_comes_out_opaque() {
+    let file = top_down_32(None, [10, 20, 30, 0, 40, 50, 60, 0]);
+    let texture = Decoder::new(&file).unwrap().texture_data();
+    assert_eq!((texture.format, &texture.bytes[..]), (TextureFormat::Rgba8UnormSrgb, &[30, 20, 10, 255, 60, 50, 40, 255][..]));
+  }
+}
@@ -0,0 +1,239 @@
+// Targa import and export: color-mapped, truecolor and grayscale, raw or RLE.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel, Rgba};
+
+const HEADER_SIZE: usize = 18;
+
+// Descriptor bits for the image origin.
+const RIGHT_TO_LEFT: u8 = 0x10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+// Expands RLE packets (or copies raw data) into `count` pixels of `depth` bytes.
+fn unpack(data: &[u8], count: usize, depth: usize, rle: bool) -> BitmapResult<Vec<u8>> {
+  let size = count * depth;
+  if !rle {
+    return data.get(.. size).map(|raw| raw.to_vec()).ok_or(BitmapError::InvalidBitmapData)
+  }
+
+  // A packet of 1 + `depth` bytes expands to at most 128 pixels, so short
+  // input can't reserve the whole declared image up front.
+  let mut out = Vec::with_capacity(size.min(data.len().saturating_mul(128)));
+  let mut at = 0;
+
+  while out.len() < size {
+    let packet = *data.get(at).ok_or(BitmapError::InvalidBitmapData)?;
+    let length = (packet & 0x7f) as usize + 1;
+    at += 1;
+
+    if packet & 0x80 != 0 {
+      let value = data.get(at .. at + depth).ok_or(BitmapError::InvalidBitmapData)?;
+      for _ in 0 .. length {
+        out.extend_from_slice(value);
+      }
+      at += depth;
+    } else {
+      out.extend_from_slice(data.get(at .. at + length * depth).ok_or(BitmapError::InvalidBitmapData)?);
+      at += length * depth;
+    }
+  }
+
+  out.truncate(size);
+  Ok(out)
+}
+
+// Packs `pixels` of `depth` bytes into RLE packets, which never span scanlines.
+fn pack(pixels: &[u8], width: usize, depth: usize, out: &mut Vec<u8>) {
+  for row in pixels.chunks(width * depth) {
+    let row = row.chunks(depth).collect::<Vec<_>>();
+    let mut x = 0;
+
+    while x < row.len() {
+      let mut run = 1;
+      while x + run < row.len() && run < 128 && row[x + run] == row[x] {
+        run += 1;
+      }
+
+      if run > 1 {
+        out.push(0x80 | (run - 1) as u8);
+        out.extend_from_slice(row[x]);
+        x += run;
+        continue
+      }
+
+      // Raw stretch up to the next pixel that starts a run.
+      let start = x;
+      x += 1;
+      while x < row.len() && x - start < 128 && (x + 1 == row.len() || row[x + 1] != row[x]) {
+        x += 1;
+      }
+
+      out.push((x - start - 1) as u8);
+      for value in &row[start .. x] {
+        out.extend_from_slice(value);
+      }
+    }
+  }
+}
+
+impl Bitmap {
+  // Accepts color-mapped (8-bit indices), 24/32-bit truecolor and 8-bit grayscale files.
+  pub fn from_tga(data: &[u8]) -> BitmapResult<Bitmap> {
+    let header = data.get(.. HEADER_SIZE).ok_or(BitmapError::InvalidBitmapData)?;
+    let u16_at = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+
+    let (id_length, map_type, image_type) = (header[0] as usize, header[1], header[2]);
+    let (map_first, map_length, map_depth) = (u16_at(3) as usize, u16_at(5) as usize, header[7]);
+    let (width, height) = (u16_at(12) as u32, u16_at(14) as u32);
+    let (depth, descriptor) = (header[16], header[17]);
+
+    if width == 0 || height == 0 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let rle = image_type & 0x8 != 0;
+    let mut at = HEADER_SIZE + id_length;
+
+    // Color map entries are BGR(A), at 24 or 32 bits.
+    let mut palette = Vec::new();
+    if map_type == 1 {
+      let entry = match map_depth {
+        24 => 3,
+        32 => 4,
+        _ => return Err(BitmapError::UnsupportedBitsPerPixel)
+      };
+      let map = data.get(at .. at + map_length * entry).ok_or(BitmapError::InvalidBitmapData)?;
+      palette = map.chunks(entry).map(|bgr| Rgba::new(bgr[2], bgr[1], bgr[0], 255)).collect();
+      at += map_length * entry;
+    }
+
+    let bytes_per_pixel = match (image_type & 0x7, depth) {
+      (1, 8) | (3, 8) => 1,
+      (2, 24) => 3,
+      (2, 32) => 4,
+      (1 ..= 3, _) => return Err(BitmapError::UnsupportedBitsPerPixel),
+      _ => return Err(BitmapError::UnsupportedCompression)
+    };
+
+    let count = width as usize * height as usize;
+    let raw = unpack(data.get(at ..).unwrap_or(&[]), count, bytes_per_pixel, rle)?;
+
+    let (bpp, palette) = match image_type & 0x7 {
+      1 => {
+        // Indices are offset by the first map entry; missing entries resolve to black.
+        let mut shifted = Vec::new();
+        shifted.resize(map_first, Rgba::BLACK);
+        shifted.extend(palette);
+        shifted.truncate(256);
+        (8, shifted)
+      }
+      3 => (8, (0 ..= 255).map(|level| Rgba::new(level, level, level, 255)).collect()),
+      _ => (depth as u16, Vec::new())
+    };
+
+    let mut pixels = raw
+      .chunks(bytes_per_pixel)
+      .map(|value| match bytes_per_pixel {
+        1 => Pixel::PaletteColor(value[0]),
+        3 => Pixel::BGR(value[0], value[1], value[2]),
+        _ => Pixel::ABGR(value[0], value[1], value[2], value[3])
+      })
+      .collect::<Vec<_>>();
+
+    if descriptor & RIGHT_TO_LEFT != 0 {
+      pixels.chunks_mut(width as usize).for_each(|row| row.reverse());
+    }
+
+    if descriptor & TOP_TO_BOTTOM == 0 {
+      pixels = pixels.chunks(width as usize).rev().flatten().copied().collect();
+    }
+
+    Bitmap::from_parts(width, height, bpp, palette, pixels)
+  }
+
+  // Top-down Targa; indexed images keep a color map with 8-bit indices.
+  // Dimensions beyond 65535 can't be represented and are rejected.
+  pub fn to_tga(&self, rle: bool) -> BitmapResult<Vec<u8>> {
+    let (width, height) = (self.width(), self.height());
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let indexed = self.header.bpp <= 8;
+    let (image_type, depth, bytes_per_pixel): (u8, u8, usize) = match self.header.bpp {
+      1 | 4 | 8 => (1, 8, 1),
+      32 => (2, 32, 4),
+      _ => (2, 24, 3)
+    };
+
+    let mut out = Vec::with_capacity(HEADER_SIZE + self.pixels.len() * bytes_per_pixel);
+    out.extend_from_slice(&[0, indexed as u8, image_type | if rle { 0x8 } else { 0 }]);
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&(if indexed { self.palette.len() as u16 } else { 0 }).to_le_bytes());
+    out.push(if indexed { 24 } else { 0 });
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    out.push(depth);
+    out.push(TOP_TO_BOTTOM | if depth == 32 { 8 } else { 0 });
+
+    if indexed {
+      for color in &self.palette {
+        out.extend_from_slice(&[color.b, color.g, color.r]);
+      }
+    }
+
+    let raw = self.pixels
+      .iter()
+      .flat_map(|pixel| {
+        let color = self.straight_color(*pixel);
+        let value = match (*pixel, bytes_per_pixel) {
+          (Pixel::PaletteColor(index), 1) => [index, 0, 0, 0],
+          _ => [color.b, color.g, color.r, color.a]
+        };
+        value.into_iter().take(bytes_per_pixel)
+      })
+      .collect::<Vec<_>>();
+
+    if rle {
+      pack(&raw, width as usize, bytes_per_pixel, &mut out);
+    } else {
+      out.extend_from_slice(&raw);
+    }
+
+    Ok(out)
+  }
+
+  #[cfg(feature = "fs")]
+  pub fn save_tga<P: AsRef<std::path::Path>>(&self, path: P, rle: bool) -> BitmapResult<()> {
+    std::fs::write(path, self.to_tga(rle)?)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::encode::tests::{sample, DEPTHS};
+  use crate::Bitmap;
+
+  #[test]
+  fn round_trips_raw_and_rle() {
+    for bpp in DEPTHS {
+      let bitmap = sample(*bpp, 5);
+      for rle in [false, true] {
+        let decoded = Bitmap::from_tga(&bitmap.to_tga(rle).unwrap()).unwrap();
+        assert_eq!(decoded.header.bpp, (*bpp).max(8), "{} bpp", bpp);
+        assert!(decoded.compare(&bitmap, 0).matches, "{} bpp, rle {}", bpp, rle);
+      }
+    }
+  }
+
+  #[test]
+  fn rejects_truncated_files() {
+    let file = sample(24, 5).to_tga(true).unwrap();
+    for len in [0, 17, file.len() - 1] {
+      assert!(Bitmap::from_tga(&file[.. len]).is_err(), "{} bytes", len);
+    }
+  }
+}
@@ -0,0 +1,159 @@
+// Async reading and writing over `tokio::io`.
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[cfg(feature = "stream")]
+use futures_util::stream::{self, Stream};
+
+#[cfg(feature = "stream")]
+use crate::Row;
+use crate::{Bitmap, BitmapResult, IncrementalDecoder};
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+// Scanlines are batched into writes of roughly this many bytes.
+const WRITE_BATCH: usize = 64 * 1024;
+
+impl Bitmap {
+  // Reads just enough of `reader` for the headers and pixel array, rejecting
+  // non-bitmaps as soon as the headers arrive. Anything after the pixel array
+  // is left unread.
+  pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: &mut R) -> BitmapResult<Bitmap> {
+    let mut decoder = IncrementalDecoder::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+      let needed = decoder.bytes_needed()?;
+      if needed == 0 {
+        break
+      }
+
+      let read = reader.read(&mut chunk[.. needed.min(CHUNK_SIZE)]).await?;
+      if read == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+      }
+
+      decoder.push(&chunk[.. read]);
+    }
+
+    decoder.finish()
+  }
+
+  // Encodes like `encode()`, but writes the headers and then batches of
+  // scanlines, awaiting each write so a slow peer applies backpressure.
+  pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> BitmapResult<()> {
+    writer.write_all(&self.encode_headers()).await?;
+
+    let stride = self.encoded_stride();
+    let rows_per_batch = (WRITE_BATCH / stride.max(1)).max(1) as u32;
+    let mut batch = Vec::with_capacity(stride * rows_per_batch as usize);
+
+    for first in (0 .. self.height()).step_by(rows_per_batch as usize) {
+      batch.clear();
+
+      for stored in first .. (first + rows_per_batch).min(self.height()) {
+        let start = batch.len();
+        batch.resize(start + stride, 0);
+        self.encode_stored_row(stored, &mut batch[start ..]);
+      }
+
+      writer.write_all(&batch).await?;
+    }
+
+    writer.flush().await?;
+    Ok(())
+  }
+}
+
+// Rows in storage order as soon as each one has arrived, for progressive
+// rendering. The stream ends after the last row or the first error.
+#[cfg(feature = "stream")]
+pub fn row_stream<R: AsyncRead + Unpin>(reader: R) -> impl Stream<Item = BitmapResult<Row>> {
+  let state = (reader, IncrementalDecoder::new(), vec![0; CHUNK_SIZE]);
+
+  stream::unfold(Some(state), |state| async move {
+    let (mut reader, mut decoder, mut chunk) = state?;
+
+    loop {
+      match decoder.next_row() {
+        Some(Ok(row)) => return Some((Ok(row), Some((reader, decoder, chunk)))),
+        Some(Err(err)) => return Some((Err(err), None)),
+        None => {}
+      }
+
+      let needed = match decoder.bytes_needed() {
+        Ok(0) => return None,
+        Ok(needed) => needed,
+        Err(err) => return Some((Err(err), None))
+      };
+
+      match reader.read(&mut chunk[.. needed.min(CHUNK_SIZE)]).await {
+        Ok(0) => return Some((Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()), None)),
+        Ok(read) => decoder.push(&chunk[.. read]),
+        Err(err) => return Some((Err(err.into()), None))
+      }
+    }
+  })
+}
+
+#[cfg(all(test, feature = "bitfields"))]
+mod tests {
+  use std::future::Future;
+  use std::pin::pin;
+  use std::task::{Context, Poll, Waker};
+
+  use crate::incremental::tests::alpha_bitfields;
+  use crate::Bitmap;
+
+  // Runs a future that never has to wait, as reads from a slice don't.
+  pub(crate) fn ready<F: Future>(future: F) -> F::Output {
+    match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+      Poll::Ready(output) => output,
+      Poll::Pending => panic!("future wasn't ready")
+    }
+  }
+
+  #[test]
+  fn reads_masks_after_a_40_byte_header() {
+    let file = alpha_bitfields();
+    let bitmap = ready(Bitmap::from_async_reader(&mut &file[..])).unwrap();
+    assert_eq!(bitmap.pixels, Bitmap::from_bytes(&file).unwrap().pixels);
+  }
+
+  // Hands out one byte per read, like a very slow socket.
+  #[cfg(feature = "stream")]
+  struct Trickle<'a>(&'a [u8]);
+
+  #[cfg(feature = "stream")]
+  impl tokio::io::AsyncRead for Trickle<'_> {
+    fn poll_read(mut self: std::pin::Pin<&mut Self>, _: &mut Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+      if let Some((first, rest)) = self.0.split_first() {
+        buf.put_slice(&[*first]);
+        self.0 = rest;
+      }
+      Poll::Ready(Ok(()))
+    }
+  }
+
+  #[cfg(feature = "stream")]
+  #[test]
+  fn streams_rows_after_a_40_byte_header_with_masks() {
+    use futures_util::StreamExt;
+
+    let file = alpha_bitfields();
+    let rows: Vec<_> = ready(crate::row_stream(Trickle(&file)).collect::<Vec<_>>());
+    let rows: Vec<_> = rows.into_iter().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!((rows[0].y, &rows[0].pixels), (0, &Bitmap::from_bytes(&file).unwrap().pixels));
+  }
+
+  #[test]
+  fn writes_what_encode_does() {
+    let bitmap = Bitmap::from_bytes(&alpha_bitfields()).unwrap();
+    let mut out = Vec::new();
+    ready(bitmap.write_to_async(&mut out)).unwrap();
+    assert_eq!(out, bitmap.encode());
+  }
+}
@@ -0,0 +1,26 @@
+// Instrumentation that forwards to `tracing` with the feature on and
+// compiles to nothing without it.
+
+// A debug-level event.
+macro_rules! trace_event {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "tracing")]
+    tracing::debug!($($arg)*);
+  };
+}
+
+// A warn-level event, for input that was accepted after a fix-up.
+macro_rules! trace_warn {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "tracing")]
+    tracing::warn!($($arg)*);
+  };
+}
+
+// A debug-level span, entered until the end of the enclosing block.
+macro_rules! trace_span {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!($($arg)*).entered();
+  };
+}
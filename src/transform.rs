@@ -0,0 +1,235 @@
+// Geometric transforms over the decoded pixels.
+
+use alloc::vec::Vec;
+
+use crate::{Bitmap, BitmapError, BitmapResult, Pixel};
+
+// How `scale_integer` fills in the enlarged pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UpscaleMode {
+  // Every pixel becomes an exact `factor` x `factor` block.
+  #[default]
+  Nearest,
+  // EPX (Scale2x, and Scale3x for factors of three): blocks along diagonal
+  // edges take their neighbours' colors, rounding off staircases without
+  // introducing new colors.
+  Epx
+}
+
+impl Bitmap {
+  // Mirrors the image top to bottom. The header keeps its row order, so
+  // `encode` writes the flipped image the same way the original was stored.
+  pub fn flip_vertical(&mut self) {
+    let width = self.width().max(1) as usize;
+    let rows = self.pixels.len() / width;
+
+    for y in 0 .. rows / 2 {
+      let (top, bottom) = self.pixels.split_at_mut((rows - 1 - y) * width);
+      top[y * width .. (y + 1) * width].swap_with_slice(&mut bottom[.. width]);
+    }
+  }
+
+  pub fn flipped_vertical(mut self) -> Bitmap {
+    self.flip_vertical();
+    self
+  }
+
+  // Mirrors every row left to right. Sub-byte depths need no special care
+  // since pixels are unpacked; `encode` repacks them.
+  pub fn flip_horizontal(&mut self) {
+    let width = self.width().max(1) as usize;
+    for row in self.pixels.chunks_exact_mut(width) {
+      row.reverse();
+    }
+  }
+
+  pub fn flipped_horizontal(mut self) -> Bitmap {
+    self.flip_horizontal();
+    self
+  }
+
+  // Quarter turn clockwise; the result is `height` wide and `width` tall.
+  pub fn rotate90(&self) -> Bitmap {
+    let (width, height) = (self.width(), self.height());
+    let pixels = (0 .. width)
+      .flat_map(|y| (0 .. height).map(move |x| (x, y)))
+      .map(|(x, y)| self.pixels[(height - 1 - x) as usize * width as usize + y as usize])
+      .collect::<Vec<_>>();
+    self.turned_with_pixels(pixels)
+  }
+
+  pub fn rotate180(&self) -> Bitmap {
+    let pixels = self.pixels.iter().rev().copied().collect();
+    self.with_pixels(self.width(), self.height(), pixels)
+  }
+
+  // Quarter turn counter-clockwise.
+  pub fn rotate270(&self) -> Bitmap {
+    let (width, height) = (self.width(), self.height());
+    let pixels = (0 .. width)
+      .flat_map(|y| (0 .. height).map(move |x| (x, y)))
+      .map(|(x, y)| self.pixels[x as usize * width as usize + (width - 1 - y) as usize])
+      .collect::<Vec<_>>();
+    self.turned_with_pixels(pixels)
+  }
+
+  // Copy of the `width` x `height` rectangle at (x, y), which must be
+  // non-empty and lie within the image.
+  pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> BitmapResult<Bitmap> {
+    let fits = |start: u32, len: u32, limit: u32| len > 0 && start.checked_add(len).is_some_and(|end| end <= limit);
+    if !fits(x, width, self.width()) || !fits(y, height, self.height()) {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let stride = self.width() as usize;
+    let pixels = (y .. y + height)
+      .flat_map(|row| {
+        let start = row as usize * stride + x as usize;
+        self.pixels[start .. start + width as usize].iter().copied()
+      })
+      .collect();
+    Ok(self.with_pixels(width, height, pixels))
+  }
+
+  // Scales by picking the source pixel under each destination pixel's
+  // centre, so indexed images keep their exact palette.
+  pub fn resize_nearest(&self, width: u32, height: u32) -> BitmapResult<Bitmap> {
+    if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+      return Err(BitmapError::InvalidBitmapData)
+    }
+
+    let source = |to: u32, from: u32, at: u32| ((2 * at as u64 + 1) * from as u64 / (2 * to as u64)) as usize;
+    let stride = self.width() as usize;
+    let columns = (0 .. width).map(|x| source(width, self.width(), x)).collect::<Vec<_>>();
+
+    let pixels = (0 .. height)
+      .flat_map(|y| {
+        let row = source(height, self.height(), y) * stride;
+        columns.iter().map(move |x| self.pixels[row + x])
+      })
+      .collect();
+    Ok(self.with_pixels(width, height, pixels))
+  }
+
+  // Enlarges by a whole `factor`, keeping every pixel crisp and indexed
+  // images on their palette. With `UpscaleMode::Epx` the factor is split
+  // into passes of 2 and 3, and whatever is left, such as 5, is applied as
+  // plain duplication.
+  pub fn scale_integer(&self, factor: u32, mode: UpscaleMode) -> BitmapResult<Bitmap> {
+    let fits = |len: u32| len.checked_mul(factor).is_some_and(|len| len <= i32::MAX as u32);
+    if factor == 0 || !fits(self.width()) || !fits(self.height()) {
+      return Err(BitmapError::DimensionOverflow)
+    }
+
+    let (mut width, mut height, mut pixels) = (self.width(), self.height(), self.pixels.clone());
+    let mut remaining = factor;
+    if mode == UpscaleMode::Epx {
+      for pass in [3, 2] {
+        while remaining.is_multiple_of(pass) {
+          pixels = epx(&pixels, width, height, pass);
+          (width, height, remaining) = (width * pass, height * pass, remaining / pass);
+        }
+      }
+    }
+
+    if remaining > 1 {
+      let stride = width as usize;
+      pixels = (0 .. height * remaining)
+        .flat_map(|y| {
+          let row = &pixels[(y / remaining) as usize * stride ..][.. stride];
+          row.iter().flat_map(move |pixel| core::iter::repeat_n(*pixel, remaining as usize))
+        })
+        .collect();
+      (width, height) = (width * remaining, height * remaining);
+    }
+
+    Ok(self.with_pixels(width, height, pixels))
+  }
+}
+
+// One Scale2x or Scale3x pass. Neighbours past the edges repeat the edge.
+fn epx(pixels: &[Pixel], width: u32, height: u32, factor: u32) -> Vec<Pixel> {
+  let (width, height, factor) = (width as usize, height as usize, factor as usize);
+  let at = |x: usize, y: usize, dx: isize, dy: isize| {
+    let x = x.saturating_add_signed(dx).min(width - 1);
+    let y = y.saturating_add_signed(dy).min(height - 1);
+    pixels[y * width + x]
+  };
+
+  let out_width = width * factor;
+  let mut out = alloc::vec![Pixel::PaletteColor(0); out_width * height * factor];
+  for y in 0 .. height {
+    for x in 0 .. width {
+      let [a, b, c, d, e, f, g, h, i] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (0, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].map(|(dx, dy)| at(x, y, dx, dy));
+      // Which corners sit on an edge running diagonally through them.
+      let top_left = d == b && b != f && d != h;
+      let top_right = b == f && b != d && f != h;
+      let bottom_left = d == h && d != b && h != f;
+      let bottom_right = h == f && d != h && b != f;
+
+      let block = match factor {
+        2 => [
+          if top_left { d } else { e },
+          if top_right { f } else { e },
+          if bottom_left { d } else { e },
+          if bottom_right { f } else { e },
+          // Past the 2x2 block; unused.
+          e, e, e, e, e
+        ],
+        _ => [
+          if top_left { d } else { e },
+          if (top_left && e != c) || (top_right && e != a) { b } else { e },
+          if top_right { f } else { e },
+          if (top_left && e != g) || (bottom_left && e != a) { d } else { e },
+          e,
+          if (top_right && e != i) || (bottom_right && e != c) { f } else { e },
+          if bottom_left { d } else { e },
+          if (bottom_left && e != i) || (bottom_right && e != g) { h } else { e },
+          if bottom_right { f } else { e }
+        ]
+      };
+
+      for (row, chunk) in block[.. factor * factor].chunks_exact(factor).enumerate() {
+        let start = (y * factor + row) * out_width + x * factor;
+        out[start .. start + factor].copy_from_slice(chunk);
+      }
+    }
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::encode::tests::sample;
+  #[cfg(feature = "low-depth")]
+  use crate::Bitmap;
+  use crate::FILE_HEADER_SIZE;
+
+  #[test]
+  fn quarter_turns_undo_each_other() {
+    for bpp in [1, 8, 24, 32] {
+      let bitmap = sample(bpp, 5);
+      let turned = bitmap.rotate90();
+
+      assert_eq!((turned.width(), turned.height()), (3, 5));
+      // The top-left pixel comes from the bottom-left corner.
+      assert_eq!(turned.pixels[0], bitmap.pixels[10]);
+      assert_eq!(turned.rotate270().pixels, bitmap.pixels, "{} bpp", bpp);
+      assert_eq!(bitmap.rotate270().rotate90().pixels, bitmap.pixels, "{} bpp", bpp);
+      assert_eq!(bitmap.rotate180().rotate180().pixels, bitmap.pixels, "{} bpp", bpp);
+    }
+  }
+
+  #[test]
+  fn rotated_low_depth_images_stay_packed() {
+    let turned = sample(1, 5).rotate90();
+
+    // Five rows of three 1-bpp pixels, each padded to four bytes, after a
+    // two-entry palette.
+    assert_eq!(turned.header.bpp, 1);
+    assert_eq!(turned.data.len(), FILE_HEADER_SIZE + 40 + 8 + 5 * 4);
+    #[cfg(feature = "low-depth")]
+    assert_eq!(Bitmap::from_bytes(&turned.data).unwrap().pixels, turned.pixels);
+  }
+}
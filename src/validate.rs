@@ -0,0 +1,337 @@
+// Spec conformance checks for files headed to picky consumers.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::decoder::{read_i32, read_u16, read_u32, row_stride};
+use crate::masks::masks_len;
+#[cfg(feature = "rle")]
+use crate::{BitmapError, Decoder};
+use crate::{HeaderVersion, FILE_HEADER_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+  // Legal but uncommon enough that some consumers reject it.
+  Info,
+  // Violates the spec in a way most decoders tolerate.
+  Warning,
+  // Violates the spec; expect consumers to fail or misrender.
+  Error
+}
+
+// One problem found by `validate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Issue {
+  BadMagic,
+  // Shorter than the headers, palette or pixel array need.
+  Truncated { needed: usize, actual: usize },
+  UnknownHeaderSize(u32),
+  InvalidPlanes(u16),
+  InvalidDimensions { width: i32, height: i32 },
+  InvalidBitsPerPixel(u16),
+  // Compression method not defined for this depth.
+  InvalidCompression { method: u32, bpp: u16 },
+  // Compressed images must be stored bottom-up.
+  TopDownCompressed,
+  // More palette entries declared than the depth can index.
+  OversizedPalette { colors: u32, bpp: u16 },
+  // `biClrImportant` counts more entries than the palette has.
+  ExcessImportantColors { important: u32, colors: usize },
+  // `bfOffBits` points inside the headers or palette.
+  OffsetOverlapsHeaders { offset: u32, minimum: usize },
+  FileSizeMismatch { declared: u32, actual: usize },
+  ImageSizeMismatch { declared: u32, computed: usize },
+  // The pixel array, or where it ends, is too large to address.
+  SizeOverflow,
+  // Compressed images must give `biSizeImage`.
+  MissingImageSize,
+  // An RLE4/RLE8 stream that writes past the last row or has no
+  // end-of-bitmap code.
+  BadRleStream,
+  NonzeroReserved,
+  TrailingData(usize),
+  NonzeroPadding,
+  // OS/2 or BITMAPCOREHEADER layouts, unsupported by many modern readers.
+  LegacyHeader,
+  // 2-bpp and 16-bpp images are valid but poorly supported.
+  UncommonDepth(u16)
+}
+
+impl Issue {
+  pub const fn severity(&self) -> Severity {
+    match self {
+      Issue::BadMagic
+      | Issue::Truncated { .. }
+      | Issue::UnknownHeaderSize(_)
+      | Issue::InvalidPlanes(_)
+      | Issue::InvalidDimensions { .. }
+      | Issue::InvalidBitsPerPixel(_)
+      | Issue::InvalidCompression { .. }
+      | Issue::TopDownCompressed
+      | Issue::OffsetOverlapsHeaders { .. }
+      | Issue::SizeOverflow
+      | Issue::BadRleStream => Severity::Error,
+      Issue::OversizedPalette { .. }
+      | Issue::FileSizeMismatch { .. }
+      | Issue::ImageSizeMismatch { .. }
+      | Issue::ExcessImportantColors { .. }
+      | Issue::MissingImageSize
+      | Issue::NonzeroReserved => Severity::Warning,
+      Issue::TrailingData(_) | Issue::NonzeroPadding | Issue::LegacyHeader | Issue::UncommonDepth(_) => Severity::Info
+    }
+  }
+}
+
+impl fmt::Display for Issue {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Issue::BadMagic => write!(f, "missing BM signature"),
+      Issue::Truncated { needed, actual } => write!(f, "file is {} bytes but needs {}", actual, needed),
+      Issue::UnknownHeaderSize(size) => write!(f, "unknown info header size {}", size),
+      Issue::InvalidPlanes(planes) => write!(f, "{} color planes instead of 1", planes),
+      Issue::InvalidDimensions { width, height } => write!(f, "invalid dimensions {}x{}", width, height),
+      Issue::InvalidBitsPerPixel(bpp) => write!(f, "invalid bit depth {}", bpp),
+      Issue::InvalidCompression { method, bpp } => write!(f, "compression {} is not defined for {} bpp", method, bpp),
+      Issue::TopDownCompressed => write!(f, "compressed image stored top-down"),
+      Issue::OversizedPalette { colors, bpp } => write!(f, "{} palette entries for {} bpp", colors, bpp),
+      Issue::ExcessImportantColors { important, colors } => write!(f, "{} important colors but only {} palette entries", important, colors),
+      Issue::OffsetOverlapsHeaders { offset, minimum } => write!(f, "pixel offset {} is before the end of the headers at {}", offset, minimum),
+      Issue::FileSizeMismatch { declared, actual } => write!(f, "declared file size {} but file is {} bytes", declared, actual),
+      Issue::ImageSizeMismatch { declared, computed } => write!(f, "declared image size {} but pixel array is {} bytes", declared, computed),
+      Issue::SizeOverflow => write!(f, "pixel array size overflows"),
+      Issue::MissingImageSize => write!(f, "compressed image without an image size"),
+      Issue::BadRleStream => write!(f, "malformed RLE stream"),
+      Issue::NonzeroReserved => write!(f, "reserved fields are not zero"),
+      Issue::TrailingData(len) => write!(f, "{} bytes after the pixel array", len),
+      Issue::NonzeroPadding => write!(f, "row padding is not zero"),
+      Issue::LegacyHeader => write!(f, "legacy OS/2 or core header"),
+      Issue::UncommonDepth(bpp) => write!(f, "poorly supported bit depth {}", bpp)
+    }
+  }
+}
+
+// Every issue found, in file order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidationReport {
+  pub issues: Vec<Issue>
+}
+
+impl ValidationReport {
+  // No errors; warnings and notes are allowed.
+  pub fn is_valid(&self) -> bool {
+    self.issues.iter().all(|issue| issue.severity() < Severity::Error)
+  }
+
+  // Nothing at all to report.
+  pub fn is_clean(&self) -> bool {
+    self.issues.is_empty()
+  }
+
+  pub fn worst(&self) -> Option<Severity> {
+    self.issues.iter().map(Issue::severity).max()
+  }
+}
+
+// Checks `data` against the BMP spec, going as far as the headers allow.
+pub fn validate(data: &[u8]) -> ValidationReport {
+  let mut issues = Vec::new();
+  check(data, &mut issues);
+  ValidationReport { issues }
+}
+
+fn check(data: &[u8], issues: &mut Vec<Issue>) {
+  if data.get(0 .. 2) != Some(&b"BM"[..]) {
+    issues.push(Issue::BadMagic);
+  }
+
+  let (Ok(declared_size), Ok(offset), Ok(header_size)) = (read_u32(data, 2), read_u32(data, 10), read_u32(data, 14)) else {
+    issues.push(Issue::Truncated { needed: FILE_HEADER_SIZE + 4, actual: data.len() });
+    return
+  };
+
+  let header = HeaderVersion::from_size(header_size);
+  match header {
+    HeaderVersion::Core | HeaderVersion::Os2 => issues.push(Issue::LegacyHeader),
+    HeaderVersion::Unknown(size) => {
+      issues.push(Issue::UnknownHeaderSize(size));
+      return
+    }
+    _ => {}
+  }
+
+  let headers_end = FILE_HEADER_SIZE + header_size as usize;
+  if data.len() < headers_end {
+    issues.push(Issue::Truncated { needed: headers_end, actual: data.len() });
+    return
+  }
+
+  // The core header has unsigned 16-bit dimensions, always bottom-up, and
+  // stops after the bit depth.
+  let core = header == HeaderVersion::Core;
+  let (width, height, planes, bpp) = if core {
+    (read_u16(data, 18).unwrap_or(0) as i32, read_u16(data, 20).unwrap_or(0) as i32, read_u16(data, 22).unwrap_or(0), read_u16(data, 24).unwrap_or(0))
+  } else {
+    (read_i32(data, 18).unwrap_or(0), read_i32(data, 22).unwrap_or(0), read_u16(data, 26).unwrap_or(0), read_u16(data, 28).unwrap_or(0))
+  };
+  let field = |at: usize| if core || (header_size as usize) < at + 4 - FILE_HEADER_SIZE { 0 } else { read_u32(data, at).unwrap_or(0) };
+  let (method, image_size, colors, important) = (field(30), field(34), field(46), field(50));
+
+  if planes != 1 {
+    issues.push(Issue::InvalidPlanes(planes));
+  }
+
+  if width <= 0 || height == 0 || height == i32::MIN {
+    issues.push(Issue::InvalidDimensions { width, height });
+  }
+
+  match bpp {
+    1 | 4 | 8 | 24 | 32 => {}
+    // Embedded JPEG and PNG data carry their own depth.
+    0 if matches!(method, 4 | 5) => {}
+    2 | 16 => issues.push(Issue::UncommonDepth(bpp)),
+    _ => {
+      issues.push(Issue::InvalidBitsPerPixel(bpp));
+      return
+    }
+  }
+
+  let defined = match method {
+    0 => true,
+    1 => bpp == 8,
+    2 => bpp == 4,
+    3 => matches!(bpp, 16 | 32) || (header.is_os2() && bpp == 1),
+    4 => bpp == 0 || (header.is_os2() && bpp == 24),
+    5 => bpp == 0,
+    6 => matches!(bpp, 16 | 32),
+    _ => false
+  };
+  if !defined {
+    issues.push(Issue::InvalidCompression { method, bpp });
+  }
+
+  let compressed = matches!(method, 1 | 2 | 4 | 5);
+  if compressed && height < 0 {
+    issues.push(Issue::TopDownCompressed);
+  }
+
+  if bpp <= 8 && colors > 1 << bpp {
+    issues.push(Issue::OversizedPalette { colors, bpp });
+  }
+
+  let masks = masks_len(header_size, method);
+  let entry = if core { 3 } else { 4 };
+  let palette_len = match colors {
+    0 if bpp <= 8 => 1 << bpp,
+    n => n.min(256) as usize
+  };
+  if important as usize > palette_len {
+    issues.push(Issue::ExcessImportantColors { important, colors: palette_len });
+  }
+
+  let minimum = headers_end + masks + palette_len * entry;
+  if (offset as usize) < minimum {
+    issues.push(Issue::OffsetOverlapsHeaders { offset, minimum });
+  }
+
+  if declared_size as usize != data.len() {
+    issues.push(Issue::FileSizeMismatch { declared: declared_size, actual: data.len() });
+  }
+
+  if data.get(6 .. 10).is_some_and(|reserved| reserved.iter().any(|byte| *byte != 0)) {
+    issues.push(Issue::NonzeroReserved);
+  }
+
+  if compressed {
+    if image_size == 0 {
+      issues.push(Issue::MissingImageSize);
+    }
+    #[cfg(feature = "rle")]
+    if let Ok(decoder) = Decoder::headers(data) {
+      if matches!(decoder.decompress(), Err(BitmapError::BadRleStream)) {
+        issues.push(Issue::BadRleStream);
+      }
+    }
+    return
+  }
+
+  let Some(stride) = row_stride(width.unsigned_abs(), bpp) else {
+    issues.push(Issue::SizeOverflow);
+    return
+  };
+  let Some(computed) = stride.checked_mul(height.unsigned_abs() as usize) else {
+    issues.push(Issue::SizeOverflow);
+    return
+  };
+  if image_size != 0 && image_size as usize != computed {
+    issues.push(Issue::ImageSizeMismatch { declared: image_size, computed });
+  }
+
+  let Some(image_end) = (offset as usize).checked_add(computed) else {
+    issues.push(Issue::SizeOverflow);
+    return
+  };
+  if image_end > data.len() {
+    issues.push(Issue::Truncated { needed: image_end, actual: data.len() });
+    return
+  }
+
+  if image_end < data.len() {
+    issues.push(Issue::TrailingData(data.len() - image_end));
+  }
+
+  let row_len = (width.unsigned_abs() as usize * bpp as usize).div_ceil(8);
+  let padding = data[offset as usize .. image_end].chunks(stride.max(1)).any(|row| row[row_len.min(row.len()) ..].iter().any(|byte| *byte != 0));
+  if padding {
+    issues.push(Issue::NonzeroPadding);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::vec;
+
+  use crate::encode::tests::{sample, DEPTHS};
+  use crate::{validate, Bitmap, Issue, Pixel, Severity};
+
+  #[test]
+  fn encoded_files_are_clean() {
+    for bpp in DEPTHS {
+      let report = validate(&sample(*bpp, 5).encode());
+      assert!(report.is_clean(), "{} bpp: {:?}", bpp, report.issues);
+    }
+  }
+
+  #[test]
+  fn reports_damaged_fields() {
+    let file = sample(24, 5).encode();
+    let with = |at: usize, value: u32| {
+      let mut file = file.clone();
+      file[at .. at + 4].copy_from_slice(&value.to_le_bytes());
+      file
+    };
+
+    assert_eq!(validate(&file[.. file.len() - 2]).issues, [
+      Issue::FileSizeMismatch { declared: file.len() as u32, actual: file.len() - 2 },
+      Issue::Truncated { needed: file.len(), actual: file.len() - 2 }
+    ]);
+    assert_eq!(validate(&with(10, 20)).issues[0], Issue::OffsetOverlapsHeaders { offset: 20, minimum: 54 });
+    assert_eq!(validate(&with(34, 7)).issues, [Issue::ImageSizeMismatch { declared: 7, computed: 48 }]);
+    assert_eq!(validate(&with(14, 100)).issues, [Issue::UnknownHeaderSize(100)]);
+    assert_eq!(validate(&file[.. 20]).worst(), Some(Severity::Error));
+  }
+
+  #[test]
+  fn reports_a_pixel_array_too_large_to_address() {
+    let mut data = Bitmap::from_parts(1, 1, 32, vec![], vec![Pixel::ABGR(0, 0, 0, 255)]).unwrap().encode();
+    data[18 .. 22].copy_from_slice(&i32::MIN.to_le_bytes());
+    data[22 .. 26].copy_from_slice(&i32::MIN.to_le_bytes());
+    data[34 .. 38].copy_from_slice(&0u32.to_le_bytes());
+
+    let report = validate(&data);
+    assert!(report.issues.contains(&Issue::SizeOverflow));
+    assert!(!report.is_valid());
+  }
+}
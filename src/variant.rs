@@ -0,0 +1,142 @@
+// Classifying BMP flavours from their headers alone, without decoding.
+
+use crate::decoder::{read_i32, read_u16, read_u32};
+use crate::masks::read_masks;
+use crate::{Bitmap, BitmapError, BitmapResult, CompressionMethod};
+
+// Info header layouts, named by their Windows structure or OS/2 release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeaderVersion {
+  // 12-byte BITMAPCOREHEADER, shared by Windows 2.x and OS/2 1.x.
+  Core,
+  // OS/2 2.x BITMAPINFOHEADER2, 16 to 64 bytes.
+  Os2,
+  Info,
+  V2,
+  V3,
+  V4,
+  V5,
+  Unknown(u32)
+}
+
+impl HeaderVersion {
+  pub const fn from_size(size: u32) -> HeaderVersion {
+    match size {
+      12 => HeaderVersion::Core,
+      40 => HeaderVersion::Info,
+      52 => HeaderVersion::V2,
+      56 => HeaderVersion::V3,
+      108 => HeaderVersion::V4,
+      124 => HeaderVersion::V5,
+      16 ..= 64 => HeaderVersion::Os2,
+      n => HeaderVersion::Unknown(n)
+    }
+  }
+
+  pub const fn is_os2(&self) -> bool {
+    matches!(self, HeaderVersion::Core | HeaderVersion::Os2)
+  }
+}
+
+// How pixel values are stored, after taking compression and masks into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelFormat {
+  // Uncompressed palette indices of this many bits.
+  Indexed(u16),
+  // Run-length encoded palette indices (4 or 8 bits), or OS/2 RLE24.
+  RunLength(u16),
+  Rgb555,
+  Rgb565,
+  Bgr24,
+  // 32-bpp with the fourth byte unused.
+  Bgrx32,
+  Bgra32,
+  // Masks that match none of the named layouts.
+  Bitfields { red: u32, green: u32, blue: u32, alpha: u32 },
+  // OS/2 modified Huffman, for 1-bpp fax images.
+  Huffman1D,
+  Jpeg,
+  Png,
+  Unknown
+}
+
+// Everything `identify` can tell about a file from its headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Variant {
+  // File type signature: `BM`, or an OS/2 type such as `BA` or `CI`.
+  pub magic: [u8; 2],
+  pub header: HeaderVersion,
+  pub bpp: u16,
+  pub compression: CompressionMethod,
+  pub format: PixelFormat,
+  pub top_down: bool
+}
+
+impl Variant {
+  pub fn is_os2(&self) -> bool {
+    self.header.is_os2() || &self.magic != b"BM"
+  }
+}
+
+// Identifies the flavour of a BMP file from its headers. Accepts layouts
+// and compressions the decoder can't read.
+pub fn identify(data: &[u8]) -> BitmapResult<Variant> {
+  let magic = [*data.first().ok_or(BitmapError::TruncatedHeader)?, *data.get(1).ok_or(BitmapError::TruncatedHeader)?];
+  if !matches!(&magic, b"BM" | b"BA" | b"CI" | b"CP" | b"IC" | b"PT") {
+    return Err(BitmapError::BadMagic)
+  }
+
+  let size = read_u32(data, 14)?;
+  let header = HeaderVersion::from_size(size);
+
+  // The core header has unsigned 16-bit dimensions, so is always bottom-up,
+  // and no compression field.
+  let (height, bpp, raw_method) = if header == HeaderVersion::Core {
+    (read_u16(data, 20)? as i32, read_u16(data, 24)?, 0)
+  } else {
+    (read_i32(data, 22)?, read_u16(data, 28)?, read_u32(data, 30)?)
+  };
+
+  let compression = match raw_method {
+    0 => CompressionMethod::None,
+    1 => CompressionMethod::Rle8Bit,
+    2 => CompressionMethod::Rle4Bit,
+    3 => CompressionMethod::Huffman1D,
+    4 => CompressionMethod::Jpeg,
+    5 => CompressionMethod::Png,
+    n => CompressionMethod::Other(n)
+  };
+
+  let format = match (raw_method, bpp) {
+    (0, 1 | 2 | 4 | 8) => PixelFormat::Indexed(bpp),
+    (0, 16) => PixelFormat::Rgb555,
+    (0, 24) => PixelFormat::Bgr24,
+    (0, 32) => PixelFormat::Bgrx32,
+    (1, _) => PixelFormat::RunLength(8),
+    (2, _) => PixelFormat::RunLength(4),
+    (3, _) if header.is_os2() => PixelFormat::Huffman1D,
+    (4, 24) if header.is_os2() => PixelFormat::RunLength(24),
+    (3 | 6, 16 | 32) => match (read_masks(data, size, raw_method)?, bpp) {
+      ([0x7c00, 0x3e0, 0x1f, 0], 16) => PixelFormat::Rgb555,
+      ([0xf800, 0x7e0, 0x1f, 0], 16) => PixelFormat::Rgb565,
+      ([0xff_0000, 0xff00, 0xff, 0], 32) => PixelFormat::Bgrx32,
+      ([0xff_0000, 0xff00, 0xff, 0xff00_0000], 32) => PixelFormat::Bgra32,
+      ([red, green, blue, alpha], _) => PixelFormat::Bitfields { red, green, blue, alpha }
+    },
+    (4, _) => PixelFormat::Jpeg,
+    (5, _) => PixelFormat::Png,
+    _ => PixelFormat::Unknown
+  };
+
+  Ok(Variant { magic, header, bpp, compression, format, top_down: height < 0 })
+}
+
+impl Bitmap {
+  // Flavour of the file in `data`.
+  pub fn variant(&self) -> BitmapResult<Variant> {
+    identify(&self.data)
+  }
+}
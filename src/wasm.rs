@@ -0,0 +1,60 @@
+// wasm-bindgen entry points taking and returning `Uint8Array`s.
+
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Bitmap, BitmapError, Pixel};
+
+// Decoded image as top-down RGBA, ready for `ImageData`.
+#[wasm_bindgen]
+pub struct DecodedImage {
+  width: u32,
+  height: u32,
+  rgba: Vec<u8>
+}
+
+#[wasm_bindgen]
+impl DecodedImage {
+  #[wasm_bindgen(getter)]
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  // Copied out into a fresh `Uint8Array` on every access.
+  #[wasm_bindgen(getter)]
+  pub fn rgba(&self) -> Vec<u8> {
+    self.rgba.clone()
+  }
+}
+
+#[wasm_bindgen(js_name = decodeBmp)]
+pub fn decode_bmp(bytes: &[u8]) -> Result<DecodedImage, JsError> {
+  let bitmap = Bitmap::from_bytes(bytes)?;
+
+  Ok(DecodedImage {
+    width: bitmap.width(),
+    height: bitmap.height(),
+    rgba: bitmap.to_rgba8()
+  })
+}
+
+// Encodes top-down RGBA (e.g. `ImageData.data`) as a 32-bpp file.
+#[wasm_bindgen(js_name = encodeBmp)]
+pub fn encode_bmp(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>, JsError> {
+  if rgba.len() as u64 != width as u64 * height as u64 * 4 {
+    return Err(BitmapError::InvalidBitmapData.into())
+  }
+
+  let pixels = rgba
+    .chunks_exact(4)
+    .map(|pixel| Pixel::ABGR(pixel[2], pixel[1], pixel[0], pixel[3]))
+    .collect();
+
+  Ok(Bitmap::from_parts(width, height, 32, Vec::new(), pixels)?.data)
+}